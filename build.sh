@@ -1,15 +1,9 @@
 #!/bin/bash
 
-build() {
-  local pwd=$(pwd)
-  pushd $1 >/dev/null && \
-  cargo build --release && \
-  cp ./target/release/$1 $pwd/bin && \
-  popd > /dev/null
-}
-
 mkdir -p bin
 
-build chip8-assembler
-build chip8-interpreter
-build chip8-decompiler
+cargo build --release || exit 1
+
+for tool in chip8 chip8-assembler chip8-interpreter chip8-decompiler; do
+  cp ./target/release/$tool bin
+done
@@ -0,0 +1,612 @@
+use crate::directives::{self, Directive};
+use crate::instructions::{self, Instruction};
+use crate::parse_utils::*;
+use crate::symbols::{Location, Symbols};
+use crate::syntax::Syntax;
+use crate::target::Target;
+use crate::{control, misc, pseudo, scope, sprite};
+use std::collections::HashMap;
+
+/// How many times the layout is recomputed before giving up.
+/// Each pass only needs the addresses of the previous one,
+/// so anything sane settles in two or three.
+const MAX_PASSES: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Address the ROM is loaded at.
+    pub origin: u16,
+    pub target: Target,
+    pub syntax: Syntax,
+    /// Constants as in `NAME=VALUE`, which take
+    /// precedence over the ones in the source.
+    pub defines: Vec<String>,
+    /// Overrides the `.entrypoint` directive.
+    pub entrypoint: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            origin: 0x200,
+            target: Target::default(),
+            syntax: Syntax::default(),
+            defines: Vec::new(),
+            entrypoint: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Item<'a> {
+    Label(&'a str),
+    Constant(&'a str, Value<'a, u16>),
+    Directive(Directive<'a>),
+    Instruction(Instruction<'a>),
+}
+
+impl<'a> Item<'a> {
+    fn size(&self, address: u16, table: &Table) -> Result<Option<u16>, String> {
+        match self {
+            Item::Label(_) | Item::Constant(_, _) => Ok(Some(0)),
+            Item::Instruction(_) => Ok(Some(2)),
+            Item::Directive(d) => d.size(address, table),
+        }
+    }
+}
+
+fn duplicate(line: usize, name: &str, previous: usize) -> String {
+    if previous == 0 {
+        format!(
+            "line {}: {:?} is already defined on the command line",
+            line, name
+        )
+    } else {
+        format!(
+            "line {}: {:?} is already defined on line {}",
+            line, name, previous
+        )
+    }
+}
+
+pub fn strip_ws_comments(line: &str, comment: char) -> &str {
+    let mut end_offt = line.len();
+    let mut rest = line;
+    // comment characters inside strings don't start comments.
+    while let Some(i) = rest.find([comment, '"']) {
+        let offset = line.len() - rest.len() + i;
+        match string_len(&rest[i..]) {
+            Some(n) => rest = &rest[i + n..],
+            None => {
+                end_offt = offset;
+                break;
+            }
+        }
+    }
+
+    line[..end_offt].trim()
+}
+
+/// The plain native statements of a source file, with only global names
+/// and no control flow or pseudo instructions left.
+fn statements(source: &str, options: &Options) -> Result<Statements, String> {
+    let comment = options.syntax.comment();
+    let statements = source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let statement = strip_ws_comments(line, comment);
+            let at = Location {
+                line: i + 1,
+                column: line[..span(line, statement).start].chars().count() + 1,
+            };
+            (at, statement.to_string())
+        })
+        .collect();
+    let statements = options.syntax.translate(statements)?;
+    let statements = sprite::expand(statements, options.target)?;
+    let statements = scope::qualify(statements)?;
+    let statements = control::expand(statements)?;
+    pseudo::expand(statements)
+}
+
+/// A label or constant as a statement mentions it, qualified as in `main.loop`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mention {
+    Label(String),
+    Constant(String),
+    Use(String),
+}
+
+/// The labels and constants every statement defines and uses, in order,
+/// as the assembler reads them. Names the assembler makes up for control
+/// flow and anonymous labels are in there too.
+pub fn mentions(source: &str, options: &Options) -> Result<Vec<(Location, Mention)>, String> {
+    let mut mentions = Vec::new();
+    for (at, statement) in statements(source, options)? {
+        let uses = if let Some(name) = misc::label(&statement) {
+            mentions.push((at, Mention::Label(name.to_string())));
+            Vec::new()
+        } else if let Some((name, value)) = misc::constant(&statement) {
+            mentions.push((at, Mention::Constant(name.to_string())));
+            value.names()
+        } else if let Some(name) = directives::entrypoint(&statement) {
+            vec![name]
+        } else if let Some(d) = directives::any(&statement) {
+            d.names()
+        } else if let Some(i) = instructions::any(&statement) {
+            i.names()
+        } else {
+            Vec::new()
+        };
+        mentions.extend(
+            uses.into_iter()
+                .map(|name| (at, Mention::Use(name.to_string()))),
+        );
+    }
+    Ok(mentions)
+}
+
+/// Assembles a whole source file into a ROM image,
+/// starting with the jump to the entrypoint.
+pub fn assemble(source: &str, options: &Options) -> Result<Vec<u8>, String> {
+    assemble_with_symbols(source, options).map(|(rom, _)| rom)
+}
+
+/// Assembles like `assemble`, also telling where the labels
+/// and the lines of the source ended up.
+pub fn assemble_with_symbols(
+    source: &str,
+    options: &Options,
+) -> Result<(Vec<u8>, Symbols), String> {
+    let origin = options.origin;
+    let memory_end = options.target.memory_end();
+    let mut entrypoint = String::from(options.syntax.entrypoint());
+    let mut jumps_to_entrypoint = true;
+
+    let defines = options
+        .defines
+        .iter()
+        .map(|d| misc::constant(d).ok_or_else(|| format!("Bad constant definition {:?}", d)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // line where each name was defined, to report duplicates.
+    // constants from the command line are line 0.
+    let mut defined_at = defines
+        .iter()
+        .map(|(name, _)| (*name, 0))
+        .collect::<HashMap<_, _>>();
+
+    let statements = statements(source, options)?;
+    let source_lines = source.lines().collect::<Vec<_>>();
+
+    // parse the file into an intermediate parsed state,
+    // so i can parse expressions when all labels and constants
+    // are known.
+    let mut items = Vec::new();
+    for (at, stripped_line) in statements.iter() {
+        let line_number = at.line;
+        let line = source_lines[line_number - 1];
+        let stripped_line = stripped_line.as_str();
+        if stripped_line.is_empty() {
+            continue;
+        }
+        let item = if let Some(name) = misc::label(stripped_line) {
+            if let Some(previous) = defined_at.insert(name, line_number) {
+                return Err(duplicate(line_number, name, previous));
+            }
+            Item::Label(name)
+        } else if let Some((name, value)) = misc::constant(stripped_line) {
+            match defined_at.get(name) {
+                // the command line wins.
+                Some(0) => continue,
+                Some(previous) => return Err(duplicate(line_number, name, *previous)),
+                None => {
+                    defined_at.insert(name, line_number);
+                    Item::Constant(name, value)
+                }
+            }
+        } else if directives::no_entrypoint(stripped_line) {
+            jumps_to_entrypoint = false;
+            continue;
+        } else if let Some(new_ep) = directives::entrypoint(stripped_line) {
+            entrypoint.clear();
+            entrypoint.push_str(new_ep);
+            continue;
+        } else if let Some(d) = directives::any(stripped_line) {
+            Item::Directive(d)
+        } else if let Some(i) = instructions::any(stripped_line) {
+            Item::Instruction(i)
+        } else {
+            return Err(format!("line {}: Unknown line: {:?}", line_number, line));
+        };
+        items.push((*at, item));
+    }
+
+    // lay out the items until every label and constant stays put.
+    // symbols that aren't known yet count as zero for now, the pass after
+    // will have them from this one.
+    let mut table = Table::new();
+    let mut addresses = vec![0; items.len()];
+    let mut pass = 0;
+    let start = if jumps_to_entrypoint {
+        origin + 2
+    } else {
+        origin
+    };
+    let end = loop {
+        let mut changed = false;
+        let mut problem = None;
+        let mut address = start;
+
+        table.insert(".", origin.into());
+        for (name, value) in defines.iter() {
+            let value = value.consume(&table);
+            changed |= update(&mut table, name, value);
+        }
+
+        for (i, (at, item)) in items.iter().enumerate() {
+            let line = at.line;
+            addresses[i] = address;
+            table.insert(".", address.into());
+            let size = match item {
+                Item::Label(name) => {
+                    changed |= update(&mut table, name, Some(address));
+                    0
+                }
+                Item::Constant(name, value) => {
+                    let value = value.consume(&table);
+                    if value.is_none() && problem.is_none() {
+                        problem = Some(format!(
+                            "line {}: {:?} unknown in constant expression",
+                            line, name
+                        ));
+                    }
+                    changed |= update(&mut table, name, value);
+                    0
+                }
+                _ => match item.size(address, &table) {
+                    Ok(Some(size)) => size,
+                    Ok(None) | Err(_) if problem.is_some() => 0,
+                    Ok(None) => {
+                        problem = Some(format!(
+                            "line {}: Couldn't size {:?}, unknown in constant expression",
+                            line, item
+                        ));
+                        0
+                    }
+                    Err(e) => {
+                        problem = Some(format!("line {}: {}", line, e));
+                        0
+                    }
+                },
+            };
+            // a guess from the last pass can make the size nonsense too,
+            // so this only counts if the layout is settled.
+            match address.checked_add(size) {
+                Some(a) if a as u32 <= memory_end => address = a,
+                _ => {
+                    problem.get_or_insert_with(|| format!("line {}: ROM exhausted", line));
+                }
+            }
+        }
+
+        if !changed {
+            // another pass would give the exact same result.
+            if let Some(problem) = problem {
+                return Err(problem);
+            }
+            break address;
+        }
+        pass += 1;
+        if pass == MAX_PASSES {
+            return Err(String::from(
+                "The layout never settles, some size depends on itself",
+            ));
+        }
+    };
+
+    let mut rom = vec![0; (end - origin) as usize];
+    let mut charmap = directives::Charmap::new();
+    let mut symbols = Symbols::default();
+    let value = |name: &str, table: &Table| table.get(name).and_then(|v| v.consume(table));
+    for (name, _) in defines.iter() {
+        if let Some(v) = value(name, &table) {
+            symbols.constants.push((name.to_string(), v));
+        }
+    }
+    for ((location, item), address) in items.iter().zip(addresses.iter().copied()) {
+        let location = *location;
+        let line = location.line;
+        table.insert(".", address.into());
+        match item {
+            Item::Label(name) => symbols.labels.push((name.to_string(), address)),
+            Item::Constant(name, _) => {
+                if let Some(v) = value(name, &table) {
+                    symbols.constants.push((name.to_string(), v));
+                }
+            }
+            Item::Instruction(_) => {
+                symbols.code.entry(address).or_insert(location);
+            }
+            Item::Directive(d) => match d.size(address, &table)? {
+                Some(size) if size > 0 => {
+                    symbols.data.entry(address).or_insert((size, location));
+                }
+                _ => {}
+            },
+        }
+        let offset = (address - origin) as usize;
+        match item {
+            Item::Instruction(i) => {
+                let v = i.compile(&table).ok_or_else(|| {
+                    format!(
+                        "line {}: Couldn't compile instruction: {:?} unknown in constant expression",
+                        line, i
+                    )
+                })?;
+                rom[offset] = (v >> 8) as u8;
+                rom[offset + 1] = (v & 0xff) as u8;
+            }
+            Item::Directive(d) => {
+                // the layout already checked the size is known.
+                let size = d.size(address, &table)?.unwrap() as usize;
+                d.emit(&mut rom[offset..offset + size], &table, &mut charmap)
+                    .map_err(|e| format!("line {}: {}", line, e))?;
+            }
+            Item::Label(_) | Item::Constant(_, _) => {}
+        }
+    }
+
+    if !jumps_to_entrypoint {
+        return Ok((rom, symbols));
+    }
+
+    if let Some(name) = options.entrypoint.as_ref() {
+        entrypoint.clone_from(name);
+    }
+
+    if let Some(entrypoint) = table
+        .get(entrypoint.as_str())
+        .and_then(|x| x.consume(&table))
+    {
+        rom[0] = (entrypoint >> 8) as u8;
+        rom[1] = (entrypoint & 0xff) as u8;
+        rom[0] |= 0x10;
+    } else {
+        return Err(format!("Entrypoint {:?} expected to be present. You can change at any time what the entrypoint label is by using '.entrypoint <entrypoint>'", entrypoint));
+    }
+
+    Ok((rom, symbols))
+}
+
+/// Sets a symbol for the next items and passes,
+/// telling whether it's different from the last pass.
+fn update<'a>(table: &mut Table<'a>, name: &'a str, value: Option<u16>) -> bool {
+    let value = value.map(Value::Complete);
+    let changed = table.get(name) != value.as_ref();
+    match value {
+        Some(v) => table.insert(name, v),
+        None => table.remove(name),
+    };
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asm(source: &str) -> Result<Vec<u8>, String> {
+        assemble(source, &Options::default())
+    }
+
+    #[test]
+    fn forward_references() {
+        assert_eq!(
+            asm("main:\n.repeat 0xff, end - table\ntable:\ndb 1, 2, size\nend:\nsize = end - table\n.entrypoint main"),
+            Ok(vec![0x12, 0x02, 0xff, 0xff, 0xff, 1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn mentions() {
+        let source = "SPEED = 2\nmain:\n.loop:\n  if V0 != SPEED then JP .loop\n.entrypoint main";
+        let found: Vec<(usize, Mention)> = super::mentions(source, &Options::default())
+            .unwrap()
+            .into_iter()
+            .map(|(at, m)| (at.line, m))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, Mention::Constant("SPEED".to_string())),
+                (2, Mention::Label("main".to_string())),
+                (3, Mention::Label("main.loop".to_string())),
+                (4, Mention::Use("SPEED".to_string())),
+                (4, Mention::Use("main.loop".to_string())),
+                (5, Mention::Use("main".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_end() {
+        let at = |origin| Options {
+            origin,
+            ..Options::default()
+        };
+        let source = "main:\n.fill 0x1200, 0\n.entrypoint main";
+        assert_eq!(
+            assemble(source, &at(0x600)),
+            Err("line 2: ROM exhausted".to_string())
+        );
+        let source = "main:\n.fill 0xf00, 0\n.entrypoint main";
+        assert_eq!(assemble(source, &at(0x200)).map(|rom| rom.len()), Ok(0xd00));
+        let source = "main:\n.fill 0xf01, 0\n.entrypoint main";
+        assert!(assemble(source, &at(0x200)).is_err());
+    }
+
+    #[test]
+    fn layout_directives() {
+        assert_eq!(
+            asm("main:\n.align 8\ndw main, 0x1234\n.fill 0x210, 0xaa\n.org . + 1\nCLR\n.entrypoint main"),
+            Ok(vec![
+                0x12, 0x02, 0, 0, 0, 0, 0, 0, //
+                0x02, 0x02, 0x12, 0x34, 0xaa, 0xaa, 0xaa, 0xaa, //
+                0, 0x00, 0xe0
+            ])
+        );
+    }
+
+    #[test]
+    fn sprites() {
+        assert_eq!(
+            asm(
+                "main:\nLDI .dot\nDRW V0, V0, .dot_height\n.sprite .dot\n#\n.end\n.entrypoint main"
+            ),
+            Ok(vec![0x12, 0x02, 0xa2, 0x06, 0xd0, 0x01, 0x80])
+        );
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            asm("main:\n.glyph \"H\"\n#.#\n###\n.end\n.glyph \"I\"\n#\n#\n.end\nmsg:\n.textw \"HI\"\n.charmap \"0123456789\"\n.text \"42\" ; as font indices\n.entrypoint main"),
+            Ok(vec![0x12, 0x02, 0xa0, 0xe0, 0x80, 0x80, 0x02, 0x02, 0x02, 0x04, 4, 2])
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        assert_eq!(
+            asm("main:\nloop\n.x:\nif V0 == 1 then\nbreak\nend\nJP .x\nagain\n.entrypoint main"),
+            Ok(vec![
+                0x12, 0x02, 0x30, 0x01, 0x12, 0x08, 0x12, 0x0c, 0x12, 0x02, 0x12, 0x02
+            ])
+        );
+    }
+
+    #[test]
+    fn pseudo_instructions() {
+        assert_eq!(
+            asm("main:\nSWAP V1, V2\nLDI after + V1\nafter:\nSUB V0, 2\n.entrypoint main"),
+            Ok(vec![
+                0x12, 0x02, 0x8f, 0x10, 0x81, 0x20, 0x82, 0xf0, 0xa2, 0x0c, 0xf1, 0x1e, 0x70, 0xfe
+            ])
+        );
+    }
+
+    #[test]
+    fn syntaxes() {
+        let native =
+            asm("_start:\nLDI data\nFNT V1\nDMP V2\nSIK V3\nSBI V4, V5\nJP0 data\ndata:\ndb 1")
+                .unwrap();
+        let cowgod = Options {
+            syntax: Syntax::Cowgod,
+            ..Options::default()
+        };
+        assert_eq!(
+            assemble("_start:\nLD I, data\nLD F, V1\nLD [I], V2\nSKP V3\nSUBN V4, V5\nJP V0, data\ndata:\ndb 1", &cowgod),
+            Ok(native.clone())
+        );
+        let octo = Options {
+            syntax: Syntax::Octo,
+            ..Options::default()
+        };
+        assert_eq!(
+            assemble(": main # start here\ni := data i := hex v1\nsave v2 if v3 -key then v4 =- v5\njump0 data : data 1", &octo),
+            Ok(native)
+        );
+    }
+
+    #[test]
+    fn symbols() {
+        let source = "; a loop\nmain:\n  LD V0, 1\n\nloop:\n  ADD V0, 1\n  JP loop\ndata:\n db 1, STEP\nSTEP = 2\n.entrypoint main";
+        let (rom, symbols) = assemble_with_symbols(source, &Options::default()).unwrap();
+        assert_eq!(rom, asm(source).unwrap());
+        assert_eq!(
+            symbols.labels,
+            [
+                ("main".to_string(), 0x202),
+                ("loop".to_string(), 0x204),
+                ("data".to_string(), 0x208)
+            ]
+        );
+        assert_eq!(symbols.line(0x202), Some(3));
+        assert_eq!(symbols.line(0x206), Some(7));
+        assert_eq!(symbols.line(0x208), None);
+        assert_eq!(symbols.address(4), Some((6, 0x204)));
+        assert_eq!(symbols.address(8), None);
+        assert_eq!(symbols.label(0x204), Some("loop"));
+        assert_eq!(
+            symbols.location(0x209),
+            Some(Location { line: 9, column: 2 })
+        );
+        assert_eq!(symbols.location(0x20a), None);
+        assert_eq!(symbols.position(0x207), Some("line 7".to_string()));
+
+        let text = symbols.to_string();
+        assert_eq!(
+            text,
+            "# chip8 symbols\n\
+             label 202 main\nlabel 204 loop\nlabel 208 data\n\
+             constant 2 STEP\n\
+             code 202 3 3\ncode 204 6 3\ncode 206 7 3\n\
+             data 208 2 9 2\n"
+        );
+        assert_eq!(Symbols::parse(&text).as_ref(), Ok(&symbols));
+        assert_eq!(
+            Symbols::parse("source my game.asm")
+                .unwrap()
+                .source
+                .as_deref(),
+            Some("my game.asm")
+        );
+        let named = Symbols {
+            source: Some("/games/draw_numbers.asm".to_string()),
+            ..symbols
+        };
+        assert_eq!(
+            named.position(0x204),
+            Some("draw_numbers.asm:6".to_string())
+        );
+        assert!(Symbols::parse("code 20x 3 1").is_err());
+
+        let octo = Options {
+            syntax: Syntax::Octo,
+            ..Options::default()
+        };
+        let (_, symbols) =
+            assemble_with_symbols(": main\n  v0 := 5 v1 += v0 i := main", &octo).unwrap();
+        let columns: Vec<(u16, usize, usize)> = symbols
+            .code
+            .iter()
+            .map(|(a, l)| (*a, l.line, l.column))
+            .collect();
+        assert_eq!(columns, [(0x202, 2, 3), (0x204, 2, 11), (0x206, 2, 20)]);
+        assert!(Symbols::parse("label 202").is_err());
+    }
+
+    #[test]
+    fn any_case() {
+        assert_eq!(
+            asm("main:\nld i, sprite + v1\nLD DT, v2\nDb 1\n.EntryPoint main\nsprite:"),
+            asm("main:\nLDI sprite\nADDI V1\nDLY V2\ndb 1\n.entrypoint main\nsprite:")
+        );
+    }
+
+    #[test]
+    fn no_entrypoint() {
+        assert_eq!(
+            asm(".noentrypoint\nCLR\nloop:\nJP loop"),
+            Ok(vec![0x00, 0xe0, 0x12, 0x02])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(asm("main:\nx = y\n.entrypoint main").is_err());
+        assert!(asm("main:\n.reserve end\nend:\n.entrypoint main").is_err());
+        assert!(asm("main:\n.org 0x100\n.entrypoint main").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{assembler, misc, output, parse_utils, syntax, target};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// Command line of the assembler, shared by `chip8-assembler`
+/// and `chip8 asm`.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "chip8 assembler", about = "a CHIP8 assembler.")]
+pub struct Args {
+    /// Source file to assemble. Reads from stdin if missing or '-'.
+    #[structopt(parse(from_os_str))]
+    pub input: Option<PathBuf>,
+
+    /// Where to write the ROM. Writes to stdout if missing or '-'.
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    /// Output format: raw, hex or ihex.
+    #[structopt(long = "format", default_value = "raw")]
+    pub format: output::Format,
+
+    /// Predefine a constant, as in 'NAME=VALUE'.
+    #[structopt(short = "D", number_of_values = 1, parse(try_from_str = parse_define))]
+    pub defines: Vec<String>,
+
+    /// Label to jump to at startup, overriding '.entrypoint'.
+    #[structopt(long = "entrypoint")]
+    pub entrypoint: Option<String>,
+
+    /// Address the ROM is loaded at.
+    #[structopt(long = "origin", default_value = "0x200", parse(try_from_str = parse_address))]
+    pub origin: u16,
+
+    /// Machine to assemble for: chip8, schip or xochip.
+    #[structopt(long = "target", default_value = "chip8")]
+    pub target: target::Target,
+
+    /// Dialect of the source: native, cowgod or octo.
+    /// Defaults to octo for .8o files, native otherwise.
+    #[structopt(long = "syntax")]
+    pub syntax: Option<syntax::Syntax>,
+
+    /// Also write the labels and the source line of every
+    /// instruction to this file, for debuggers.
+    #[structopt(long = "symbols", parse(from_os_str))]
+    pub symbols: Option<PathBuf>,
+}
+
+fn parse_define(input: &str) -> Result<String, String> {
+    match misc::constant(input) {
+        Some(_) => Ok(input.to_string()),
+        None => Err(format!("expected NAME=VALUE, got {:?}", input)),
+    }
+}
+
+fn parse_address(input: &str) -> Result<u16, String> {
+    match parse_utils::parse_num(input) {
+        Some(("", v)) => Ok(v),
+        _ => Err(format!("expected an address, got {:?}", input)),
+    }
+}
+
+fn is_std(path: &Option<PathBuf>) -> bool {
+    path.as_ref().map(|p| p.as_os_str() == "-").unwrap_or(true)
+}
+
+impl Args {
+    /// The syntax asked for, or the one of the input's extension.
+    pub fn syntax(&self) -> syntax::Syntax {
+        let input = self.input.as_ref().filter(|_| !is_std(&self.input));
+        self.syntax
+            .or_else(|| input.and_then(|path| syntax::Syntax::of_file(path)))
+            .unwrap_or_default()
+    }
+
+    pub fn options(&self) -> assembler::Options {
+        assembler::Options {
+            origin: self.origin,
+            target: self.target,
+            syntax: self.syntax(),
+            defines: self.defines.clone(),
+            entrypoint: self.entrypoint.clone(),
+        }
+    }
+
+    /// Reads the input, assembles it and writes the ROM.
+    pub fn run(&self) -> Result<(), String> {
+        let mut source = String::new();
+        let read = if is_std(&self.input) {
+            io::stdin().read_to_string(&mut source)
+        } else {
+            File::open(self.input.as_ref().unwrap()).and_then(|mut f| f.read_to_string(&mut source))
+        };
+        read.map_err(|e| format!("Couldn't read input: {}", e))?;
+
+        let (rom, mut symbols) = assembler::assemble_with_symbols(&source, &self.options())?;
+        if let Some(path) = &self.symbols {
+            if !is_std(&self.input) {
+                // absolute, as debuggers don't run where this did.
+                let input = self.input.as_ref().unwrap();
+                let input = std::fs::canonicalize(input).unwrap_or_else(|_| input.clone());
+                symbols.source = Some(input.display().to_string());
+            }
+            std::fs::write(path, symbols.to_string())
+                .map_err(|e| format!("Couldn't write {}: {}", path.display(), e))?;
+        }
+
+        let written = if is_std(&self.output) {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            output::write(&mut out, self.format, self.origin, &rom).and_then(|_| out.flush())
+        } else {
+            File::create(self.output.as_ref().unwrap()).and_then(|f| {
+                let mut out = BufWriter::new(f);
+                output::write(&mut out, self.format, self.origin, &rom).and_then(|_| out.flush())
+            })
+        };
+        written.map_err(|e| format!("Couldn't write output: {}", e))
+    }
+}
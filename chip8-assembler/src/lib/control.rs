@@ -0,0 +1,322 @@
+//! Octo style structured control flow, compiled down to skips and jumps:
+//!
+//! ```text
+//! loop
+//!   LD V0, 5
+//!   if V0 key then
+//!     ADD V3, 1
+//!   else
+//!     while V3 != 0
+//!     ADD V3, 0xff
+//!   end
+//!   if V3 == 10 then break
+//! again
+//! ```
+//!
+//! Conditions are `VX == NN`, `VX != NN`, `VX == VY`, `VX != VY`,
+//! `VX key` and `VX -key`.
+//! `if C then` with nothing after it opens a block closed by `end`,
+//! and with an instruction after it only guards that instruction.
+//! `while C` leaves the innermost `loop` when `C` doesn't hold,
+//! `break` leaves it and `continue` starts it over.
+
+use crate::parse_utils::{pkeyword, Statements};
+use crate::{instructions, pseudo};
+
+/// A condition, as the instruction that skips when it holds
+/// and the one that skips when it doesn't.
+struct Condition {
+    holds: String,
+    fails: String,
+}
+
+fn condition(input: &str) -> Option<Condition> {
+    let skips = |holds: &str, fails: &str, args: String| Condition {
+        holds: format!("{} {}", holds, args),
+        fails: format!("{} {}", fails, args),
+    };
+    if let Some((a, b)) = input.split_once("==") {
+        return Some(skips("SEQ", "SNE", format!("{}, {}", a.trim(), b.trim())));
+    }
+    if let Some((a, b)) = input.split_once("!=") {
+        return Some(skips("SNE", "SEQ", format!("{}, {}", a.trim(), b.trim())));
+    }
+    let lower = input.to_ascii_lowercase();
+    if lower.ends_with("-key") {
+        let register = &input[..input.len() - 4];
+        return Some(skips("SNK", "SIK", register.trim().to_string()));
+    }
+    if lower.ends_with("key") {
+        let register = &input[..input.len() - 3];
+        return Some(skips("SIK", "SNK", register.trim().to_string()));
+    }
+    None
+}
+
+/// `keyword` in any case, and what comes after it.
+fn keyword<'a>(input: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = pkeyword(input, keyword)?;
+    if rest.is_empty() {
+        return Some(rest);
+    }
+    let c = rest.chars().next()?;
+    if c.is_whitespace() {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+/// Whether `statement` assembles to one instruction,
+/// be it a real one or a pseudo instruction like `LD [I], V0`.
+fn single_instruction(statement: &str) -> bool {
+    match pseudo::any(statement) {
+        Some(expanded) => {
+            expanded.is_ok_and(|e| e.len() == 1 && instructions::any(&e[0]).is_some())
+        }
+        None => instructions::any(statement).is_some(),
+    }
+}
+
+enum Block {
+    If { id: usize, has_else: bool },
+    Loop { id: usize },
+}
+
+struct Expander {
+    blocks: Vec<Block>,
+    next_id: usize,
+}
+
+impl Expander {
+    fn innermost_loop(&self) -> Result<usize, String> {
+        self.blocks
+            .iter()
+            .rev()
+            .find_map(|b| match b {
+                Block::Loop { id } => Some(*id),
+                _ => None,
+            })
+            .ok_or_else(|| String::from("not inside a loop"))
+    }
+
+    fn id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// The statements a line turns into.
+    fn line(&mut self, line: &str) -> Result<Vec<String>, String> {
+        if let Some(rest) = keyword(line, "if") {
+            // lowercasing keeps the offsets, for any case of `then`.
+            let (c, statement) = rest
+                .to_ascii_lowercase()
+                .find(" then")
+                .map(|i| (&rest[..i], &rest[i + " then".len()..]))
+                .filter(|(_, s)| s.is_empty() || s.starts_with(char::is_whitespace))
+                .ok_or_else(|| String::from("expected 'if <condition> then'"))?;
+            let c = condition(c).ok_or_else(|| format!("bad condition {:?}", c))?;
+            let statement = statement.trim();
+            if !statement.is_empty() {
+                // a skip only jumps over one instruction.
+                let mut guarded = match statement.to_ascii_lowercase().as_str() {
+                    "break" | "continue" => self.line(statement)?,
+                    _ => vec![statement.to_string()],
+                };
+                if guarded.len() != 1 || !single_instruction(&guarded[0]) {
+                    return Err(format!(
+                        "{:?} isn't a single instruction, use a block with 'end'",
+                        statement
+                    ));
+                }
+                guarded.insert(0, c.fails);
+                return Ok(guarded);
+            }
+            let id = self.id();
+            self.blocks.push(Block::If {
+                id,
+                has_else: false,
+            });
+            return Ok(vec![c.holds, format!("JP @if{}_else", id)]);
+        }
+        if let Some(rest) = keyword(line, "while") {
+            let id = self.innermost_loop()?;
+            let c = condition(rest).ok_or_else(|| format!("bad condition {:?}", rest))?;
+            return Ok(vec![c.holds, format!("JP @loop{}_end", id)]);
+        }
+        match line.to_ascii_lowercase().as_str() {
+            "else" => match self.blocks.last_mut() {
+                Some(Block::If { id, has_else }) if !*has_else => {
+                    *has_else = true;
+                    Ok(vec![
+                        format!("JP @if{}_end", id),
+                        format!("@if{}_else:", id),
+                    ])
+                }
+                _ => Err(String::from("'else' without an 'if'")),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { id, has_else }) => Ok(vec![format!(
+                    "@if{}_{}:",
+                    id,
+                    if has_else { "end" } else { "else" }
+                )]),
+                _ => Err(String::from("'end' without an 'if'")),
+            },
+            "loop" => {
+                let id = self.id();
+                self.blocks.push(Block::Loop { id });
+                Ok(vec![format!("@loop{}:", id)])
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { id }) => {
+                    Ok(vec![format!("JP @loop{}", id), format!("@loop{}_end:", id)])
+                }
+                _ => Err(String::from("'again' without a 'loop'")),
+            },
+            "break" => Ok(vec![format!("JP @loop{}_end", self.innermost_loop()?)]),
+            "continue" => Ok(vec![format!("JP @loop{}", self.innermost_loop()?)]),
+            _ => Ok(vec![line.to_string()]),
+        }
+    }
+}
+
+/// Compiles the control flow in the statements down to plain instructions
+/// and labels, keeping the line each one comes from.
+pub fn expand(statements: Statements) -> Result<Statements, String> {
+    let mut expander = Expander {
+        blocks: Vec::new(),
+        next_id: 0,
+    };
+    let mut out = Vec::new();
+    let mut opened_at = Vec::new();
+    for (at, statement) in statements {
+        let depth = expander.blocks.len();
+        let expanded = expander
+            .line(&statement)
+            .map_err(|e| format!("line {}: {}", at.line, e))?;
+        if expander.blocks.len() > depth {
+            opened_at.push(at.line);
+        } else if expander.blocks.len() < depth {
+            opened_at.pop();
+        }
+        out.extend(expanded.into_iter().map(|s| (at, s)));
+    }
+    if let Some(line) = opened_at.pop() {
+        return Err(format!("line {}: block is never closed", line));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_utils::numbered;
+
+    fn expand(lines: Vec<&str>) -> Result<Statements, String> {
+        super::expand(numbered(lines))
+    }
+
+    fn statements(lines: Vec<&str>) -> Vec<String> {
+        expand(lines).unwrap().into_iter().map(|(_, s)| s).collect()
+    }
+
+    #[test]
+    fn if_else() {
+        assert_eq!(
+            statements(vec![
+                "if V0 == 3 then",
+                "CLR",
+                "else",
+                "RET",
+                "end",
+                "if V1 key then ADD V2, 1",
+                "if V0 == 1 then LD [I], V0",
+                "if V0 != 1 then SUB V1, 2"
+            ]),
+            vec![
+                "SEQ V0, 3",
+                "JP @if0_else",
+                "CLR",
+                "JP @if0_end",
+                "@if0_else:",
+                "RET",
+                "@if0_end:",
+                "SNK V1",
+                "ADD V2, 1",
+                "SNE V0, 1",
+                "LD [I], V0",
+                "SEQ V0, 1",
+                "SUB V1, 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(
+            statements(vec![
+                "loop",
+                "while V0 != V1",
+                "if V0 -key then",
+                "break",
+                "end",
+                "continue",
+                "if V2 == 0 then break",
+                "again"
+            ]),
+            vec![
+                "@loop0:",
+                "SNE V0, V1",
+                "JP @loop0_end",
+                "SNK V0",
+                "JP @if1_else",
+                "JP @loop0_end",
+                "@if1_else:",
+                "JP @loop0",
+                "SNE V2, 0",
+                "JP @loop0_end",
+                "JP @loop0",
+                "@loop0_end:"
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(expand(vec!["loop"]).is_err());
+        assert!(expand(vec!["break"]).is_err());
+        assert!(expand(vec!["loop", "end"]).is_err());
+        assert!(expand(vec!["if V0 < 3 then"]).is_err());
+        assert!(expand(vec!["if V0 == 3 then loop"]).is_err());
+        assert!(expand(vec!["if V0 == 3 then SWAP V1, V2"]).is_err());
+    }
+
+    #[test]
+    fn any_case() {
+        assert_eq!(
+            statements(vec![
+                "LOOP",
+                "WHILE V0 != 2",
+                "IF V0 KEY THEN",
+                "Break",
+                "ELSE",
+                "ADD V0, 1",
+                "END",
+                "If V1 == 0 Then CONTINUE",
+                "AGAIN"
+            ]),
+            statements(vec![
+                "loop",
+                "while V0 != 2",
+                "if V0 key then",
+                "break",
+                "else",
+                "ADD V0, 1",
+                "end",
+                "if V1 == 0 then continue",
+                "again"
+            ])
+        );
+    }
+}
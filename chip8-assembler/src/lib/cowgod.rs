@@ -0,0 +1,111 @@
+//! The mnemonics from Cowgod's CHIP-8 technical reference,
+//! which most documentation and other assemblers use:
+//!
+//! ```text
+//! LD I, font      ; LDI font
+//! LD F, V0        ; FNT V0
+//! LD [I], V3      ; DMP V3
+//! SE V0, 3        ; SEQ V0, 3
+//! SKP V1          ; SIK V1
+//! SUBN V1, V2     ; SBI V1, V2
+//! ```
+//!
+//! Every statement is translated on its own into the native one,
+//! anything that isn't a Cowgod instruction is left alone.
+//! Mnemonics both dialects share (`CALL`, `RET`, `JP`, `SNE`, `ADD`,
+//! `DRW`...) mean the same thing in both.
+use crate::parse_utils::*;
+
+/// Operands that name something other than a value, in any case.
+const NAMED: &[&str] = &["I", "[I]", "DT", "ST", "F", "B", "K", "V0"];
+
+/// The native statement for a Cowgod instruction,
+/// `None` if it's written the same way in both.
+fn instruction(mnemonic: &str, args: &[&str]) -> Option<String> {
+    let native = match (mnemonic, args) {
+        ("CLS", []) => String::from("CLR"),
+        // machine code routines, only a word with the address in it.
+        ("SYS", [address]) => format!("dw {}", address),
+        ("JP", ["V0", address]) => format!("JP0 {}", address),
+        ("SE", [a, b]) => format!("SEQ {}, {}", a, b),
+        ("SKP", [vx]) => format!("SIK {}", vx),
+        ("SKNP", [vx]) => format!("SNK {}", vx),
+        ("SUBN", [vx, vy]) => format!("SBI {}, {}", vx, vy),
+        ("ADD", ["I", vx]) => format!("ADDI {}", vx),
+        ("LD", ["I", address]) => format!("LDI {}", address),
+        ("LD", ["DT", vx]) => format!("DLY {}", vx),
+        ("LD", ["ST", vx]) => format!("SND {}", vx),
+        ("LD", ["F", vx]) => format!("FNT {}", vx),
+        ("LD", ["B", vx]) => format!("BCD {}", vx),
+        ("LD", ["[I]", vx]) => format!("DMP {}", vx),
+        ("LD", [vx, "DT"]) => format!("LDD {}", vx),
+        ("LD", [vx, "K"]) => format!("LDK {}", vx),
+        ("LD", [vx, "[I]"]) => format!("LDR {}", vx),
+        _ => return None,
+    };
+    Some(native)
+}
+
+fn statement(input: &str) -> Option<String> {
+    let (mnemonic, rest) = match input.split_once(char::is_whitespace) {
+        Some((mnemonic, rest)) => (mnemonic, rest.trim()),
+        None => (input, ""),
+    };
+    // strings can't show up in instructions, so a plain split is enough.
+    let args = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.split(',').map(str::trim).collect()
+    };
+    let args = args
+        .into_iter()
+        .map(|a| {
+            NAMED
+                .iter()
+                .find(|n| n.eq_ignore_ascii_case(a))
+                .copied()
+                .unwrap_or(a)
+        })
+        .collect::<Vec<_>>();
+    instruction(&mnemonic.to_ascii_uppercase(), &args)
+}
+
+/// Translates the Cowgod instructions in the statements into native ones.
+pub fn translate(statements: Statements) -> Statements {
+    statements
+        .into_iter()
+        .map(|(at, s)| (at, statement(&s).unwrap_or(s)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads() {
+        assert_eq!(
+            statement("LD I, sprite + 2"),
+            Some(String::from("LDI sprite + 2"))
+        );
+        assert_eq!(statement("LD F, V0"), Some(String::from("FNT V0")));
+        assert_eq!(statement("LD B,V3"), Some(String::from("BCD V3")));
+        assert_eq!(statement("LD [I], V3"), Some(String::from("DMP V3")));
+        assert_eq!(statement("LD V3, [I]"), Some(String::from("LDR V3")));
+        assert_eq!(statement("LD V1, K"), Some(String::from("LDK V1")));
+        assert_eq!(statement("LD DT, V1"), Some(String::from("DLY V1")));
+        assert_eq!(statement("ld v1, k"), Some(String::from("LDK v1")));
+        assert_eq!(statement("LD V1, V2"), None);
+    }
+
+    #[test]
+    fn others() {
+        assert_eq!(statement("CLS"), Some(String::from("CLR")));
+        assert_eq!(statement("SE V0, 3"), Some(String::from("SEQ V0, 3")));
+        assert_eq!(statement("SKNP VA"), Some(String::from("SNK VA")));
+        assert_eq!(statement("SUBN V1, V2"), Some(String::from("SBI V1, V2")));
+        assert_eq!(statement("JP V0, table"), Some(String::from("JP0 table")));
+        assert_eq!(statement("JP table"), None);
+        assert_eq!(statement("main:"), None);
+    }
+}
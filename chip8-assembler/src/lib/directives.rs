@@ -1,57 +1,345 @@
 use crate::parse_utils::*;
-pub fn repeat(input: &str) -> Option<(u8, u16)> {
-    if !input.starts_with(".repeat") {
-        return None;
-    }
-    let (input, x) = parse_num(whitespace1(&input[7..])?)?;
-    let input = pcomma(input)?;
-    let (_, y) = parse_num(input)?;
-    Some((x as u8, y))
+use std::collections::HashMap;
+
+/// What every character of a `.text` turns into.
+pub type Charmap = HashMap<char, u16>;
+
+/// Directives that lay out data in the ROM.
+/// All of them take expressions, which may refer to
+/// labels defined further down in the file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Directive<'a> {
+    /// `.repeat what, how_many`
+    Repeat {
+        what: Value<'a, u8>,
+        how_many: Value<'a, u16>,
+    },
+    /// `.reserve how_much`
+    Reserve(Value<'a, u16>),
+    /// `.align n`, pads with zeroes until the address is a multiple of `n`.
+    Align(Value<'a, u16>),
+    /// `.org address`, moves forward to `address` leaving zeroes behind.
+    Org(Value<'a, u16>),
+    /// `.fill address, what`, like `.org` but padding with `what`.
+    Fill {
+        until: Value<'a, u16>,
+        what: Value<'a, u8>,
+    },
+    /// `db a, b, c...`
+    Bytes(Vec<Value<'a, u8>>),
+    /// `dw a, b, c...`, stored big endian like the opcodes.
+    Words(Vec<Value<'a, u16>>),
+    /// `.charmap "chars", start, step` maps the n-th character
+    /// to `start + n * step`, for the `.text` below it.
+    /// `start` defaults to 0 and `step` to 1.
+    Charmap {
+        chars: String,
+        start: Value<'a, u16>,
+        step: Value<'a, u16>,
+    },
+    /// `.text "chars"` puts a byte per character, `.textw "chars"` a word.
+    Text { text: String, wide: bool },
 }
 
-pub fn reserve(input: &str) -> Option<u16> {
-    if !input.starts_with(".reserve") {
-        return None;
-    }
-    let (_, x) = parse_num(whitespace1(&input[8..])?)?;
-    Some(x)
+fn pdirective<'a>(input: &'a str, name: &str) -> Option<&'a str> {
+    whitespace1(pkeyword(input, name)?)
+}
+
+/// Comma separated expressions.
+fn plist<T>(input: &str) -> Option<Vec<Value<'_, T>>> {
+    input
+        .split(',')
+        .map(|x| pexpr(x.trim()))
+        .collect::<Option<Vec<_>>>()
+}
+
+pub fn repeat(input: &str) -> Option<Directive<'_>> {
+    let input = pdirective(input, ".repeat")?;
+    let (what, how_many) = input.split_once(',')?;
+    Some(Directive::Repeat {
+        what: pexpr(what.trim())?,
+        how_many: pexpr(how_many.trim())?,
+    })
+}
+
+pub fn reserve(input: &str) -> Option<Directive<'_>> {
+    let input = pdirective(input, ".reserve")?;
+    Some(Directive::Reserve(pexpr(input)?))
+}
+
+pub fn align(input: &str) -> Option<Directive<'_>> {
+    let input = pdirective(input, ".align")?;
+    Some(Directive::Align(pexpr(input)?))
+}
+
+pub fn org(input: &str) -> Option<Directive<'_>> {
+    let input = pdirective(input, ".org")?;
+    Some(Directive::Org(pexpr(input)?))
+}
+
+pub fn fill(input: &str) -> Option<Directive<'_>> {
+    let input = pdirective(input, ".fill")?;
+    let (until, what) = match input.split_once(',') {
+        Some((until, what)) => (pexpr(until.trim())?, pexpr(what.trim())?),
+        None => (pexpr(input)?, Value::Complete(0)),
+    };
+    Some(Directive::Fill { until, what })
 }
 
 pub fn entrypoint(input: &str) -> Option<&str> {
-    if !input.starts_with(".entrypoint") {
+    let input = pdirective(input, ".entrypoint")?;
+    let (_, inp) = parse_name(input)?;
+    Some(inp)
+}
+
+/// `.noentrypoint`, for code that starts right at the origin
+/// instead of behind the jump to the entrypoint.
+pub fn no_entrypoint(input: &str) -> bool {
+    pkeyword(input, ".noentrypoint") == Some("")
+}
+
+pub fn sequence_bytes(input: &str) -> Option<Directive<'_>> {
+    let input = pdirective(input, "db")?;
+    Some(Directive::Bytes(plist(input)?))
+}
+
+pub fn sequence_words(input: &str) -> Option<Directive<'_>> {
+    let input = pdirective(input, "dw")?;
+    Some(Directive::Words(plist(input)?))
+}
+
+pub fn charmap(input: &str) -> Option<Directive<'_>> {
+    let input = pdirective(input, ".charmap")?;
+    let (input, chars) = pstring(input)?;
+    let mut start = Value::Complete(0);
+    let mut step = Value::Complete(1);
+    if let Some(input) = pcomma(input) {
+        let mut values = plist(input)?.into_iter();
+        start = values.next()?;
+        if let Some(v) = values.next() {
+            step = v;
+        }
+        if values.next().is_some() {
+            return None;
+        }
+    } else if !input.is_empty() {
         return None;
     }
-    let (_, inp) = parse_name(whitespace1(&input[11..])?)?;
-    Some(inp)
+    Some(Directive::Charmap { chars, start, step })
 }
 
-pub fn sequence_bytes(input: &str) -> Option<Vec<u8>> {
-    if !input.starts_with("db") {
+pub fn text(input: &str) -> Option<Directive<'_>> {
+    let (input, wide) = match pdirective(input, ".textw") {
+        Some(input) => (input, true),
+        None => (pdirective(input, ".text")?, false),
+    };
+    let (rest, text) = pstring(input)?;
+    if !rest.is_empty() {
         return None;
     }
+    Some(Directive::Text { text, wide })
+}
 
-    let mut values = Vec::new();
-    let (mut input, first_value) = parse_num(whitespace1(&input[2..])?)?;
-    values.push(first_value as u8);
+pub fn any(input: &str) -> Option<Directive<'_>> {
+    const PARSERS: &[fn(&str) -> Option<Directive>] = &[
+        repeat,
+        reserve,
+        align,
+        org,
+        fill,
+        sequence_bytes,
+        sequence_words,
+        charmap,
+        text,
+    ];
 
-    loop {
-        if let Some(next_input) = pcomma(whitespace(input)).map(whitespace) {
-            // new value
-            let (next_input, next_value) = parse_num(next_input)?;
-            input = next_input;
-            values.push(next_value as u8);
-            continue;
+    PARSERS.iter().find_map(|p| p(input))
+}
+
+impl<'a> Directive<'a> {
+    /// How many bytes the directive takes when placed at `address`,
+    /// if all the symbols it needs are known already.
+    pub fn size(&self, address: u16, table: &Table) -> Result<Option<u16>, String> {
+        let size = match self {
+            Directive::Repeat { how_many: n, .. } | Directive::Reserve(n) => n.consume(table),
+            Directive::Bytes(values) => Some(values.len() as u16),
+            Directive::Words(values) => Some(values.len() as u16 * 2),
+            Directive::Charmap { .. } => Some(0),
+            Directive::Text { text, wide } => {
+                Some(text.chars().count() as u16 * if *wide { 2 } else { 1 })
+            }
+            Directive::Align(n) => match n.consume(table) {
+                Some(0) => return Err(String::from("Can't align to 0 bytes")),
+                Some(n) => Some((n - address % n) % n),
+                None => None,
+            },
+            Directive::Org(until) | Directive::Fill { until, .. } => match until.consume(table) {
+                Some(until) if until < address => {
+                    return Err(format!(
+                        "Can't move back to {:#x} from {:#x}",
+                        until, address
+                    ))
+                }
+                Some(until) => Some(until - address),
+                None => None,
+            },
+        };
+        Ok(size)
+    }
+
+    /// The labels and constants its expressions look up.
+    pub fn names(&self) -> Vec<&'a str> {
+        match self {
+            Directive::Repeat { what, how_many } => [what.names(), how_many.names()].concat(),
+            Directive::Fill { until, what } => [until.names(), what.names()].concat(),
+            Directive::Reserve(v) | Directive::Align(v) | Directive::Org(v) => v.names(),
+            Directive::Bytes(values) => values.iter().flat_map(Value::names).collect(),
+            Directive::Words(values) => values.iter().flat_map(Value::names).collect(),
+            Directive::Charmap { start, step, .. } => [start.names(), step.names()].concat(),
+            Directive::Text { .. } => Vec::new(),
         }
-        break;
     }
 
-    Some(values)
+    /// Writes the directive's bytes into `out`,
+    /// which is exactly as long as its size.
+    /// `.charmap` updates `charmap` for the `.text` coming after.
+    pub fn emit(&self, out: &mut [u8], table: &Table, charmap: &mut Charmap) -> Result<(), String> {
+        let unknown = || format!("{:?} unknown in constant expression", self);
+        match self {
+            Directive::Repeat { what, .. } | Directive::Fill { what, .. } => {
+                let what = what.consume(table).ok_or_else(unknown)?;
+                out.iter_mut().for_each(|x| *x = what);
+            }
+            Directive::Reserve(_) | Directive::Align(_) | Directive::Org(_) => {
+                out.iter_mut().for_each(|x| *x = 0);
+            }
+            Directive::Bytes(values) => {
+                for (x, v) in out.iter_mut().zip(values) {
+                    *x = v.consume(table).ok_or_else(unknown)?;
+                }
+            }
+            Directive::Words(values) => {
+                for (x, v) in out.chunks_mut(2).zip(values) {
+                    let v = v.consume(table).ok_or_else(unknown)?;
+                    x[0] = (v >> 8) as u8;
+                    x[1] = (v & 0xff) as u8;
+                }
+            }
+            Directive::Charmap { chars, start, step } => {
+                let start = start.consume(table).ok_or_else(unknown)?;
+                let step = step.consume(table).ok_or_else(unknown)?;
+                for (i, c) in chars.chars().enumerate() {
+                    charmap.insert(c, start.wrapping_add(step.wrapping_mul(i as u16)));
+                }
+            }
+            Directive::Text { text, wide } => {
+                let width = if *wide { 2 } else { 1 };
+                for (x, c) in out.chunks_mut(width).zip(text.chars()) {
+                    let v = *charmap
+                        .get(&c)
+                        .ok_or_else(|| format!("{:?} isn't in the charmap", c))?;
+                    if *wide {
+                        x[0] = (v >> 8) as u8;
+                        x[1] = (v & 0xff) as u8;
+                    } else if v > 0xff {
+                        return Err(format!(
+                            "{:?} maps to {:#x}, which doesn't fit in a byte. Use .textw",
+                            c, v
+                        ));
+                    } else {
+                        x[0] = v as u8;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn repeat() {
-        assert_eq!(super::repeat(".repeat 0x80, 15"), Some((0x80, 15)));
+        assert_eq!(
+            super::repeat(".repeat 0x80, 15"),
+            Some(Directive::Repeat {
+                what: Value::Partial("0x80"),
+                how_many: Value::Partial("15")
+            })
+        );
+    }
+
+    #[test]
+    fn sequence() {
+        assert_eq!(
+            super::sequence_bytes("db 1, end - start, 3"),
+            Some(Directive::Bytes(vec![
+                Value::Partial("1"),
+                Value::Partial("end - start"),
+                Value::Partial("3")
+            ]))
+        );
+        assert_eq!(
+            super::sequence_words("dw table"),
+            Some(Directive::Words(vec![Value::Partial("table")]))
+        );
+    }
+
+    #[test]
+    fn text() {
+        let mut table = Table::new();
+        table.insert(".", 0x300.into());
+        let mut charmap = Charmap::new();
+        super::charmap(r#".charmap "AB", ., 5"#)
+            .unwrap()
+            .emit(&mut [], &table, &mut charmap)
+            .unwrap();
+        super::charmap(r#".charmap "0123""#)
+            .unwrap()
+            .emit(&mut [], &table, &mut charmap)
+            .unwrap();
+
+        let mut out = [0; 3];
+        super::text(r#".text "310""#)
+            .unwrap()
+            .emit(&mut out, &table, &mut charmap)
+            .unwrap();
+        assert_eq!(out, [3, 1, 0]);
+
+        let mut out = [0; 4];
+        super::text(r#".textw "BA""#)
+            .unwrap()
+            .emit(&mut out, &table, &mut charmap)
+            .unwrap();
+        assert_eq!(out, [0x03, 0x05, 0x03, 0x00]);
+
+        let mut out = [0; 1];
+        let text = super::text(r#".text "A""#).unwrap();
+        assert!(text.emit(&mut out, &table, &mut charmap).is_err());
+        let text = super::text(r#".text "?""#).unwrap();
+        assert!(text.emit(&mut out, &table, &mut charmap).is_err());
+    }
+
+    #[test]
+    fn size() {
+        let mut table = Table::new();
+        table.insert("end", 0x300.into());
+        assert_eq!(
+            super::align(".align 16").unwrap().size(0x202, &table),
+            Ok(Some(14))
+        );
+        assert_eq!(
+            super::fill(".fill end, 0xff").unwrap().size(0x2f0, &table),
+            Ok(Some(0x10))
+        );
+        assert!(super::org(".org end").unwrap().size(0x310, &table).is_err());
+        assert_eq!(
+            super::reserve(".reserve later")
+                .unwrap()
+                .size(0x202, &table),
+            Ok(None)
+        );
     }
 }
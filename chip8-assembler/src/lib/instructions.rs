@@ -1,5 +1,5 @@
 use crate::parse_utils::*;
-use std::collections::HashMap;
+use chip8_isa::Opcode;
 #[derive(Debug, PartialEq, Eq)]
 pub enum Argument<'a> {
     Constant(Value<'a, u8>),
@@ -78,109 +78,119 @@ pub enum Instruction<'a> {
 }
 
 impl<'a> Instruction<'a> {
-    pub fn compile(&self, table: &HashMap<&str, Value<u16>>) -> Option<u16> {
-        let v = match self {
+    pub fn compile(&self, table: &Table) -> Option<u16> {
+        let op = match self {
             Instruction::Load { register, value } => match value {
-                Argument::Constant(x) => {
-                    let nn = x.consume(&table)?;
-                    0x6000 | (*register as u16) << 8 | (nn as u16)
-                }
-                Argument::Register(x) => {
-                    let vx = x.consume(&table)? & 0xf;
-                    0x8000 | (*register as u16) << 8 | (vx as u16) << 4
-                }
+                Argument::Constant(nn) => Opcode::Load(*register, nn.consume(table)?),
+                Argument::Register(vy) => Opcode::LoadRegister(*register, vy.consume(table)?),
             },
             Instruction::Add { target, value } => match value {
-                Argument::Constant(x) => {
-                    let nn = x.consume(&table)?;
-                    0x7000 | (*target as u16) << 8 | (nn as u16)
-                }
-                Argument::Register(r) => {
-                    let vy = r.consume(&table)?;
-                    0x8004 | (*target as u16) << 8 | (vy as u16) << 4
-                }
+                Argument::Constant(nn) => Opcode::Add(*target, nn.consume(table)?),
+                Argument::Register(vy) => Opcode::AddRegister(*target, vy.consume(table)?),
             },
             Instruction::Sub {
                 target: vx,
                 value: vy,
                 inverse,
-            } => (if *inverse { 0x8005 } else { 0x8007 }) | (*vx as u16) << 8 | (*vy as u16) << 4,
+            } => {
+                if *inverse {
+                    Opcode::SubInverse(*vx, *vy)
+                } else {
+                    Opcode::Sub(*vx, *vy)
+                }
+            }
             Instruction::Shift {
                 target: vy,
                 from: vx,
                 is_left,
-            } => 0x8000 | (*vx as u16) << 8 | (*vy as u16) << 4 | if !*is_left { 6 } else { 0xe },
+            } => {
+                if *is_left {
+                    Opcode::ShiftLeft(*vx, *vy)
+                } else {
+                    Opcode::ShiftRight(*vx, *vy)
+                }
+            }
             Instruction::And {
-                from: vx,
-                target: vy,
-            } => 0x8002 | (*vx as u16) << 8 | (*vy as u16) << 4,
+                from: vy,
+                target: vx,
+            } => Opcode::And(*vx, *vy),
             Instruction::Or {
-                from: vx,
-                target: vy,
-            } => 0x8001 | (*vx as u16) << 8 | (*vy as u16) << 4,
+                from: vy,
+                target: vx,
+            } => Opcode::Or(*vx, *vy),
             Instruction::Xor {
-                from: vx,
-                target: vy,
-            } => 0x8003 | (*vx as u16) << 8 | (*vy as u16) << 4,
+                from: vy,
+                target: vx,
+            } => Opcode::Xor(*vx, *vy),
             Instruction::Jump { uses_zero, target } => {
-                let target = target.consume(table)? & 0xfff;
-                (if *uses_zero { 0xb000 } else { 0x1000 }) | target
-            }
-            Instruction::Call(target) => {
-                let target = target.consume(table)? & 0xfff;
-                0x2000 | target
-            }
-            Instruction::Return => 0xee,
-            Instruction::ConditionalSkip { a: vx, b, negated } => {
-                let code = match b {
-                    Argument::Register(vy) => {
-                        let vy = vy.consume(table)?;
-                        (if *negated { 0x9000 } else { 0x5000 }) | (vy as u16) << 4
-                    }
-                    Argument::Constant(nn) => {
-                        let nn = nn.consume(table)?;
-                        (if *negated { 0x4000 } else { 0x3000 }) | (nn as u16)
-                    }
-                };
-
-                code | (*vx as u16) << 8
-            }
-            Instruction::Dump(vx) => 0xf055 | (*vx as u16) << 8,
-            Instruction::LoadR(vx) => 0xf065 | (*vx as u16) << 8,
-            Instruction::LoadI(v) => {
-                let v = v.consume(table)?;
-                0xa000 | v
+                let target = target.consume(table)?;
+                if *uses_zero {
+                    Opcode::JumpV0(target)
+                } else {
+                    Opcode::Jump(target)
+                }
             }
-            Instruction::Font(vx) => 0xf029 | (*vx as u16) << 8,
-            Instruction::AddI(vx) => 0xf01e | (*vx as u16) << 8,
-            Instruction::LoadDelay(vx) => 0xf007 | (*vx as u16) << 8,
-            Instruction::SetDelay(vx) => 0xf015 | (*vx as u16) << 8,
-            Instruction::SetSound(vx) => 0xf017 | (*vx as u16) << 8,
-            Instruction::BinaryCodedDecimal(vx) => 0xf033 | (*vx as u16) << 8,
-            Instruction::Clear => 0xe0,
+            Instruction::Call(target) => Opcode::Call(target.consume(table)?),
+            Instruction::Return => Opcode::Return,
+            Instruction::ConditionalSkip { a: vx, b, negated } => match (b, negated) {
+                (Argument::Register(vy), false) => {
+                    Opcode::SkipEqualRegister(*vx, vy.consume(table)?)
+                }
+                (Argument::Register(vy), true) => {
+                    Opcode::SkipNotEqualRegister(*vx, vy.consume(table)?)
+                }
+                (Argument::Constant(nn), false) => Opcode::SkipEqual(*vx, nn.consume(table)?),
+                (Argument::Constant(nn), true) => Opcode::SkipNotEqual(*vx, nn.consume(table)?),
+            },
+            Instruction::Dump(vx) => Opcode::Dump(*vx),
+            Instruction::LoadR(vx) => Opcode::LoadR(*vx),
+            Instruction::LoadI(v) => Opcode::LoadI(v.consume(table)?),
+            Instruction::Font(vx) => Opcode::Font(*vx),
+            Instruction::AddI(vx) => Opcode::AddI(*vx),
+            Instruction::LoadDelay(vx) => Opcode::LoadDelay(*vx),
+            Instruction::SetDelay(vx) => Opcode::SetDelay(*vx),
+            Instruction::SetSound(vx) => Opcode::SetSound(*vx),
+            Instruction::BinaryCodedDecimal(vx) => Opcode::Bcd(*vx),
+            Instruction::Clear => Opcode::Clear,
             Instruction::Draw {
                 x: vx,
                 y: vy,
                 height,
-            } => {
-                let height = height.consume(table)? & 0xf;
-                0xd000 | (*vx as u16) << 8 | (*vy as u16) << 4 | height as u16
-            }
-            Instruction::LoadKey(vx) => 0xf00a | (*vx as u16) << 8,
+            } => Opcode::Draw(*vx, *vy, height.consume(table)?),
+            Instruction::LoadKey(vx) => Opcode::LoadKey(*vx),
             Instruction::ConditionalKey {
                 negated,
                 register: vx,
-            } => 0xe000 | (*vx as u16) << 8 | if *negated { 0xa1 } else { 0x9e },
-            Instruction::Random { target: vx, mask } => {
-                let mask = mask.consume(table)? as u16;
-                0xc00 | (*vx as u16) << 8 | mask & 0xff
+            } => {
+                if *negated {
+                    Opcode::SkipNotKey(*vx)
+                } else {
+                    Opcode::SkipKey(*vx)
+                }
             }
+            Instruction::Random { target: vx, mask } => Opcode::Random(*vx, mask.consume(table)?),
         };
-        Some(v)
+        Some(op.encode())
+    }
+
+    /// The labels and constants its operands look up.
+    pub fn names(&self) -> Vec<&'a str> {
+        match self {
+            Instruction::Load { value: a, .. }
+            | Instruction::Add { value: a, .. }
+            | Instruction::ConditionalSkip { b: a, .. } => match a {
+                Argument::Constant(v) | Argument::Register(v) => v.names(),
+            },
+            Instruction::Jump { target: v, .. } | Instruction::Call(v) | Instruction::LoadI(v) => {
+                v.names()
+            }
+            Instruction::Draw { height: v, .. } | Instruction::Random { mask: v, .. } => v.names(),
+            _ => Vec::new(),
+        }
     }
 }
 
-fn parg(input: &str) -> Option<Argument> {
+pub(crate) fn parg(input: &str) -> Option<Argument<'_>> {
     if let Some(x) = preg(input) {
         Some(Argument::Register(Value::Complete(x)))
     } else {
@@ -188,10 +198,14 @@ fn parg(input: &str) -> Option<Argument> {
         Some(Argument::Constant(expr))
     }
 }
-fn preg(input: &str) -> Option<u8> {
-    if let Some('V') = input.chars().next() {
+pub(crate) fn preg(input: &str) -> Option<u8> {
+    // names like `velocity` aren't registers.
+    let ends = |rest: &str| {
+        !rest.starts_with(|c: char| c.is_alphanumeric() || matches!(c, '@' | '_' | '.'))
+    };
+    if let Some('V' | 'v') = input.chars().next() {
         if let Some(c) = input[1..].chars().next() {
-            if c.is_ascii_hexdigit() {
+            if c.is_ascii_hexdigit() && ends(&input[2..]) {
                 return Some(match c.to_ascii_lowercase() {
                     '0' => 0x0,
                     '1' => 0x1,
@@ -216,11 +230,8 @@ fn preg(input: &str) -> Option<u8> {
     }
     None
 }
-fn load(mut input: &str) -> Option<Instruction> {
-    if &input[..2] != "LD" {
-        return None;
-    }
-    input = whitespace1(&input[2..])?;
+fn load(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "LD")?)?;
     let vx = preg(input)?;
     input = pcomma(&input[2..])?;
     let value = parg(input)?;
@@ -229,24 +240,19 @@ fn load(mut input: &str) -> Option<Instruction> {
         value,
     })
 }
-fn add(mut input: &str) -> Option<Instruction> {
-    if &input[..3] != "ADD" {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn add(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "ADD")?)?;
     let vx = preg(input)?;
     input = pcomma(&input[2..])?;
     let value = parg(input)?;
     Some(Instruction::Add { target: vx, value })
 }
-fn sub(mut input: &str) -> Option<Instruction> {
-    let mut inverse = false;
-    if input.starts_with("SBI") {
-        inverse = true;
-    } else if !input.starts_with("SUB") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn sub(input: &str) -> Option<Instruction<'_>> {
+    let (mut input, inverse) = match pkeyword(input, "SBI") {
+        Some(input) => (input, true),
+        None => (pkeyword(input, "SUB")?, false),
+    };
+    input = whitespace1(input)?;
     let vx = preg(input)?;
     input = pcomma(&input[2..])?;
     let vy = preg(input)?;
@@ -256,23 +262,12 @@ fn sub(mut input: &str) -> Option<Instruction> {
         inverse,
     })
 }
-fn shift(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("SH") {
-        return None;
-    }
-    input = &input[2..];
-    let mut is_left = false;
-    let c = input.chars().next()?;
-    match c {
-        'L' => {
-            is_left = true;
-        }
-        'R' => {}
-        _ => {
-            return None;
-        }
-    }
-    input = whitespace1(&input[1..])?;
+fn shift(input: &str) -> Option<Instruction<'_>> {
+    let (mut input, is_left) = match pkeyword(input, "SHL") {
+        Some(input) => (input, true),
+        None => (pkeyword(input, "SHR")?, false),
+    };
+    input = whitespace1(input)?;
     let vx = preg(input)?;
     let mut vy = vx;
     if let Some(input) = pcomma(&input[2..]) {
@@ -284,11 +279,8 @@ fn shift(mut input: &str) -> Option<Instruction> {
         target: vy,
     })
 }
-fn and(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("AND") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn and(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "AND")?)?;
     let vx = preg(input)?;
     input = pcomma(&input[2..])?;
     let vy = preg(input)?;
@@ -297,11 +289,8 @@ fn and(mut input: &str) -> Option<Instruction> {
         target: vx,
     })
 }
-fn xor(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("XOR") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn xor(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "XOR")?)?;
     let vx = preg(input)?;
     input = pcomma(&input[2..])?;
     let vy = preg(input)?;
@@ -310,11 +299,8 @@ fn xor(mut input: &str) -> Option<Instruction> {
         target: vx,
     })
 }
-fn or(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("OR") {
-        return None;
-    }
-    input = whitespace1(&input[2..])?;
+fn or(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "OR")?)?;
     let vx = preg(input)?;
     input = pcomma(&input[2..])?;
     let vy = preg(input)?;
@@ -324,17 +310,11 @@ fn or(mut input: &str) -> Option<Instruction> {
     })
 }
 
-fn jmp(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("JP") {
-        return None;
-    }
-    input = &input[2..];
-    let mut uses_zero = false;
-    let c = input.chars().next()?;
-    if c == '0' {
-        uses_zero = true;
-        input = &input[1..];
-    }
+fn jmp(input: &str) -> Option<Instruction<'_>> {
+    let (mut input, uses_zero) = match pkeyword(input, "JP0") {
+        Some(input) => (input, true),
+        None => (pkeyword(input, "JP")?, false),
+    };
     input = whitespace1(input)?;
     let addr = pexpr(input)?;
 
@@ -344,119 +324,84 @@ fn jmp(mut input: &str) -> Option<Instruction> {
     })
 }
 
-fn call(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("CALL") {
-        return None;
-    }
-    input = whitespace1(&input[4..])?;
+fn call(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "CALL")?)?;
     let addr = pexpr(input)?;
     Some(Instruction::Call(addr))
 }
-fn ret(input: &str) -> Option<Instruction> {
-    if !input.starts_with("RET") {
+fn ret(input: &str) -> Option<Instruction<'_>> {
+    if !input.eq_ignore_ascii_case("RET") {
         None
     } else {
         Some(Instruction::Return)
     }
 }
-fn conditional_skip(mut input: &str) -> Option<Instruction> {
-    let mut negated = false;
-    if input.starts_with("SNE") {
-        negated = true;
-    } else if !input.starts_with("SEQ") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn conditional_skip(input: &str) -> Option<Instruction<'_>> {
+    let (mut input, negated) = match pkeyword(input, "SNE") {
+        Some(input) => (input, true),
+        None => (pkeyword(input, "SEQ")?, false),
+    };
+    input = whitespace1(input)?;
     let vx = preg(input)?;
     input = pcomma(&input[2..])?;
     let b = parg(input)?;
     Some(Instruction::ConditionalSkip { a: vx, b, negated })
 }
 
-fn dump(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("DMP") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn dump(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "DMP")?)?;
     let vx = preg(input)?;
     Some(Instruction::Dump(vx))
 }
 
-fn load_registers(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("LDR") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn load_registers(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "LDR")?)?;
     let vx = preg(input)?;
     Some(Instruction::LoadR(vx))
 }
-fn set_address(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("LDI") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn set_address(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "LDI")?)?;
     let addr = pexpr(input)?;
     Some(Instruction::LoadI(addr))
 }
-fn font(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("FNT") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn font(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "FNT")?)?;
     let vx = preg(input)?;
     Some(Instruction::Font(vx))
 }
-fn add_i(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("ADDI") {
-        return None;
-    }
-    input = whitespace1(&input[4..])?;
+fn add_i(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "ADDI")?)?;
     let vx = preg(input)?;
     Some(Instruction::AddI(vx))
 }
-fn load_delay(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("LDD") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn load_delay(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "LDD")?)?;
     let vx = preg(input)?;
     Some(Instruction::LoadDelay(vx))
 }
-fn set_delay(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("DLY") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn set_delay(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "DLY")?)?;
     let vx = preg(input)?;
     Some(Instruction::SetDelay(vx))
 }
-fn set_sound(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("SND") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn set_sound(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "SND")?)?;
     let vx = preg(input)?;
     Some(Instruction::SetSound(vx))
 }
-fn bcd(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("BCD") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn bcd(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "BCD")?)?;
     let vx = preg(input)?;
     Some(Instruction::BinaryCodedDecimal(vx))
 }
-fn clear(input: &str) -> Option<Instruction> {
-    if input != "CLR" {
+fn clear(input: &str) -> Option<Instruction<'_>> {
+    if !input.eq_ignore_ascii_case("CLR") {
         return None;
     }
     Some(Instruction::Clear)
 }
-fn draw(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("DRW") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn draw(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "DRW")?)?;
     let vx = preg(input)?;
     input = pcomma(&input[2..])?;
     let vy = preg(input)?;
@@ -468,33 +413,25 @@ fn draw(mut input: &str) -> Option<Instruction> {
         height,
     })
 }
-fn load_key(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("LDK") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn load_key(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "LDK")?)?;
     let vx = preg(input)?;
     Some(Instruction::LoadKey(vx))
 }
-fn conditional_key(mut input: &str) -> Option<Instruction> {
-    let mut negated = false;
-    if input.starts_with("SNK") {
-        negated = true;
-    } else if !input.starts_with("SIK") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn conditional_key(input: &str) -> Option<Instruction<'_>> {
+    let (mut input, negated) = match pkeyword(input, "SNK") {
+        Some(input) => (input, true),
+        None => (pkeyword(input, "SIK")?, false),
+    };
+    input = whitespace1(input)?;
     let vx = preg(input)?;
     Some(Instruction::ConditionalKey {
         register: vx,
         negated,
     })
 }
-fn random(mut input: &str) -> Option<Instruction> {
-    if !input.starts_with("RND") {
-        return None;
-    }
-    input = whitespace1(&input[3..])?;
+fn random(mut input: &str) -> Option<Instruction<'_>> {
+    input = whitespace1(pkeyword(input, "RND")?)?;
     let vx = preg(input)?;
     let mut mask = Value::Complete(0xff);
     if let Some(input) = pcomma(&input[2..]) {
@@ -502,7 +439,7 @@ fn random(mut input: &str) -> Option<Instruction> {
     }
     Some(Instruction::Random { target: vx, mask })
 }
-pub fn any(input: &str) -> Option<Instruction> {
+pub fn any(input: &str) -> Option<Instruction<'_>> {
     const PARSERS: &[fn(&str) -> Option<Instruction>] = &[
         clear,
         ret,
@@ -543,6 +480,56 @@ pub fn any(input: &str) -> Option<Instruction> {
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    mod compiling {
+        use super::*;
+
+        fn compile(input: &str) -> Option<u16> {
+            any(input)?.compile(&Table::new())
+        }
+
+        #[test]
+        fn logic() {
+            assert_eq!(compile("AND V0, VF"), Some(0x80f2));
+            assert_eq!(compile("OR V1, V2"), Some(0x8121));
+            assert_eq!(compile("XOR V3, V4"), Some(0x8343));
+        }
+
+        #[test]
+        fn sub() {
+            assert_eq!(compile("SUB V0, V1"), Some(0x8015));
+            assert_eq!(compile("SBI V0, V1"), Some(0x8017));
+        }
+
+        #[test]
+        fn random_sound() {
+            assert_eq!(compile("RND V3, 0x0f"), Some(0xc30f));
+            assert_eq!(compile("SND V2"), Some(0xf218));
+        }
+
+        #[test]
+        fn any_case() {
+            assert_eq!(compile("ld v0, 0x12"), Some(0x6012));
+            assert_eq!(compile("Jp0 0x300"), Some(0xb300));
+            assert_eq!(compile("shl vA"), Some(0x8aae));
+            assert_eq!(compile("sknp v1"), None);
+            assert_eq!(compile("snk v1"), Some(0xe1a1));
+            assert_eq!(compile("ret"), Some(0x00ee));
+            assert_eq!(compile("l"), None);
+        }
+
+        #[test]
+        fn names_like_registers() {
+            let mut table = Table::new();
+            table.insert("velocity", 5.into());
+            table.insert("vdelta", 0x20.into());
+            let compile = |input| any(input)?.compile(&table);
+            assert_eq!(compile("LD V0, velocity"), Some(0x6005));
+            assert_eq!(compile("SEQ V1, vdelta"), Some(0x3120));
+            assert_eq!(compile("LD VE, V1"), Some(0x8e10));
+            assert_eq!(compile("LD vdelta, 1"), None);
+        }
+    }
     mod parsing {
         use super::*;
 
@@ -0,0 +1,721 @@
+//! A Language Server Protocol server for the assembly, so editors can show
+//! its errors on save, jump between labels and constants and their uses,
+//! show what a line assembles to, complete instructions and outline labels.
+//!
+//! Files ending in `.8o` are assembled as Octo, the rest as native.
+//! The names are the ones the assembler reads, found back in the source.
+use crate::assembler::{assemble_with_symbols, mentions, strip_ws_comments, Mention, Options};
+use crate::parse_utils::{span, string_len};
+use crate::symbols::Symbols;
+use crate::syntax::Syntax;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// The mnemonics of the native syntax, pseudo instructions included.
+const MNEMONICS: &[&str] = &[
+    "CLR", "RET", "JP", "JP0", "CALL", "SEQ", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SBI",
+    "SHR", "SHL", "LDI", "ADDI", "RND", "DRW", "SIK", "SNK", "LDK", "LDD", "DLY", "SND", "FNT",
+    "BCD", "DMP", "LDR", "NOT", "NEG", "SWAP",
+];
+
+const DIRECTIVES: &[&str] = &[
+    "db",
+    "dw",
+    ".repeat",
+    ".reserve",
+    ".align",
+    ".org",
+    ".fill",
+    ".entrypoint",
+    ".noentrypoint",
+    ".charmap",
+    ".text",
+    ".textw",
+    ".sprite",
+    ".glyph",
+    ".end",
+];
+
+const REGISTERS: &[&str] = &[
+    "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8", "V9", "VA", "VB", "VC", "VD", "VE", "VF",
+    "I", "DT", "ST",
+];
+
+/// `SymbolKind`s and `CompletionItemKind`s of the protocol.
+const SYMBOL_LABEL: u64 = 12;
+const SYMBOL_CONSTANT: u64 = 14;
+const ITEM_LABEL: u64 = 3;
+const ITEM_REGISTER: u64 = 6;
+const ITEM_KEYWORD: u64 = 14;
+const ITEM_CONSTANT: u64 = 21;
+
+/// Reads the next message, `None` at the end of the input.
+/// The Debug Adapter Protocol frames its messages the same way.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() && length.is_some() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            length = n.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// The characters of `line` that `bytes` covers, in the UTF-16
+/// code units the protocol counts positions in.
+fn columns(line: &str, bytes: Range<usize>) -> Range<usize> {
+    let start = line[..bytes.start].encode_utf16().count();
+    start..start + line[bytes].encode_utf16().count()
+}
+
+fn is_name(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '@' | '_' | '.')
+}
+
+/// Where `name` is written in `statement` from byte `from` on, outside
+/// strings, as is or, for a local name like `main.loop`, as `.loop`.
+fn find(statement: &str, from: usize, name: &str) -> Option<Range<usize>> {
+    let local = name.match_indices('.').map(|(i, _)| &name[i..]);
+    let written: Vec<&str> = std::iter::once(name).chain(local).collect();
+    let mut start = from;
+    while let Some(rest) = statement.get(start..).filter(|rest| !rest.is_empty()) {
+        if let Some(n) = string_len(rest) {
+            start += n;
+            continue;
+        }
+        if !statement[..start].ends_with(is_name) {
+            let found = written
+                .iter()
+                .find(|w| rest.starts_with(**w) && !rest[w.len()..].starts_with(is_name));
+            if let Some(w) = found {
+                return Some(start..start + w.len());
+            }
+        }
+        start += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+fn range(line: usize, columns: &Range<usize>) -> Value {
+    json!({
+        "start": { "line": line, "character": columns.start },
+        "end": { "line": line, "character": columns.end },
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Label,
+    Constant,
+}
+
+/// A label or constant where it's written, with the name
+/// qualified as in `main.loop`, and its line from 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Name {
+    name: String,
+    line: usize,
+    columns: Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Definition {
+    kind: Kind,
+    /// a local label or constant, as in `.loop:`.
+    local: bool,
+    at: Name,
+    /// the whole statement.
+    columns: Range<usize>,
+    /// the statement as written, for when the file doesn't assemble.
+    text: String,
+}
+
+/// A source file as the editor has it.
+pub struct Document {
+    definitions: Vec<Definition>,
+    /// every use of the names defined, definitions included.
+    uses: Vec<Name>,
+    /// the statement of every line, to point errors at.
+    statements: Vec<Range<usize>>,
+    origin: u16,
+    assembled: Result<(Vec<u8>, Symbols), String>,
+}
+
+impl Document {
+    pub fn new(text: &str, syntax: Syntax) -> Self {
+        let lines: Vec<&str> = text.lines().collect();
+        let statements: Vec<Range<usize>> = lines
+            .iter()
+            .map(|line| span(line, strip_ws_comments(line, syntax.comment())))
+            .collect();
+        let options = Options {
+            syntax,
+            ..Options::default()
+        };
+        let mut definitions = Vec::new();
+        let mut written = Vec::new();
+        // where the next name on each line is looked for.
+        let mut next = statements.iter().map(|s| s.start).collect::<Vec<_>>();
+        // a broken block or scope leaves no names until it's fixed.
+        for (at, mention) in mentions(text, &options).unwrap_or_default() {
+            let n = at.line - 1;
+            let (line, statement) = (lines[n], &statements[n]);
+            let (kind, name) = match &mention {
+                Mention::Label(name) => (Some(Kind::Label), name),
+                Mention::Constant(name) => (Some(Kind::Constant), name),
+                Mention::Use(name) => (None, name),
+            };
+            let column = line
+                .char_indices()
+                .nth(at.column - 1)
+                .map_or(line.len(), |(i, _)| i);
+            let from = next[n].max(column);
+            // the names the assembler makes up aren't anywhere.
+            let bytes = match find(&line[..statement.end], from, name) {
+                Some(bytes) => bytes,
+                None => continue,
+            };
+            next[n] = bytes.end;
+            let at = Name {
+                name: name.clone(),
+                line: n,
+                columns: columns(line, bytes.clone()),
+            };
+            if let Some(kind) = kind {
+                definitions.push(Definition {
+                    kind,
+                    local: line[bytes].starts_with('.'),
+                    at: at.clone(),
+                    columns: columns(line, statement.clone()),
+                    text: line[statement.clone()].to_string(),
+                });
+            }
+            written.push(at);
+        }
+        let uses = written
+            .into_iter()
+            .filter(|name| definitions.iter().any(|d| d.at.name == name.name))
+            .collect();
+        Self {
+            definitions,
+            uses,
+            statements: statements
+                .into_iter()
+                .zip(lines.iter())
+                .map(|(statement, line)| columns(line, statement))
+                .collect(),
+            origin: options.origin,
+            assembled: assemble_with_symbols(text, &options),
+        }
+    }
+
+    /// The error assembling the file, on the line it's about.
+    pub fn diagnostics(&self) -> Vec<Value> {
+        let error = match &self.assembled {
+            Ok(_) => return Vec::new(),
+            Err(e) => e,
+        };
+        let located = error
+            .strip_prefix("line ")
+            .and_then(|rest| rest.split_once(": "))
+            .and_then(|(n, message)| Some((n.parse::<usize>().ok()?.checked_sub(1)?, message)))
+            .filter(|(line, _)| *line < self.statements.len());
+        let (range, message) = match located {
+            Some((line, message)) => (range(line, &self.statements[line]), message),
+            None => (range(0, &(0..0)), error.as_str()),
+        };
+        vec![json!({
+            "range": range,
+            "severity": 1,
+            "source": "chip8",
+            "message": message,
+        })]
+    }
+
+    /// The name at a position, if it's one defined.
+    fn name(&self, line: usize, character: usize) -> Option<&Name> {
+        self.uses.iter().find(|name| {
+            name.line == line && name.columns.start <= character && character <= name.columns.end
+        })
+    }
+
+    fn definition_of(&self, name: &str) -> Option<&Definition> {
+        self.definitions.iter().find(|d| d.at.name == name)
+    }
+
+    /// Where the name at a position is defined.
+    pub fn definition(&self, line: usize, character: usize) -> Option<(usize, Value)> {
+        let name = self.name(line, character)?;
+        let definition = self.definition_of(&name.name)?;
+        Some((
+            definition.at.line,
+            range(definition.at.line, &definition.at.columns),
+        ))
+    }
+
+    /// Where the name at a position is used, with its definition if `declaration`.
+    pub fn references(&self, line: usize, character: usize, declaration: bool) -> Vec<Value> {
+        let name = match self.name(line, character) {
+            Some(name) => name,
+            None => return Vec::new(),
+        };
+        let definition = self.definition_of(&name.name).map(|d| &d.at);
+        self.uses
+            .iter()
+            .filter(|u| u.name == name.name && (declaration || Some(*u) != definition))
+            .map(|u| range(u.line, &u.columns))
+            .collect()
+    }
+
+    /// The value of the name at a position, or what its line assembles to.
+    /// Names show where they're defined while the file doesn't assemble.
+    pub fn hover(&self, line: usize, character: usize) -> Option<String> {
+        if let Some(name) = self.name(line, character) {
+            let definition = self.definition_of(&name.name)?;
+            let what = match definition.kind {
+                Kind::Label => "label at",
+                Kind::Constant => "=",
+            };
+            let value = self.assembled.as_ref().ok().and_then(|(_, symbols)| {
+                let table = match definition.kind {
+                    Kind::Label => &symbols.labels,
+                    Kind::Constant => &symbols.constants,
+                };
+                table.iter().find(|(n, _)| *n == name.name)
+            });
+            return Some(match value {
+                Some((_, value)) => format!("`{}` {} 0x{:03x}", name.name, what, value),
+                None => format!("`{}` on line {}", definition.text, definition.at.line + 1),
+            });
+        }
+        let (rom, symbols) = self.assembled.as_ref().ok()?;
+        let bytes = |address: u16, len: u16| {
+            let start = (address - self.origin) as usize;
+            let end = (start + len as usize).min(rom.len());
+            rom[start..end]
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+        };
+        let mut lines = Vec::new();
+        for (address, location) in symbols.code.iter() {
+            if location.line == line + 1 {
+                lines.push(format!(
+                    "0x{:03x}: `{}`",
+                    address,
+                    bytes(*address, 2).concat()
+                ));
+            }
+        }
+        for (address, (len, location)) in symbols.data.iter() {
+            if location.line == line + 1 {
+                let mut shown = bytes(*address, (*len).min(8));
+                if *len > 8 {
+                    shown.push("…".to_string());
+                }
+                lines.push(format!("0x{:03x}: `{}`", address, shown.join(" ")));
+            }
+        }
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("  \n"))
+        }
+    }
+
+    pub fn completions(&self) -> Vec<Value> {
+        let item = |label: &str, kind: u64| json!({ "label": label, "kind": kind });
+        let mut items: Vec<Value> = MNEMONICS
+            .iter()
+            .chain(DIRECTIVES.iter())
+            .map(|m| item(m, ITEM_KEYWORD))
+            .chain(REGISTERS.iter().map(|r| item(r, ITEM_REGISTER)))
+            .collect();
+        for d in self.definitions.iter() {
+            let kind = match d.kind {
+                Kind::Label => ITEM_LABEL,
+                Kind::Constant => ITEM_CONSTANT,
+            };
+            items.push(item(&d.at.name, kind));
+        }
+        items
+    }
+
+    /// The labels and constants, the local ones in their global label.
+    pub fn outline(&self) -> Vec<Value> {
+        let mut symbols: Vec<Value> = Vec::new();
+        let mut global = None;
+        for d in self.definitions.iter() {
+            let kind = match d.kind {
+                Kind::Label => SYMBOL_LABEL,
+                Kind::Constant => SYMBOL_CONSTANT,
+            };
+            let symbol = json!({
+                "name": d.at.name,
+                "kind": kind,
+                "range": range(d.at.line, &d.columns),
+                "selectionRange": range(d.at.line, &d.at.columns),
+                "children": [],
+            });
+            match global {
+                Some(i) if d.local => {
+                    let parent: &mut Value = &mut symbols[i];
+                    if let Some(children) = parent["children"].as_array_mut() {
+                        children.push(symbol);
+                    }
+                }
+                _ => {
+                    if d.kind == Kind::Label && !d.local {
+                        global = Some(symbols.len());
+                    }
+                    symbols.push(symbol);
+                }
+            }
+        }
+        symbols
+    }
+}
+
+/// Answers the requests of an editor about the files it has open.
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    done: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the editor said to exit.
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    fn open(&mut self, uri: &str, text: &str) {
+        let syntax = Syntax::of_file(Path::new(uri)).unwrap_or_default();
+        self.documents
+            .insert(uri.to_string(), Document::new(text, syntax));
+    }
+
+    fn publish(&self, uri: &str) -> Vec<Value> {
+        let diagnostics = self
+            .documents
+            .get(uri)
+            .map(Document::diagnostics)
+            .unwrap_or_default();
+        vec![json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })]
+    }
+
+    /// The response to a message, if it's a request, and the notifications it causes.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+        let document = self.documents.get(uri);
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": {
+                        "openClose": true,
+                        "change": 1,
+                        "save": { "includeText": true },
+                    },
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "chip8" },
+            })),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                let uri = uri.to_string();
+                self.open(&uri, text);
+                return self.publish(&uri);
+            }
+            "textDocument/didChange" => {
+                // the whole text, as the capabilities ask.
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    let uri = uri.to_string();
+                    self.open(&uri, text);
+                }
+                return Vec::new();
+            }
+            "textDocument/didSave" => {
+                let uri = uri.to_string();
+                if let Some(text) = params["text"].as_str() {
+                    self.open(&uri, text);
+                }
+                return self.publish(&uri);
+            }
+            "textDocument/didClose" => {
+                let uri = uri.to_string();
+                self.documents.remove(&uri);
+                return self.publish(&uri);
+            }
+            "textDocument/definition" => Ok(document
+                .and_then(|d| d.definition(line, character))
+                .map(|(_, range)| json!({ "uri": uri, "range": range }))
+                .unwrap_or(Value::Null)),
+            "textDocument/references" => {
+                let declaration = params["context"]["includeDeclaration"]
+                    .as_bool()
+                    .unwrap_or(true);
+                let ranges = document
+                    .map(|d| d.references(line, character, declaration))
+                    .unwrap_or_default();
+                Ok(ranges
+                    .into_iter()
+                    .map(|range| json!({ "uri": uri, "range": range }))
+                    .collect())
+            }
+            "textDocument/hover" => Ok(document
+                .and_then(|d| d.hover(line, character))
+                .map(|text| json!({ "contents": { "kind": "markdown", "value": text } }))
+                .unwrap_or(Value::Null)),
+            "textDocument/completion" => Ok(document
+                .map(|d| Value::from(d.completions()))
+                .unwrap_or(Value::Null)),
+            "textDocument/documentSymbol" => Ok(document
+                .map(|d| Value::from(d.outline()))
+                .unwrap_or(Value::Null)),
+            "shutdown" => Ok(Value::Null),
+            "exit" => {
+                self.done = true;
+                return Vec::new();
+            }
+            _ => Err(format!("unknown method {:?}", method)),
+        };
+        // notifications get no response.
+        let id = match message.get("id") {
+            Some(id) => id,
+            None => return Vec::new(),
+        };
+        vec![match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": e },
+            }),
+        }]
+    }
+}
+
+/// Answers the messages from `input` on `output` until the editor exits.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server::new();
+    while !server.done() {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => continue,
+            Ok(None) => break,
+            Err(e) => return Err(e),
+        };
+        for reply in server.handle(&message).iter() {
+            write_message(&mut output, reply)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SOURCE: &str = "\
+SPEED = 2
+main:
+  LD V0, SPEED
+.loop:
+  ADD V0, SPEED ; faster
+  JP .loop
+data:
+  db 1, 2
+.entrypoint main
+";
+
+    fn position(line: usize, character: usize) -> Value {
+        json!({ "line": line, "character": character })
+    }
+
+    #[test]
+    fn document() {
+        let document = Document::new(SOURCE, Syntax::Native);
+        assert!(document.diagnostics().is_empty());
+
+        // `.loop` in `JP .loop`, and `SPEED` in the ADD.
+        assert_eq!(
+            document.definition(5, 7),
+            Some((3, json!({ "start": position(3, 0), "end": position(3, 5) })))
+        );
+        assert_eq!(document.definition(4, 12).map(|(line, _)| line), Some(0));
+        assert_eq!(document.definition(4, 3), None);
+        assert_eq!(document.references(0, 0, true).len(), 3);
+        assert_eq!(
+            document.references(3, 2, false),
+            vec![json!({ "start": position(5, 5), "end": position(5, 10) })]
+        );
+
+        assert_eq!(document.hover(0, 1), Some("`SPEED` = 0x002".to_string()));
+        assert_eq!(
+            document.hover(3, 2),
+            Some("`main.loop` label at 0x204".to_string())
+        );
+        assert_eq!(document.hover(4, 2), Some("0x204: `7002`".to_string()));
+        assert_eq!(document.hover(7, 3), Some("0x208: `01 02`".to_string()));
+        assert_eq!(document.hover(1, 6), None);
+
+        let completions = document.completions();
+        let names: Vec<&str> = completions
+            .iter()
+            .filter(|item| item["kind"] == ITEM_LABEL || item["kind"] == ITEM_CONSTANT)
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert!(completions.contains(&json!({ "label": "DRW", "kind": ITEM_KEYWORD })));
+        assert!(completions.contains(&json!({ "label": "VF", "kind": ITEM_REGISTER })));
+        assert_eq!(names, vec!["SPEED", "main", "main.loop", "data"]);
+
+        let outline = document.outline();
+        let names: Vec<&Value> = outline.iter().map(|s| &s["name"]).collect();
+        assert_eq!(names, vec!["SPEED", "main", "data"]);
+        assert_eq!(outline[1]["children"][0]["name"], "main.loop");
+
+        let broken = Document::new("main:\n  JP nowhere\n.entrypoint main", Syntax::Native);
+        let diagnostics = broken.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({ "start": position(1, 2), "end": position(1, 12) })
+        );
+    }
+
+    #[test]
+    fn names() {
+        // only the names the assembler reads, so not the one in the string.
+        let source = "\
+SPEED = 2
+main:
+  if V0 == SPEED then CLR
+  .text \"SPEED\"
+  .charmap \"🦀\", SPEED
+  .charmap \"SPEED\", SPEED
+  JP nowhere
+";
+        let document = Document::new(source, Syntax::Native);
+        assert_eq!(document.references(0, 0, true).len(), 4);
+        assert_eq!(document.definition(2, 12).map(|(line, _)| line), Some(0));
+        // the crab takes two UTF-16 units.
+        assert_eq!(
+            document.references(0, 0, false)[1],
+            json!({ "start": position(4, 17), "end": position(4, 22) })
+        );
+        assert_eq!(
+            document.references(0, 0, false)[2],
+            json!({ "start": position(5, 20), "end": position(5, 25) })
+        );
+        // it doesn't assemble, but the names are still there.
+        assert_eq!(
+            document.hover(2, 12),
+            Some("`SPEED = 2` on line 1".to_string())
+        );
+        assert_eq!(document.hover(2, 2), None);
+
+        let octo = Document::new(": main\n  jump main\n", Syntax::Octo);
+        assert_eq!(
+            octo.definition(1, 8),
+            Some((0, json!({ "start": position(0, 2), "end": position(0, 6) })))
+        );
+    }
+
+    #[test]
+    fn session() {
+        let uri = "file:///game.asm";
+        let messages = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": { "uri": uri, "languageId": "chip8", "version": 1, "text": SOURCE },
+            }}),
+            json!({ "jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": "main:\n  JP main\n" }],
+            }}),
+            json!({ "jsonrpc": "2.0", "method": "textDocument/didSave", "params": {
+                "textDocument": { "uri": uri },
+            }}),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "textDocument/definition", "params": {
+                "textDocument": { "uri": uri }, "position": position(1, 6),
+            }}),
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "textDocument/formatting", "params": {} }),
+            json!({ "jsonrpc": "2.0", "id": 4, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ];
+        let mut input = Vec::new();
+        for message in messages.iter() {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output).unwrap();
+
+        let mut output = Cursor::new(output);
+        let mut replies = Vec::new();
+        while let Some(reply) = read_message(&mut output).unwrap() {
+            replies.push(reply);
+        }
+        assert_eq!(replies.len(), 6);
+        assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(replies[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+        // the change has no `_start` to jump to, which shows on save.
+        let diagnostics = &replies[2]["params"]["diagnostics"];
+        assert!(diagnostics[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("_start"));
+        assert_eq!(
+            replies[3]["result"],
+            json!({ "uri": uri, "range": { "start": position(0, 0), "end": position(0, 4) } })
+        );
+        assert_eq!(replies[4]["error"]["code"], -32601);
+        assert_eq!(
+            replies[5],
+            json!({ "jsonrpc": "2.0", "id": 4, "result": null })
+        );
+    }
+}
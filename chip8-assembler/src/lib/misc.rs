@@ -1,8 +1,8 @@
 use crate::parse_utils::*;
-pub fn constant(input: &str) -> Option<(&str, Value<u16>)> {
-    let (mut input, name) = parse_name(&input).map(|(a, b)| (whitespace(a), b))?;
+pub fn constant(input: &str) -> Option<(&str, Value<'_, u16>)> {
+    let (mut input, name) = parse_name(input).map(|(a, b)| (whitespace(a), b))?;
     input = input.strip_prefix("=").map(whitespace)?;
-    let value = pexpr(&input)?;
+    let value = pexpr(input)?;
     Some((name, value))
 }
 
@@ -1,4 +1,17 @@
+pub mod assembler;
+pub mod cli;
+pub mod control;
+pub mod cowgod;
 pub mod directives;
 pub mod instructions;
+pub mod lsp;
 pub mod misc;
+pub mod octo;
+pub mod output;
 pub mod parse_utils;
+pub mod pseudo;
+pub mod scope;
+pub mod sprite;
+pub mod symbols;
+pub mod syntax;
+pub mod target;
@@ -0,0 +1,382 @@
+//! The syntax of the Octo assembler, which most modern CHIP-8 games use:
+//!
+//! ```text
+//! : main
+//!   i := digits
+//!   v0 := 0
+//!   loop
+//!     sprite v0 v1 5
+//!     v0 += 6
+//!     if v0 == 60 then return
+//!   again
+//! ```
+//!
+//! Octo doesn't care about lines, only about tokens, so a statement
+//! may span several of them and a line may hold several statements.
+//! Every statement becomes one native statement, on the line of its first token.
+//! Names may have dashes in them, which become underscores.
+//!
+//! `:macro`, `:calc` and the other compile time features aren't supported,
+//! neither are the SCHIP and XO-CHIP instructions.
+use crate::parse_utils::*;
+use crate::symbols::Location;
+use std::collections::HashMap;
+
+/// Instructions the assembler has no encoding for yet.
+const UNSUPPORTED: &[&str] = &[
+    "hires",
+    "lores",
+    "exit",
+    "scroll-down",
+    "scroll-up",
+    "scroll-left",
+    "scroll-right",
+    "saveflags",
+    "loadflags",
+    "plane",
+    "audio",
+    "pitch",
+];
+
+fn register(token: &str, aliases: &HashMap<&str, u8>) -> Option<u8> {
+    if let Some(r) = aliases.get(token) {
+        return Some(*r);
+    }
+    let mut chars = token.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v' | 'V'), Some(c), None) => c.to_digit(16).map(|r| r as u8),
+        _ => None,
+    }
+}
+
+fn name(token: &str) -> Result<String, String> {
+    let name = token.replace('-', "_");
+    match parse_name(&name) {
+        Some(("", _)) => Ok(name),
+        _ => Err(format!("{:?} isn't a valid name", token)),
+    }
+}
+
+/// A number or a name, as a native expression.
+fn value(token: &str) -> Result<String, String> {
+    if let Some(negated) = token.strip_prefix('-') {
+        return Ok(format!("0 - {}", value(negated)?));
+    }
+    if let Some(binary) = token.strip_prefix("0b") {
+        return u16::from_str_radix(binary, 2)
+            .map(|v| v.to_string())
+            .map_err(|_| format!("{:?} isn't a valid number", token));
+    }
+    if token.starts_with(|c: char| c.is_ascii_digit()) {
+        return match parse_num(token) {
+            Some(("", _)) => Ok(token.to_string()),
+            _ => Err(format!("{:?} isn't a valid number", token)),
+        };
+    }
+    name(token)
+}
+
+fn is_number(token: &str) -> bool {
+    token
+        .trim_start_matches('-')
+        .starts_with(|c: char| c.is_ascii_digit())
+}
+
+struct Translator<'a> {
+    tokens: std::vec::IntoIter<(Location, &'a str)>,
+    aliases: HashMap<&'a str, u8>,
+    /// line of the last token, where errors get reported.
+    line: usize,
+}
+
+impl<'a> Translator<'a> {
+    fn token(&mut self) -> Result<&'a str, String> {
+        let (at, token) = self
+            .tokens
+            .next()
+            .ok_or_else(|| String::from("unexpected end of file"))?;
+        self.line = at.line;
+        Ok(token)
+    }
+
+    fn register_number(&mut self) -> Result<u8, String> {
+        let token = self.token()?;
+        register(token, &self.aliases)
+            .ok_or_else(|| format!("expected a register, got {:?}", token))
+    }
+
+    fn register(&mut self) -> Result<String, String> {
+        self.register_number().map(|r| format!("V{:X}", r))
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        value(self.token()?)
+    }
+
+    /// A register or a value, for the instructions that take both.
+    fn operand(&mut self) -> Result<String, String> {
+        let token = self.token()?;
+        match register(token, &self.aliases) {
+            Some(r) => Ok(format!("V{:X}", r)),
+            None => value(token),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.token()? {
+            token if token == expected => Ok(()),
+            token => Err(format!("expected {:?}, got {:?}", expected, token)),
+        }
+    }
+
+    fn condition(&mut self) -> Result<String, String> {
+        let vx = self.register()?;
+        match self.token()? {
+            op @ ("==" | "!=") => Ok(format!("{} {} {}", vx, op, self.operand()?)),
+            op @ ("key" | "-key") => Ok(format!("{} {}", vx, op)),
+            op => Err(format!(
+                "{:?} isn't a supported comparison, use == or != instead",
+                op
+            )),
+        }
+    }
+
+    fn assignment(&mut self, vx: u8) -> Result<String, String> {
+        let vx = format!("V{:X}", vx);
+        let native = match self.token()? {
+            ":=" => match self.token()? {
+                "key" => format!("LDK {}", vx),
+                "delay" => format!("LDD {}", vx),
+                "random" => format!("RND {}, {}", vx, self.value()?),
+                token => match register(token, &self.aliases) {
+                    Some(vy) => format!("LD {}, V{:X}", vx, vy),
+                    None => format!("LD {}, {}", vx, value(token)?),
+                },
+            },
+            "+=" => format!("ADD {}, {}", vx, self.operand()?),
+            "-=" => format!("SUB {}, {}", vx, self.operand()?),
+            "=-" => format!("SBI {}, {}", vx, self.register()?),
+            "|=" => format!("OR {}, {}", vx, self.register()?),
+            "&=" => format!("AND {}, {}", vx, self.register()?),
+            "^=" => format!("XOR {}, {}", vx, self.register()?),
+            ">>=" => format!("SHR {}, {}", vx, self.register()?),
+            "<<=" => format!("SHL {}, {}", vx, self.register()?),
+            op => return Err(format!("unknown operator {:?}", op)),
+        };
+        Ok(native)
+    }
+
+    /// The native statements for the Octo one starting with `token`.
+    fn statement(&mut self, token: &'a str) -> Result<Vec<String>, String> {
+        let native = match token {
+            ":" => format!("{}:", name(self.token()?)?),
+            ":const" => {
+                let name = name(self.token()?)?;
+                format!("{} = {}", name, self.value()?)
+            }
+            ":alias" => {
+                let alias = self.token()?;
+                let r = self.register_number()?;
+                self.aliases.insert(alias, r);
+                return Ok(Vec::new());
+            }
+            ":org" => format!(".org {}", self.value()?),
+            ":byte" => format!("db {}", self.value()?),
+            ":call" => format!("CALL {}", self.value()?),
+            ":breakpoint" => {
+                // only means something to Octo's debugger.
+                self.token()?;
+                return Ok(Vec::new());
+            }
+            ";" | "return" => String::from("RET"),
+            "clear" => String::from("CLR"),
+            "bcd" => format!("BCD {}", self.register()?),
+            "save" => format!("DMP {}", self.register()?),
+            "load" => format!("LDR {}", self.register()?),
+            "sprite" => {
+                let vx = self.register()?;
+                let vy = self.register()?;
+                format!("DRW {}, {}, {}", vx, vy, self.value()?)
+            }
+            "jump" => format!("JP {}", self.value()?),
+            "jump0" => format!("JP0 {}", self.value()?),
+            "native" => format!("dw {}", self.value()?),
+            "loop" | "again" | "else" | "end" => token.to_string(),
+            "while" => format!("while {}", self.condition()?),
+            "if" => {
+                let c = self.condition()?;
+                match self.token()? {
+                    "begin" => format!("if {} then", c),
+                    "then" => {
+                        let token = self.token()?;
+                        match self.statement(token)?.as_slice() {
+                            [s] => format!("if {} then {}", c, s),
+                            _ => return Err(format!("{:?} can't go after 'then'", token)),
+                        }
+                    }
+                    token => return Err(format!("expected 'then' or 'begin', got {:?}", token)),
+                }
+            }
+            "i" => match self.token()? {
+                ":=" => match self.token()? {
+                    "hex" => format!("FNT {}", self.register()?),
+                    token => format!("LDI {}", value(token)?),
+                },
+                "+=" => format!("ADDI {}", self.register()?),
+                op => return Err(format!("unknown operator {:?} for i", op)),
+            },
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let vx = self.register()?;
+                if token == "delay" {
+                    format!("DLY {}", vx)
+                } else {
+                    format!("SND {}", vx)
+                }
+            }
+            _ if UNSUPPORTED.contains(&token) => {
+                return Err(format!("{:?} isn't supported yet", token))
+            }
+            _ if token.starts_with(':') => return Err(format!("{:?} isn't supported", token)),
+            _ if is_number(token) => format!("db {}", value(token)?),
+            _ => match register(token, &self.aliases) {
+                Some(vx) => self.assignment(vx)?,
+                // a bare name is a call.
+                None => format!("CALL {}", name(token)?),
+            },
+        };
+        Ok(vec![native])
+    }
+}
+
+/// Translates Octo source, already stripped of comments, into native statements.
+pub fn translate(statements: Statements) -> Result<Statements, String> {
+    // every token where it starts, as statements start at their first one.
+    let tokens = statements
+        .iter()
+        .flat_map(|(at, s)| {
+            s.split_whitespace().map(move |t| {
+                let column = at.column + s[..span(s, t).start].chars().count();
+                let at = Location {
+                    line: at.line,
+                    column,
+                };
+                (at, t)
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut translator = Translator {
+        tokens: tokens.into_iter(),
+        aliases: HashMap::new(),
+        line: 1,
+    };
+    let mut out = Vec::new();
+    while let Some((at, token)) = translator.tokens.next() {
+        translator.line = at.line;
+        let native = translator
+            .statement(token)
+            .map_err(|e| format!("line {}: {}", translator.line, e))?;
+        out.extend(native.into_iter().map(|s| (at, s)));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translate(lines: Vec<&str>) -> Result<Statements, String> {
+        super::translate(numbered(lines))
+    }
+
+    fn at(line: usize, column: usize) -> Location {
+        Location { line, column }
+    }
+
+    #[test]
+    fn statements() {
+        assert_eq!(
+            translate(vec![
+                ": main",
+                "  v0 := 5 v1 += v0 i := sprite-data",
+                "  sprite v0 v1 5 if v1 != 0x1f then",
+                "  return",
+            ]),
+            Ok(vec![
+                (at(1, 1), String::from("main:")),
+                (at(2, 3), String::from("LD V0, 5")),
+                (at(2, 11), String::from("ADD V1, V0")),
+                (at(2, 20), String::from("LDI sprite_data")),
+                (at(3, 3), String::from("DRW V0, V1, 5")),
+                (at(3, 18), String::from("if V1 != 0x1f then RET")),
+            ])
+        );
+    }
+
+    #[test]
+    fn assignments() {
+        let statements = translate(vec![
+            ":alias x v3",
+            "x := random 0b111 x := key x := delay",
+            "x -= 1 x =- v1 x >>= v1 i := hex x i += x delay := x",
+        ])
+        .unwrap()
+        .into_iter()
+        .map(|(_, s)| s)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            statements,
+            vec![
+                "RND V3, 7",
+                "LDK V3",
+                "LDD V3",
+                "SUB V3, 1",
+                "SBI V3, V1",
+                "SHR V3, V1",
+                "FNT V3",
+                "ADDI V3",
+                "DLY V3"
+            ]
+        );
+    }
+
+    #[test]
+    fn blocks_and_data() {
+        let statements = translate(vec![
+            ": draw loop while v0 key",
+            "if v1 == v2 begin draw else -1 end again",
+            ":const size 3 0xff",
+        ])
+        .unwrap()
+        .into_iter()
+        .map(|(_, s)| s)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            statements,
+            vec![
+                "draw:",
+                "loop",
+                "while V0 key",
+                "if V1 == V2 then",
+                "CALL draw",
+                "else",
+                "db 0 - 1",
+                "end",
+                "again",
+                "size = 3",
+                "db 0xff"
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            translate(vec![": main", "v0 := ", ""]),
+            Err(String::from("line 2: unexpected end of file"))
+        );
+        assert!(translate(vec!["if v0 < 3 then return"]).is_err());
+        assert!(translate(vec!["hires"]).is_err());
+        assert!(translate(vec!["i := 0b12"]).is_err());
+    }
+}
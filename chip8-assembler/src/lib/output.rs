@@ -0,0 +1,89 @@
+use std::io::{Result, Write};
+use std::str::FromStr;
+
+/// How the assembled ROM gets written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// The bytes as they are, ready for an interpreter to load.
+    #[default]
+    Raw,
+    /// Lowercase hex text, 16 bytes per line.
+    Hex,
+    /// Intel HEX records, addressed from the origin.
+    IntelHex,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Format::Raw),
+            "hex" => Ok(Format::Hex),
+            "ihex" => Ok(Format::IntelHex),
+            _ => Err(format!(
+                "unknown format {:?}, expected one of raw, hex, ihex",
+                s
+            )),
+        }
+    }
+}
+
+const BYTES_PER_LINE: usize = 16;
+
+pub fn write<W: Write>(out: &mut W, format: Format, origin: u16, rom: &[u8]) -> Result<()> {
+    match format {
+        Format::Raw => out.write_all(rom),
+        Format::Hex => {
+            for line in rom.chunks(BYTES_PER_LINE) {
+                for b in line {
+                    write!(out, "{:02x}", b)?;
+                }
+                writeln!(out)?;
+            }
+            Ok(())
+        }
+        Format::IntelHex => {
+            for (i, line) in rom.chunks(BYTES_PER_LINE).enumerate() {
+                let address = origin.wrapping_add((i * BYTES_PER_LINE) as u16);
+                writeln!(out, "{}", ihex_record(address, 0x00, line))?;
+            }
+            writeln!(out, "{}", ihex_record(0, 0x01, &[]))
+        }
+    }
+}
+
+fn ihex_record(address: u16, kind: u8, data: &[u8]) -> String {
+    let mut record = format!(":{:02X}{:04X}{:02X}", data.len(), address, kind);
+    let mut sum = (data.len() as u8)
+        .wrapping_add((address >> 8) as u8)
+        .wrapping_add(address as u8)
+        .wrapping_add(kind);
+    for b in data {
+        record.push_str(&format!("{:02X}", b));
+        sum = sum.wrapping_add(*b);
+    }
+    record.push_str(&format!("{:02X}", sum.wrapping_neg()));
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ihex() {
+        let mut out = Vec::new();
+        write(&mut out, Format::IntelHex, 0x200, &[0x12, 0x04, 0x00, 0xe0]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ":04020000120400E004\n:00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn hex() {
+        let mut out = Vec::new();
+        write(&mut out, Format::Hex, 0x200, &[0x12, 0x04, 0x00, 0xe0]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "120400e0\n");
+    }
+}
@@ -1,5 +1,7 @@
+use crate::symbols::Location;
 use std::collections::HashMap;
-pub fn pexpr<T>(input: &str) -> Option<Value<T>> {
+use std::ops::Range;
+pub fn pexpr<T>(input: &str) -> Option<Value<'_, T>> {
     let mut end_offt = input.len();
     if input.is_empty() {
         return None;
@@ -9,6 +11,49 @@ pub fn pexpr<T>(input: &str) -> Option<Value<T>> {
     }
     Some(Value::Partial(&input[..end_offt]))
 }
+/// A double quoted string, where `\"` and `\\` escape
+/// the quote and the backslash.
+pub fn pstring(input: &str) -> Option<(&str, String)> {
+    let body = input.strip_prefix('"')?;
+    let mut value = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((&body[i + 1..], value)),
+            '\\' => value.push(chars.next()?.1),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// How long the quoted string at the start of `input` is,
+/// quotes included. Unterminated strings run until the end.
+pub fn string_len(input: &str) -> Option<usize> {
+    let body = input.strip_prefix('"')?;
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some(i + 2),
+            '\\' => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    Some(input.len())
+}
+
+/// `keyword` at the start of `input`, in any case, and what comes after it.
+pub fn pkeyword<'a>(input: &'a str, keyword: &str) -> Option<&'a str> {
+    let head = input.get(..keyword.len())?;
+    if head.eq_ignore_ascii_case(keyword) {
+        Some(&input[keyword.len()..])
+    } else {
+        None
+    }
+}
+
 pub fn whitespace1(input: &str) -> Option<&str> {
     let c = input.chars().next().filter(|c| c.is_whitespace())?;
     Some(whitespace(&input[c.len_utf8()..]))
@@ -23,6 +68,30 @@ pub enum Value<'a, T> {
     Partial(&'a str),
 }
 
+/// Every known symbol, plus `.` for the current address.
+pub type Table<'a> = HashMap<&'a str, Value<'a, u16>>;
+
+/// Statements, each with the line and column (starting at 1) it comes from.
+/// A line may turn into any number of them along the way.
+pub type Statements = Vec<(Location, String)>;
+
+/// Numbers the lines as statements of their own.
+pub fn numbered<'a, I: IntoIterator<Item = &'a str>>(lines: I) -> Statements {
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            (
+                Location {
+                    line: i + 1,
+                    column: 1,
+                },
+                line.to_string(),
+            )
+        })
+        .collect()
+}
+
 fn parse_hex(a: &str) -> Option<(&str, u16)> {
     let a = a.strip_prefix("0x").or_else(|| a.strip_prefix("0X"))?;
     // at least one hex character
@@ -144,8 +213,9 @@ pub fn parse_name(input: &str) -> Option<(&str, &str)> {
     if offset == 0 {
         return None;
     }
+    // dots can only show up inside a name, from scoped labels like `main.loop`.
     for c in input.chars().skip(offset) {
-        if c != '@' && c != '_' && !c.is_alphanumeric() {
+        if c != '@' && c != '_' && c != '.' && !c.is_alphanumeric() {
             break;
         }
         offset += c.len_utf8();
@@ -154,55 +224,98 @@ pub fn parse_name(input: &str) -> Option<(&str, &str)> {
     Some((&input[offset..], &input[..offset]))
 }
 
-pub fn parse_const<'a>(
-    input: &'a str,
-    table: &HashMap<&str, Value<u16>>,
-) -> Option<(&'a str, u16)> {
-    // if either theres no more input (last value) or there's a whitespace after the dot.
-    if let Some(input) = input
-        .strip_prefix(".")
-        .filter(|i| matches!(i.chars().next().filter(|c| c.is_whitespace()), Some(_)))
-    {
-        let current_address = table["."].consume(table)?;
-        return Some((input, current_address));
+/// Where `part`, a slice of `input` the parsers returned,
+/// as the names they find, sits in it.
+pub fn span(input: &str, part: &str) -> Range<usize> {
+    let start = part.as_ptr() as usize - input.as_ptr() as usize;
+    start..start + part.len()
+}
+
+/// A term of an expression.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Term<'a> {
+    /// `.`, the current address.
+    Here,
+    Name(&'a str),
+    Number(u16),
+}
+
+impl<'a> Term<'a> {
+    pub fn value(&self, table: &Table) -> Option<u16> {
+        match self {
+            Term::Here => table["."].consume(table),
+            Term::Name(name) => table.get(name)?.consume(table),
+            Term::Number(v) => Some(*v),
+        }
+    }
+}
+
+fn pterm(input: &str) -> Option<(&str, Term<'_>)> {
+    // if either theres no more input (last value) or the dot isn't part of a name.
+    if let Some(input) = input.strip_prefix(".").filter(|i| {
+        i.chars()
+            .next()
+            .filter(|c| c.is_alphanumeric() || matches!(c, '@' | '_' | '.'))
+            .is_none()
+    }) {
+        return Some((input, Term::Here));
     }
     if let Some((rest, name)) = parse_name(input) {
-        let v = table.get(name)?.consume(table)?;
-        Some((rest, v))
+        Some((rest, Term::Name(name)))
     } else {
-        parse_num(input)
+        parse_num(input).map(|(rest, v)| (rest, Term::Number(v)))
+    }
+}
+
+/// The terms of an expression, each telling whether it's subtracted.
+pub fn pterms(input: &str) -> Option<Vec<(bool, Term<'_>)>> {
+    // first term
+    let (mut input, first) = pterm(input).map(|(a, b)| (whitespace(a), b))?;
+    let mut terms = vec![(false, first)];
+    while let Some(c) = input.chars().next().filter(|c| c == &'-' || c == &'+') {
+        input = whitespace(&input[1..]);
+        let (rest, term) = pterm(input).map(|(a, b)| (whitespace(a), b))?;
+        terms.push((c == '-', term));
+        input = rest;
     }
+    Some(terms)
 }
+
+pub fn parse_const<'a>(input: &'a str, table: &Table) -> Option<(&'a str, u16)> {
+    let (rest, term) = pterm(input)?;
+    Some((rest, term.value(table)?))
+}
+
+impl<'a, T> Value<'a, T> {
+    /// The labels and constants the expression looks up.
+    pub fn names(&self) -> Vec<&'a str> {
+        match self {
+            Value::Complete(_) => Vec::new(),
+            Value::Partial(input) => pterms(input)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|(_, term)| match term {
+                    Term::Name(name) => Some(name),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
 impl<'a> Value<'a, u16> {
-    pub fn consume(&self, table: &HashMap<&str, Value<u16>>) -> Option<u16> {
+    pub fn consume(&self, table: &Table) -> Option<u16> {
         match self {
             Value::Complete(t) => Some(*t),
             Value::Partial(input) => {
-                // first term
-                let (mut input, mut value) =
-                    parse_const(input, table).map(|(a, b)| (whitespace(a), b))?;
-                loop {
-                    // this is actually overwritten in this statement.
-                    #[allow(unused_assignments)]
-                    let mut do_negate = false;
-                    if let Some(c) = input.chars().next().filter(|c| c == &'-' || c == &'+') {
-                        do_negate = c == '-';
+                let mut value: u16 = 0;
+                for (subtracted, term) in pterms(input)? {
+                    let term = term.value(table)?;
+                    if subtracted {
+                        value = value.wrapping_sub(term);
                     } else {
-                        break;
-                    }
-
-                    input = whitespace(&input[1..]);
-
-                    let (rest, mut next_term) =
-                        parse_const(input, table).map(|(a, b)| (whitespace(a), b))?;
-
-                    if do_negate {
-                        // safety: an add to a value with a 1 in the first
-                        // bit will signify a substraction.
-                        next_term = !next_term + 1;
+                        value = value.wrapping_add(term);
                     }
-                    input = rest;
-                    value = value.wrapping_add(next_term);
                 }
                 Some(value)
             }
@@ -211,7 +324,7 @@ impl<'a> Value<'a, u16> {
 }
 
 impl<'a> Value<'a, u8> {
-    pub fn consume(&self, table: &HashMap<&str, Value<u16>>) -> Option<u8> {
+    pub fn consume(&self, table: &Table) -> Option<u8> {
         match self {
             Value::Complete(t) => Some(*t),
             Value::Partial(v) => Value::<u16>::Partial(v).consume(table).map(|x| x as u8),
@@ -250,6 +363,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn strings() {
+        assert_eq!(
+            pstring(r#""SCORE \"1\"", 3"#),
+            Some((", 3", String::from("SCORE \"1\"")))
+        );
+        assert_eq!(pstring(r#""open"#), None);
+        assert_eq!(string_len(r#""a;\"b" ; c"#), Some(7));
+    }
+
+    #[test]
+    fn spans() {
+        let line = "loop: JP main.loop + 0x10";
+        let (_, name) = parse_name(&line[9..]).unwrap();
+        assert_eq!(span(line, name), 9..18);
+    }
+
     #[test]
     fn constants() {
         assert_eq!(parse_hex("0xf0"), Some(("", 0xf0)));
@@ -269,5 +399,9 @@ mod tests {
             pexpr::<u16>(". + 10 - 3 + hey").and_then(|x| x.consume(&map)),
             Some(42u16)
         );
+        assert_eq!(
+            pexpr::<u16>("end - main.loop + . - 1").map(|x| x.names()),
+            Some(vec!["end", "main.loop"])
+        );
     }
 }
@@ -0,0 +1,226 @@
+//! Pseudo instructions, which are rewritten into one or more real ones
+//! before the layout, so they take as many bytes as what they turn into:
+//!
+//! - `SUB VX, NN` is `ADD VX, -NN`.
+//! - `NOT VX` flips every bit, `NEG VX` negates it. Both use VF.
+//! - `LD VX, [I]` and `LD [I], VX` are `LDR VX` and `DMP VX`.
+//! - `I`, `DT` and `ST` can be loaded like registers, as in `LD I, label`,
+//!   `LD DT, VX`, `LD VX, DT`, `LD ST, VX` and `ADD I, VX`.
+//! - `SWAP VX, VY` swaps the registers through VF.
+//! - `LDI label + VX` is `LDI label` and `ADDI VX`.
+use crate::instructions::preg;
+use crate::parse_utils::*;
+
+/// What a statement turns into, `None` if it isn't a pseudo instruction.
+type Expansion = Option<Result<Vec<String>, String>>;
+
+fn operand<'a>(input: &'a str, mnemonic: &str) -> Option<&'a str> {
+    whitespace1(pkeyword(input, mnemonic)?)
+}
+
+/// A register on its own, not followed by anything else.
+fn register(input: &str) -> Option<u8> {
+    let input = input.trim();
+    if input.len() != 2 {
+        return None;
+    }
+    preg(input)
+}
+
+fn two_registers(input: &str) -> Option<(u8, u8)> {
+    let (a, b) = input.split_once(',')?;
+    Some((register(a)?, register(b)?))
+}
+
+/// Anything but VF, which the expansions use as scratch.
+fn scratch_free(vx: u8) -> Result<u8, String> {
+    if vx == 0xf {
+        Err(String::from(
+            "VF is used as scratch, it can't be an operand",
+        ))
+    } else {
+        Ok(vx)
+    }
+}
+
+/// `-(expr)`, without parentheses: the evaluator goes left to right
+/// over `+` and `-` only, so flipping every operator does it.
+fn negate(expr: &str) -> String {
+    let flipped = expr
+        .chars()
+        .map(|c| match c {
+            '+' => '-',
+            '-' => '+',
+            c => c,
+        })
+        .collect::<String>();
+    format!("0 - {}", flipped)
+}
+
+fn sub(input: &str) -> Expansion {
+    let (vx, nn) = operand(input, "SUB")?.split_once(',')?;
+    let vx = register(vx)?;
+    let nn = nn.trim();
+    // the real SUB.
+    if preg(nn).is_some() || nn.is_empty() {
+        return None;
+    }
+    Some(Ok(vec![format!("ADD V{:X}, {}", vx, negate(nn))]))
+}
+
+fn not(input: &str) -> Expansion {
+    let vx = register(operand(input, "NOT")?)?;
+    Some(scratch_free(vx).map(|vx| vec![String::from("LD VF, 0xff"), format!("XOR V{:X}, VF", vx)]))
+}
+
+fn neg(input: &str) -> Expansion {
+    let vx = register(operand(input, "NEG")?)?;
+    // VX = VF - VX, with VF = 0.
+    Some(scratch_free(vx).map(|vx| vec![String::from("LD VF, 0"), format!("SBI V{:X}, VF", vx)]))
+}
+
+fn load_named(input: &str) -> Expansion {
+    let (a, b) = operand(input, "LD")?.split_once(',')?;
+    let (a, b) = (a.trim(), b.trim());
+    let named = match (
+        a.to_ascii_uppercase().as_str(),
+        b.to_ascii_uppercase().as_str(),
+    ) {
+        ("[I]", _) => format!("DMP V{:X}", register(b)?),
+        (_, "[I]") => format!("LDR V{:X}", register(a)?),
+        // `LD I, label + VX` is the offset one.
+        ("I", _) => {
+            let load = format!("LDI {}", b);
+            return load_i_offset(&load).or(Some(Ok(vec![load])));
+        }
+        ("DT", _) => format!("DLY V{:X}", register(b)?),
+        ("ST", _) => format!("SND V{:X}", register(b)?),
+        (_, "DT") => format!("LDD V{:X}", register(a)?),
+        _ => return None,
+    };
+    Some(Ok(vec![named]))
+}
+
+fn add_i(input: &str) -> Expansion {
+    let (i, vx) = operand(input, "ADD")?.split_once(',')?;
+    if !i.trim().eq_ignore_ascii_case("I") {
+        return None;
+    }
+    Some(Ok(vec![format!("ADDI V{:X}", register(vx)?)]))
+}
+
+fn swap(input: &str) -> Expansion {
+    let (vx, vy) = two_registers(operand(input, "SWAP")?)?;
+    Some(scratch_free(vx).and_then(|vx| {
+        let vy = scratch_free(vy)?;
+        Ok(vec![
+            format!("LD VF, V{:X}", vx),
+            format!("LD V{:X}, V{:X}", vx, vy),
+            format!("LD V{:X}, VF", vy),
+        ])
+    }))
+}
+
+fn load_i_offset(input: &str) -> Expansion {
+    let expr = operand(input, "LDI")?;
+    let (address, vx) = expr.rsplit_once('+')?;
+    let vx = register(vx)?;
+    Some(Ok(vec![
+        format!("LDI {}", address.trim()),
+        format!("ADDI V{:X}", vx),
+    ]))
+}
+
+/// The real instructions a statement turns into.
+pub fn any(input: &str) -> Expansion {
+    const PARSERS: &[fn(&str) -> Expansion] =
+        &[sub, not, neg, load_named, add_i, swap, load_i_offset];
+
+    PARSERS.iter().find_map(|p| p(input))
+}
+
+/// Expands the pseudo instructions in the statements,
+/// keeping the line each one comes from.
+pub fn expand(statements: Statements) -> Result<Statements, String> {
+    let mut out = Vec::with_capacity(statements.len());
+    for (at, statement) in statements {
+        // this goes before the real instructions, as things like `LDI label + V1`
+        // would parse as a `LDI` with an expression that can't be computed.
+        match any(&statement) {
+            Some(expanded) => {
+                let expanded = expanded.map_err(|e| format!("line {}: {}", at.line, e))?;
+                out.extend(expanded.into_iter().map(|s| (at, s)));
+            }
+            None => out.push((at, statement)),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sub() {
+        assert_eq!(
+            any("SUB V3, 5 + size - 1"),
+            Some(Ok(vec![String::from("ADD V3, 0 - 5 - size + 1")]))
+        );
+        assert_eq!(any("SUB V3, V4"), None);
+    }
+
+    #[test]
+    fn not_neg() {
+        assert_eq!(
+            any("NOT VA"),
+            Some(Ok(vec![
+                String::from("LD VF, 0xff"),
+                String::from("XOR VA, VF")
+            ]))
+        );
+        assert_eq!(
+            any("NEG V1"),
+            Some(Ok(vec![
+                String::from("LD VF, 0"),
+                String::from("SBI V1, VF")
+            ]))
+        );
+        assert!(matches!(any("NEG VF"), Some(Err(_))));
+    }
+
+    #[test]
+    fn loads() {
+        assert_eq!(any("LD [I], V5"), Some(Ok(vec![String::from("DMP V5")])));
+        assert_eq!(any("LD V5, [I]"), Some(Ok(vec![String::from("LDR V5")])));
+        assert_eq!(
+            any("ld i, sprite"),
+            Some(Ok(vec![String::from("LDI sprite")]))
+        );
+        assert_eq!(any("LD DT, v1"), Some(Ok(vec![String::from("DLY V1")])));
+        assert_eq!(any("LD V1, DT"), Some(Ok(vec![String::from("LDD V1")])));
+        assert_eq!(any("LD ST, V1"), Some(Ok(vec![String::from("SND V1")])));
+        assert_eq!(any("ADD I, V2"), Some(Ok(vec![String::from("ADDI V2")])));
+        assert_eq!(any("LD V1, V2"), None);
+        assert_eq!(
+            any("LDI table + 2 + V1"),
+            Some(Ok(vec![
+                String::from("LDI table + 2"),
+                String::from("ADDI V1")
+            ]))
+        );
+        assert_eq!(any("LDI table + 2"), None);
+    }
+
+    #[test]
+    fn swap() {
+        assert_eq!(
+            any("SWAP V1, V2"),
+            Some(Ok(vec![
+                String::from("LD VF, V1"),
+                String::from("LD V1, V2"),
+                String::from("LD V2, VF")
+            ]))
+        );
+    }
+}
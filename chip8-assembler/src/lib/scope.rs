@@ -0,0 +1,222 @@
+//! Rewrites scoped and anonymous labels into plain global names,
+//! so the rest of the assembler only has to deal with one flat table.
+//!
+//! - `.name` refers to `name` inside the last global label,
+//!   so `.loop` after `main:` becomes `main.loop`.
+//! - `-:` and `+:` define anonymous labels. A `-` operand refers to the
+//!   closest `-:` above it, `--` to the one before that and so on.
+//!   `+`, `++`... do the same with the `+:` labels below.
+use crate::parse_utils::*;
+
+fn is_boundary(c: char) -> bool {
+    c.is_whitespace() || c == ',' || c == '+' || c == '-'
+}
+
+fn backward_label(n: usize) -> String {
+    format!("@anonb{}", n)
+}
+
+fn forward_label(n: usize) -> String {
+    format!("@anonf{}", n)
+}
+
+/// Whether what comes next is the first term of an operand,
+/// that is, right after the mnemonic or after a comma.
+/// This is what tells `JP -` apart from `10 - 3`.
+fn at_operand_start(before: &str) -> bool {
+    let trimmed = before.trim_end();
+    if trimmed.ends_with(',') {
+        return true;
+    }
+    !trimmed.is_empty()
+        && trimmed.len() < before.len()
+        && !trimmed.contains(|c: char| c.is_whitespace() || c == ',')
+}
+
+struct Scope {
+    global: Option<String>,
+    backward: usize,
+    forward: usize,
+}
+
+impl Scope {
+    fn local(&self, name: &str) -> Result<String, String> {
+        match self.global.as_ref() {
+            Some(global) => Ok(format!("{}.{}", global, name)),
+            None => Err(format!(
+                "local label .{} used before any global label",
+                name
+            )),
+        }
+    }
+
+    fn anonymous(&self, c: char, n: usize) -> Result<String, String> {
+        if c == '+' {
+            Ok(forward_label(self.forward + n - 1))
+        } else if n <= self.backward {
+            Ok(backward_label(self.backward - n))
+        } else {
+            Err(format!(
+                "there is no anonymous label {} above",
+                "-".repeat(n)
+            ))
+        }
+    }
+
+    /// Rewrites the references in the operands of a line.
+    fn operands(&self, line: &str) -> Result<String, String> {
+        let mut out = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(c) = rest.chars().next() {
+            if let Some(n) = string_len(rest) {
+                out.push_str(&rest[..n]);
+                rest = &rest[n..];
+                continue;
+            }
+            if c == '.' && out.chars().last().map(is_boundary).unwrap_or(true) {
+                if let Some((next, name)) = parse_name(&rest[1..]) {
+                    out.push_str(&self.local(name)?);
+                    rest = next;
+                    continue;
+                }
+            }
+            if (c == '-' || c == '+') && at_operand_start(&out) {
+                let n = rest.find(|x| x != c).unwrap_or(rest.len());
+                let after = rest[n..].chars().next();
+                if after.map(|x| x.is_whitespace() || x == ',').unwrap_or(true) {
+                    out.push_str(&self.anonymous(c, n)?);
+                    rest = &rest[n..];
+                    continue;
+                }
+            }
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        Ok(out)
+    }
+
+    fn line(&mut self, line: &str) -> Result<String, String> {
+        match line.strip_suffix(':').unwrap_or(line) {
+            "-" => {
+                self.backward += 1;
+                return Ok(format!("{}:", backward_label(self.backward - 1)));
+            }
+            "+" => {
+                self.forward += 1;
+                return Ok(format!("{}:", forward_label(self.forward - 1)));
+            }
+            _ => {}
+        }
+
+        if let Some(input) = line.strip_prefix('.') {
+            if let Some((rest, name)) = parse_name(input) {
+                let after = whitespace(rest);
+                if after.starts_with(':') || after.starts_with('=') {
+                    // local label or constant definition
+                    let qualified = self.local(name)?;
+                    return Ok(format!("{}{}", qualified, self.operands(rest)?));
+                }
+                // a directive, leave its name alone.
+                return Ok(format!(".{}{}", name, self.operands(rest)?));
+            }
+        }
+
+        if let Some(name) = crate::misc::label(line) {
+            self.global = Some(name.to_string());
+            return Ok(line.to_string());
+        }
+
+        self.operands(line)
+    }
+}
+
+/// Rewrites every statement (already stripped of comments)
+/// so that it only contains global names.
+/// Errors carry the line number they happened at.
+pub fn qualify(statements: Statements) -> Result<Statements, String> {
+    let mut scope = Scope {
+        global: None,
+        backward: 0,
+        forward: 0,
+    };
+    statements
+        .into_iter()
+        .map(|(at, statement)| {
+            let statement = scope
+                .line(&statement)
+                .map_err(|e| format!("line {}: {}", at.line, e))?;
+            Ok((at, statement))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qualify(lines: Vec<&str>) -> Result<Vec<String>, String> {
+        let out = super::qualify(numbered(lines))?;
+        Ok(out.into_iter().map(|(_, s)| s).collect())
+    }
+
+    #[test]
+    fn locals() {
+        assert_eq!(
+            qualify(vec![
+                "main:", ".loop:", "JP .loop", "other:", ".loop:", "JP .loop"
+            ]),
+            Ok(vec![
+                "main:".to_string(),
+                "main.loop:".to_string(),
+                "JP main.loop".to_string(),
+                "other:".to_string(),
+                "other.loop:".to_string(),
+                "JP other.loop".to_string(),
+            ])
+        );
+        assert!(qualify(vec![".loop:"]).is_err());
+    }
+
+    #[test]
+    fn keeps_directives_and_address() {
+        assert_eq!(
+            qualify(vec!["main:", ".repeat 0, .size", "x = . + 2", ".size = 3"]),
+            Ok(vec![
+                "main:".to_string(),
+                ".repeat 0, main.size".to_string(),
+                "x = . + 2".to_string(),
+                "main.size = 3".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            qualify(vec!["main:", r#".text ", - .x", .x"#]),
+            Ok(vec![
+                "main:".to_string(),
+                r#".text ", - .x", main.x"#.to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn anonymous() {
+        assert_eq!(
+            qualify(vec!["-:", "JP +", "JP -", "+:", "LD V1, 32 - 8", "JP --"]),
+            Err("line 6: there is no anonymous label -- above".to_string())
+        );
+        assert_eq!(
+            qualify(vec!["-:", "JP +", "-", "JP0 --", "+:", "LD V1, 32 - 8"]),
+            Ok(vec![
+                "@anonb0:".to_string(),
+                "JP @anonf0".to_string(),
+                "@anonb1:".to_string(),
+                "JP0 @anonb0".to_string(),
+                "@anonf0:".to_string(),
+                "LD V1, 32 - 8".to_string(),
+            ])
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Expands `.sprite` blocks, where every row is drawn
+//! with `#`/`X` for pixels that are on and `.`/`_` for the ones that are off:
+//!
+//! ```text
+//! .sprite heart
+//!   .#.#.
+//!   #####
+//!   .###.
+//!   ..#..
+//! .end
+//! ```
+//!
+//! The block is rewritten line by line, so errors keep their line numbers:
+//! the `.sprite` line becomes the label, each row a `db` and `.end`
+//! defines the `heart_height` constant.
+//! Rows up to 8 pixels wide make normal sprites, rows up to 16 make
+//! the 16x16 SCHIP ones.
+//!
+//! `.glyph "A"` blocks work the same, but instead of a label they
+//! map the character to the sprite in the charmap, so a run of them
+//! makes a font for `.textw`.
+use crate::parse_utils::*;
+use crate::target::Target;
+
+struct Block {
+    line: usize,
+    name: Option<String>,
+    width: usize,
+    /// index in the output of every row, with its pixels.
+    rows: Vec<(usize, u16)>,
+}
+
+enum Start<'a> {
+    Sprite(Option<&'a str>),
+    Glyph(char),
+}
+
+fn start(input: &str) -> Option<Start<'_>> {
+    if let Some(input) = pkeyword(input, ".glyph") {
+        let (rest, glyph) = pstring(whitespace1(input)?)?;
+        let mut chars = glyph.chars();
+        let c = chars.next()?;
+        if chars.next().is_some() || !rest.is_empty() {
+            return None;
+        }
+        return Some(Start::Glyph(c));
+    }
+    let input = pkeyword(input, ".sprite")?;
+    if input.is_empty() {
+        return Some(Start::Sprite(None));
+    }
+    let input = whitespace1(input)?;
+    // local names keep their dot, the scopes get resolved later.
+    let (rest, _) = parse_name(input.strip_prefix('.').unwrap_or(input))?;
+    let name = &input[..input.len() - rest.len()];
+    if !rest.is_empty() {
+        return None;
+    }
+    Some(Start::Sprite(Some(name)))
+}
+
+fn row(input: &str) -> Result<u16, String> {
+    let mut bits = 0;
+    for (i, c) in input.chars().enumerate() {
+        let on = match c {
+            '#' | 'X' | 'x' => true,
+            '.' | '_' => false,
+            _ => return Err(format!("{:?} isn't a pixel, use #/X or ./_", c)),
+        };
+        if i >= 16 {
+            return Err(String::from("Sprites are at most 16 pixels wide"));
+        }
+        if on {
+            bits |= 0x8000 >> i;
+        }
+    }
+    Ok(bits)
+}
+
+impl Block {
+    fn end(&self, target: Target, out: &mut Statements) -> Result<String, String> {
+        let height = self.rows.len();
+        if height == 0 {
+            return Err(String::from("Empty sprite"));
+        }
+        if self.width > 8 {
+            if target == Target::Chip8 {
+                return Err(String::from(
+                    "16 pixel wide sprites need SCHIP, use --target schip",
+                ));
+            }
+            if height != 16 {
+                return Err(format!(
+                    "16 pixel wide sprites are 16 rows high, not {}",
+                    height
+                ));
+            }
+        } else if height > 15 {
+            return Err(format!(
+                "{} rows don't fit in DRW's height, 15 at most",
+                height
+            ));
+        }
+        for (i, bits) in self.rows.iter() {
+            out[*i].1 = if self.width > 8 {
+                format!("db {:#04x}, {:#04x}", bits >> 8, bits & 0xff)
+            } else {
+                format!("db {:#04x}", bits >> 8)
+            };
+        }
+        // 16x16 sprites are drawn with a height of 0.
+        let height = if self.width > 8 { 0 } else { height };
+        Ok(match self.name.as_ref() {
+            Some(name) => format!("{}_height = {}", name, height),
+            None => String::new(),
+        })
+    }
+}
+
+/// Rewrites the `.sprite` blocks in the statements (already stripped of comments)
+/// into plain labels, bytes and constants.
+pub fn expand(statements: Statements, target: Target) -> Result<Statements, String> {
+    let mut out = Vec::with_capacity(statements.len());
+    let mut block: Option<Block> = None;
+    for (location, line) in statements {
+        let line = line.as_str();
+        let at = |e: String| format!("line {}: {}", location.line, e);
+        match block.as_mut() {
+            None => {
+                let name = match start(line) {
+                    Some(Start::Sprite(name)) => {
+                        out.push((
+                            location,
+                            name.map(|n| format!("{}:", n)).unwrap_or_default(),
+                        ));
+                        name.map(String::from)
+                    }
+                    Some(Start::Glyph(c)) => {
+                        let escaped = if c == '"' || c == '\\' {
+                            format!("\\{}", c)
+                        } else {
+                            c.to_string()
+                        };
+                        out.push((location, format!(".charmap \"{}\", .", escaped)));
+                        None
+                    }
+                    None if pkeyword(line, ".sprite").is_some() => {
+                        return Err(at(String::from("Expected '.sprite' or '.sprite <name>'")))
+                    }
+                    None if pkeyword(line, ".glyph").is_some() => {
+                        return Err(at(String::from(
+                            "Expected '.glyph' and a string with one character",
+                        )))
+                    }
+                    None => {
+                        out.push((location, line.to_string()));
+                        continue;
+                    }
+                };
+                block = Some(Block {
+                    line: location.line,
+                    name,
+                    width: 0,
+                    rows: Vec::new(),
+                });
+            }
+            Some(b) if line.eq_ignore_ascii_case(".end") => {
+                let height = b.end(target, &mut out).map_err(at)?;
+                out.push((location, height));
+                block = None;
+            }
+            Some(_) if line.is_empty() => out.push((location, String::new())),
+            Some(b) => {
+                let bits = row(line).map_err(at)?;
+                b.width = b.width.max(line.chars().count());
+                b.rows.push((out.len(), bits));
+                // filled in by `.end`, once the width is known.
+                out.push((location, String::new()));
+            }
+        }
+    }
+    if let Some(b) = block {
+        return Err(format!(
+            "line {}: sprite is never closed with '.end'",
+            b.line
+        ));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprites(lines: Vec<&str>, target: Target) -> Result<Vec<String>, String> {
+        let out = expand(numbered(lines), target)?;
+        Ok(out.into_iter().map(|(_, s)| s).collect())
+    }
+
+    #[test]
+    fn small() {
+        assert_eq!(
+            sprites(
+                vec![".sprite heart", ".#.#.", "#####", "", "..X..", ".end"],
+                Target::Chip8
+            ),
+            Ok(vec![
+                "heart:".to_string(),
+                "db 0x50".to_string(),
+                "db 0xf8".to_string(),
+                "".to_string(),
+                "db 0x20".to_string(),
+                "heart_height = 3".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn big() {
+        let mut lines = vec![".sprite"];
+        lines.extend(std::iter::repeat_n("#______________#", 16));
+        lines.push(".end");
+        assert!(sprites(lines.clone(), Target::Chip8).is_err());
+        let out = sprites(lines, Target::SuperChip).unwrap();
+        assert_eq!(out[1], "db 0x80, 0x01");
+        assert_eq!(out[17], "");
+    }
+
+    #[test]
+    fn glyphs() {
+        assert_eq!(
+            sprites(
+                vec![
+                    r#".glyph "\"""#,
+                    "#.#",
+                    ".end",
+                    r#".glyph "I""#,
+                    "#",
+                    ".end"
+                ],
+                Target::Chip8
+            ),
+            Ok(vec![
+                r#".charmap "\"", ."#.to_string(),
+                "db 0xa0".to_string(),
+                "".to_string(),
+                r#".charmap "I", ."#.to_string(),
+                "db 0x80".to_string(),
+                "".to_string(),
+            ])
+        );
+        assert!(sprites(vec![r#".glyph "AB""#, "#", ".end"], Target::Chip8).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(sprites(vec![".sprite a", "#", "#"], Target::Chip8).is_err());
+        assert!(sprites(vec![".sprite a", "#o#", ".end"], Target::Chip8).is_err());
+        assert!(sprites(vec![".sprite a", ".end"], Target::Chip8).is_err());
+        let tall = std::iter::once(".sprite a")
+            .chain(std::iter::repeat_n("#", 16))
+            .chain(std::iter::once(".end"))
+            .collect::<Vec<_>>();
+        assert!(sprites(tall, Target::Chip8).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Where every part of a program came from in its source, with its labels
+//! and constants, as `--symbols` writes it for debuggers:
+//!
+//! ```text
+//! # chip8 symbols
+//! source /home/me/game.asm
+//! label 202 main
+//! constant 5 SPEED
+//! code 202 4 3
+//! code 204 5 3
+//! data 206 2 8 1
+//! ```
+//!
+//! Instructions are `code ADDRESS LINE COLUMN`, everything else the
+//! directives emit `data ADDRESS LENGTH LINE COLUMN`, in hexadecimal
+//! for addresses and values and from 1 for lines and columns.
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+const HEADER: &str = "# chip8 symbols";
+
+/// A place in the source, both from 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    /// The file that was assembled, if it was one.
+    pub source: Option<String>,
+    /// Labels and their addresses, in the order they're defined.
+    pub labels: Vec<(String, u16)>,
+    /// Constants and their values, in the order they're defined.
+    pub constants: Vec<(String, u16)>,
+    /// Where every instruction came from, by address.
+    pub code: BTreeMap<u16, Location>,
+    /// Where the data came from, by address, with its length.
+    pub data: BTreeMap<u16, (u16, Location)>,
+}
+
+impl Symbols {
+    /// Where the byte at `address` came from, be it code or data.
+    pub fn location(&self, address: u16) -> Option<Location> {
+        let code = self
+            .code
+            .range(..=address)
+            .next_back()
+            .filter(|(start, _)| address - **start < 2)
+            .map(|(_, location)| *location);
+        let data = || {
+            self.data
+                .range(..=address)
+                .next_back()
+                .filter(|(start, (len, _))| address - **start < *len)
+                .map(|(_, (_, location))| *location)
+        };
+        code.or_else(data)
+    }
+
+    /// The line of the instruction at `address`.
+    pub fn line(&self, address: u16) -> Option<usize> {
+        self.code.get(&address).map(|location| location.line)
+    }
+
+    /// The first instruction on `line`, or on the next line with one,
+    /// as `(line, address)`.
+    pub fn address(&self, line: usize) -> Option<(usize, u16)> {
+        self.code
+            .iter()
+            .filter(|(_, l)| l.line >= line)
+            .min_by_key(|(address, l)| (l.line, **address))
+            .map(|(address, l)| (l.line, *address))
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(_, a)| *a == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The file name and line `address` came from, as in
+    /// `draw_numbers.asm:12`.
+    pub fn position(&self, address: u16) -> Option<String> {
+        let location = self.location(address)?;
+        let file = self
+            .source
+            .as_ref()
+            .and_then(|source| Path::new(source).file_name())
+            .map(|name| name.to_string_lossy().into_owned());
+        Some(match file {
+            Some(file) => format!("{}:{}", file, location.line),
+            None => format!("line {}", location.line),
+        })
+    }
+
+    /// Reads a file written by `--symbols`.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut symbols = Self::default();
+        for (n, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let hex = |field: &str| u16::from_str_radix(field, 16).ok();
+            let number = |field: &str| field.parse::<usize>().ok();
+            let known = match fields[..] {
+                ["source", ..] => {
+                    symbols.source = Some(line["source".len()..].trim().to_string());
+                    true
+                }
+                ["label", a, name] => hex(a)
+                    .map(|a| symbols.labels.push((name.to_string(), a)))
+                    .is_some(),
+                ["constant", v, name] => hex(v)
+                    .map(|v| symbols.constants.push((name.to_string(), v)))
+                    .is_some(),
+                ["code", a, l, c] => match (hex(a), number(l), number(c)) {
+                    (Some(a), Some(line), Some(column)) => {
+                        symbols.code.insert(a, Location { line, column });
+                        true
+                    }
+                    _ => false,
+                },
+                ["data", a, len, l, c] => match (hex(a), hex(len), number(l), number(c)) {
+                    (Some(a), Some(len), Some(line), Some(column)) => {
+                        symbols.data.insert(a, (len, Location { line, column }));
+                        true
+                    }
+                    _ => false,
+                },
+                _ => false,
+            };
+            if !known {
+                return Err(format!("line {}: unknown symbol {:?}", n + 1, line));
+            }
+        }
+        Ok(symbols)
+    }
+}
+
+impl Display for Symbols {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        if let Some(source) = &self.source {
+            writeln!(f, "source {}", source)?;
+        }
+        for (name, address) in self.labels.iter() {
+            writeln!(f, "label {:x} {}", address, name)?;
+        }
+        for (name, value) in self.constants.iter() {
+            writeln!(f, "constant {:x} {}", value, name)?;
+        }
+        for (address, l) in self.code.iter() {
+            writeln!(f, "code {:x} {} {}", address, l.line, l.column)?;
+        }
+        for (address, (len, l)) in self.data.iter() {
+            writeln!(f, "data {:x} {:x} {} {}", address, len, l.line, l.column)?;
+        }
+        Ok(())
+    }
+}
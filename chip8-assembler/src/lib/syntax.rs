@@ -0,0 +1,67 @@
+use crate::parse_utils::Statements;
+use crate::{cowgod, octo};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The dialect the source is written in.
+/// Everything gets translated to the native one first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    #[default]
+    Native,
+    /// The mnemonics from Cowgod's technical reference, `LD I, addr` and so on.
+    Cowgod,
+    /// Octo's `v0 := 5`, `i := label`...
+    Octo,
+}
+
+impl Syntax {
+    /// The syntax a file is written in by its extension, `None` if it isn't a source.
+    pub fn of_file(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "asm" | "s" => Some(Syntax::Native),
+            "8o" => Some(Syntax::Octo),
+            _ => None,
+        }
+    }
+
+    /// What starts a comment.
+    pub fn comment(self) -> char {
+        match self {
+            Syntax::Native | Syntax::Cowgod => ';',
+            Syntax::Octo => '#',
+        }
+    }
+
+    /// The label the ROM starts at, unless told otherwise.
+    pub fn entrypoint(self) -> &'static str {
+        match self {
+            Syntax::Native | Syntax::Cowgod => "_start",
+            Syntax::Octo => "main",
+        }
+    }
+
+    /// Translates the statements, stripped of comments, into native ones.
+    pub fn translate(self, statements: Statements) -> Result<Statements, String> {
+        match self {
+            Syntax::Native => Ok(statements),
+            Syntax::Cowgod => Ok(cowgod::translate(statements)),
+            Syntax::Octo => octo::translate(statements),
+        }
+    }
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" => Ok(Syntax::Native),
+            "cowgod" => Ok(Syntax::Cowgod),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!(
+                "unknown syntax {:?}, expected one of native, cowgod, octo",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::str::FromStr;
+
+/// The machine the ROM is being assembled for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Target {
+    /// The address right after the last byte a program can use.
+    pub fn memory_end(self) -> u32 {
+        match self {
+            // the last 0x100 bytes are used as display memory.
+            Target::Chip8 => 0xf00,
+            Target::SuperChip => 0x1000,
+            Target::XoChip => 0x10000,
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chip8" => Ok(Target::Chip8),
+            "schip" => Ok(Target::SuperChip),
+            "xochip" => Ok(Target::XoChip),
+            _ => Err(format!(
+                "unknown target {:?}, expected one of chip8, schip, xochip",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::coverage::Coverage;
+use crate::graph;
+use crate::listing::{Colors, Json, Listing, Reassemblable};
+use crate::program::{disassemble, Options};
+use crate::pseudo;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "unknown color choice {:?}, expected auto, always or never",
+                s
+            )),
+        }
+    }
+}
+
+impl ColorChoice {
+    /// Auto colors only a terminal, and not when `NO_COLOR` is set.
+    pub fn colors(self) -> Colors {
+        Colors(match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    /// structured pseudo-C.
+    C,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "c" => Ok(OutputFormat::C),
+            _ => Err(format!("unknown format {:?}, expected text, json or c", s)),
+        }
+    }
+}
+
+/// Command line of the decompiler, shared by `chip8-decompiler`
+/// and `chip8 dis`.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "chip8 decompiler", about = "a CHIP8 instruction deassembler.")]
+pub struct Args {
+    #[structopt(parse(from_os_str))]
+    pub input: PathBuf,
+
+    /// Print source for chip8-assembler, which assembles back into the same ROM.
+    #[structopt(long = "reassemblable", conflicts_with = "format")]
+    pub reassemblable: bool,
+
+    /// Write the control-flow graph of every function to this file.
+    #[structopt(long = "cfg", parse(from_os_str))]
+    pub cfg: Option<PathBuf>,
+
+    /// Write the call graph to this file.
+    #[structopt(long = "calls", parse(from_os_str))]
+    pub calls: Option<PathBuf>,
+
+    /// Address known to be code, like the targets of a `JP0` that
+    /// can't be guessed. Can be given many times.
+    #[structopt(long = "code", number_of_values = 1, parse(try_from_str = parse_address))]
+    pub code: Vec<u16>,
+
+    /// When to color the listing: auto, always or never.
+    #[structopt(long = "color", default_value = "auto")]
+    pub color: ColorChoice,
+
+    /// Format of the listing: text, json or c, for structured pseudo-C.
+    #[structopt(long = "format", default_value = "text")]
+    pub format: OutputFormat,
+
+    /// Format of the graphs: dot or json.
+    #[structopt(long = "graph-format", default_value = "dot")]
+    pub graph_format: graph::Format,
+
+    /// Coverage map written by the interpreter's --coverage, to show
+    /// how many times every instruction ran in the listing.
+    #[structopt(long = "coverage", parse(from_os_str))]
+    pub coverage: Option<PathBuf>,
+}
+
+fn parse_address(input: &str) -> Result<u16, String> {
+    let parsed = match input.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => input.parse(),
+    };
+    parsed.map_err(|_| format!("expected an address, got {:?}", input))
+}
+
+impl Args {
+    /// Reads the ROM, writes the graphs asked for and prints the listing.
+    pub fn run(&self) -> Result<(), String> {
+        let buffer = fs::read(&self.input)
+            .map_err(|e| format!("Couldn't read {}: {}", self.input.display(), e))?;
+        let options = Options {
+            code: self.code.clone(),
+        };
+        let prog = disassemble(&buffer, options);
+        let coverage = match &self.coverage {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+                Some(Coverage::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?)
+            }
+            None => None,
+        };
+        if self.cfg.is_some() || self.calls.is_some() {
+            let graph = graph::Graph::new(&prog);
+            let outputs = [
+                (&self.cfg, graph.cfg(self.graph_format)),
+                (&self.calls, graph.calls(self.graph_format)),
+            ];
+            for (path, contents) in outputs.iter() {
+                if let Some(path) = path {
+                    fs::write(path, contents)
+                        .map_err(|e| format!("Couldn't write the graph: {}", e))?;
+                }
+            }
+        }
+        if self.reassemblable {
+            print!("{}", Reassemblable(&prog));
+        } else {
+            match self.format {
+                OutputFormat::Text => {
+                    let listing = Listing::new(&prog, self.color.colors());
+                    match &coverage {
+                        Some(coverage) => println!("{}", listing.coverage(coverage)),
+                        None => println!("{}", listing),
+                    }
+                }
+                OutputFormat::Json => print!("{}", Json(&prog)),
+                OutputFormat::C => print!("{}", pseudo::decompile(&prog)),
+            }
+        }
+        Ok(())
+    }
+}
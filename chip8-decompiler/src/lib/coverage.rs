@@ -0,0 +1,93 @@
+//! Which instructions ran and how many times, as the interpreter's
+//! `--coverage` writes it, to be overlaid on the listing.
+//!
+//! A line for every address that ran, in hexadecimal, and its count:
+//!
+//! ```text
+//! # chip8 coverage
+//! 200 1
+//! 202 1534
+//! ```
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+const HEADER: &str = "# chip8 coverage";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage(BTreeMap<u16, u32>);
+
+impl Coverage {
+    /// From a count for every address in memory.
+    pub fn from_counts(counts: &[u32]) -> Self {
+        Self(
+            counts
+                .iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .map(|(address, count)| (address as u16, *count))
+                .collect(),
+        )
+    }
+
+    pub fn count(&self, address: u16) -> u32 {
+        self.0.get(&address).copied().unwrap_or(0)
+    }
+
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut counts = BTreeMap::new();
+        for (n, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let address = fields.next().and_then(|a| u16::from_str_radix(a, 16).ok());
+            let count = fields.next().and_then(|c| c.parse().ok());
+            match (address, count, fields.next()) {
+                (Some(address), Some(count), None) => {
+                    counts.insert(address, count);
+                }
+                _ => {
+                    return Err(format!(
+                        "line {}: expected ADDRESS COUNT, got {:?}",
+                        n + 1,
+                        line
+                    ))
+                }
+            }
+        }
+        Ok(Self(counts))
+    }
+}
+
+impl Display for Coverage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for (address, count) in self.0.iter() {
+            writeln!(f, "{:x} {}", address, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut counts = vec![0; 0x1000];
+        counts[0x200] = 1;
+        counts[0x20a] = 1534;
+        let coverage = Coverage::from_counts(&counts);
+        let text = coverage.to_string();
+        assert_eq!(text, "# chip8 coverage\n200 1\n20a 1534\n");
+        assert_eq!(Coverage::parse(&text), Ok(coverage.clone()));
+        assert_eq!(coverage.count(0x20a), 1534);
+        assert_eq!(coverage.count(0x202), 0);
+
+        assert!(Coverage::parse("200").is_err());
+        assert!(Coverage::parse("zz 1").is_err());
+        assert!(Coverage::parse("200 1 2").is_err());
+    }
+}
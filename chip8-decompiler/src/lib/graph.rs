@@ -0,0 +1,274 @@
+//! Basic blocks, the control-flow graph of every function
+//! and the call graph between them, as Graphviz DOT or JSON.
+use crate::json;
+use crate::program::{successors, Program};
+use chip8_isa::Opcode;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Dot,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown graph format {:?}, expected dot or json",
+                s
+            )),
+        }
+    }
+}
+
+/// Instructions that run one after the other, only entered at the top.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block {
+    pub start: u16,
+    /// address after the last instruction.
+    pub end: u16,
+    pub successors: Vec<u16>,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub entry: u16,
+    pub name: String,
+    pub blocks: BTreeMap<u16, Block>,
+    pub calls: BTreeSet<u16>,
+}
+
+pub struct Graph<'p> {
+    program: &'p Program,
+    pub functions: BTreeMap<u16, Function>,
+}
+
+impl<'p> Graph<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        let decoded = |address: &u16| program.instructions.contains_key(address);
+
+        // a block starts at every label and wherever control doesn't just fall through.
+        let mut leaders = program.labels.keys().copied().collect::<BTreeSet<_>>();
+        for (address, i) in program.instructions.iter() {
+            let successors = successors(i, *address);
+            if successors != [address + 2] {
+                leaders.extend(successors);
+            }
+        }
+
+        let mut entries = BTreeSet::new();
+        entries.insert(program.entrypoint());
+        for i in program.instructions.values() {
+            if let Opcode::Call(target) = i {
+                entries.insert(*target);
+            }
+        }
+
+        let functions = entries
+            .into_iter()
+            .filter(decoded)
+            .map(|entry| {
+                let mut blocks = BTreeMap::new();
+                let mut calls = BTreeSet::new();
+                let mut queue = vec![entry];
+                while let Some(start) = queue.pop() {
+                    if blocks.contains_key(&start) {
+                        continue;
+                    }
+                    let mut address = start;
+                    let successors = loop {
+                        let i = program.instructions[&address];
+                        if let Opcode::Call(target) = i {
+                            calls.insert(target);
+                        }
+                        let successors = successors(&i, address);
+                        let next = address + 2;
+                        if successors != [next] || leaders.contains(&next) || !decoded(&next) {
+                            break successors;
+                        }
+                        address = next;
+                    };
+                    let successors = successors.into_iter().filter(decoded).collect::<Vec<_>>();
+                    queue.extend(successors.iter().copied());
+                    blocks.insert(
+                        start,
+                        Block {
+                            start,
+                            end: address + 2,
+                            successors,
+                        },
+                    );
+                }
+                let function = Function {
+                    entry,
+                    name: program.name(entry),
+                    blocks,
+                    calls,
+                };
+                (entry, function)
+            })
+            .collect();
+
+        Self { program, functions }
+    }
+
+    fn listing(&self, block: &Block) -> Vec<String> {
+        (block.start..block.end)
+            .step_by(2)
+            .map(|address| {
+                let i = self.program.instructions[&address];
+                format!("{:04X}: {}", address, self.program.native(&i))
+            })
+            .collect()
+    }
+
+    /// The control-flow graph of every function, a cluster each.
+    pub fn cfg(&self, format: Format) -> String {
+        let mut out = String::new();
+        match format {
+            Format::Dot => {
+                out.push_str("digraph cfg {\n  node [shape=box fontname=monospace];\n");
+                for f in self.functions.values() {
+                    let node = |start: u16| format!("\"{:x}_{:x}\"", f.entry, start);
+                    writeln!(out, "  subgraph \"cluster_{}\" {{", f.name).unwrap();
+                    writeln!(out, "    label=\"{}\";", f.name).unwrap();
+                    for block in f.blocks.values() {
+                        let label = self
+                            .listing(block)
+                            .into_iter()
+                            .map(|line| line + "\\l")
+                            .collect::<String>();
+                        writeln!(out, "    {} [label=\"{}\"];", node(block.start), label).unwrap();
+                        for s in block.successors.iter() {
+                            writeln!(out, "    {} -> {};", node(block.start), node(*s)).unwrap();
+                        }
+                    }
+                    out.push_str("  }\n");
+                }
+                out.push_str("}\n");
+            }
+            Format::Json => {
+                let functions = self
+                    .functions
+                    .values()
+                    .map(|f| {
+                        let blocks = f
+                            .blocks
+                            .values()
+                            .map(|b| {
+                                format!(
+                                    "{{\"start\":{},\"end\":{},\"instructions\":[{}],\"successors\":[{}]}}",
+                                    b.start,
+                                    b.end,
+                                    json::strings(self.listing(b)),
+                                    json::numbers(b.successors.iter())
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        format!(
+                            "{{\"name\":{},\"entry\":{},\"blocks\":[{}]}}",
+                            json::string(&f.name),
+                            f.entry,
+                            blocks.join(",")
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                writeln!(out, "{{\"functions\":[{}]}}", functions.join(",")).unwrap();
+            }
+        }
+        out
+    }
+
+    /// Which function calls which.
+    pub fn calls(&self, format: Format) -> String {
+        let mut out = String::new();
+        match format {
+            Format::Dot => {
+                out.push_str("digraph calls {\n  node [shape=box fontname=monospace];\n");
+                for f in self.functions.values() {
+                    writeln!(out, "  \"{}\";", f.name).unwrap();
+                    for callee in f.calls.iter() {
+                        writeln!(
+                            out,
+                            "  \"{}\" -> \"{}\";",
+                            f.name,
+                            self.program.name(*callee)
+                        )
+                        .unwrap();
+                    }
+                }
+                out.push_str("}\n");
+            }
+            Format::Json => {
+                let functions = self
+                    .functions
+                    .values()
+                    .map(|f| {
+                        format!(
+                            "{{\"name\":{},\"entry\":{},\"calls\":[{}]}}",
+                            json::string(&f.name),
+                            f.entry,
+                            json::numbers(f.calls.iter())
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                writeln!(out, "{{\"functions\":[{}]}}", functions.join(",")).unwrap();
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::{disassemble, Options};
+
+    #[test]
+    fn blocks() {
+        let rom = [
+            0x22, 0x06, // 200: CALL 206
+            0x30, 0x01, // 202: SEQ V0, 1
+            0x12, 0x00, // 204: JP 200
+            0x70, 0x01, // 206: ADD V0, 1
+            0x00, 0xee, // 208: RET
+        ];
+        let program = disassemble(&rom, Options::default());
+        let graph = Graph::new(&program);
+        let main = &graph.functions[&0x200];
+        assert_eq!(
+            main.blocks.values().collect::<Vec<_>>(),
+            vec![
+                &Block {
+                    start: 0x200,
+                    end: 0x204,
+                    successors: vec![0x204, 0x206]
+                },
+                &Block {
+                    start: 0x204,
+                    end: 0x206,
+                    successors: vec![0x200]
+                },
+                &Block {
+                    start: 0x206,
+                    end: 0x20a,
+                    successors: vec![]
+                },
+            ]
+        );
+        assert_eq!(main.calls.iter().collect::<Vec<_>>(), vec![&0x206]);
+        assert_eq!(graph.functions[&0x206].blocks.len(), 1);
+        assert!(graph
+            .calls(Format::Dot)
+            .contains("\"main\" -> \"function@206\";"));
+        assert!(graph
+            .calls(Format::Json)
+            .starts_with("{\"functions\":[{\"name\":\"main\",\"entry\":512,\"calls\":[518]}"));
+    }
+}
@@ -0,0 +1,28 @@
+//! Just enough JSON writing for the listing and the graphs.
+
+pub fn numbers<'a, I: Iterator<Item = &'a u16>>(numbers: I) -> String {
+    numbers.map(u16::to_string).collect::<Vec<_>>().join(",")
+}
+
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn strings(strings: Vec<String>) -> String {
+    strings
+        .iter()
+        .map(|s| string(s))
+        .collect::<Vec<_>>()
+        .join(",")
+}
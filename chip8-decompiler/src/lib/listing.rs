@@ -0,0 +1,503 @@
+//! The ways of printing a program: a listing, source for the assembler and JSON.
+use crate::coverage::Coverage;
+use crate::json;
+use crate::program::{format_native, read_u16, Program, Row};
+use chip8_isa::Opcode;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter, Result};
+
+#[derive(Clone, Copy)]
+pub(crate) enum Argument {
+    Constant(u16),
+    Register(u16),
+}
+
+/// The ANSI colors of the listing, which are all empty when it's plain.
+#[derive(Clone, Copy)]
+pub struct Colors(pub bool);
+
+impl Colors {
+    fn fg(self, color: u8) -> String {
+        if self.0 {
+            format!("\x1b[38;5;{}m", color)
+        } else {
+            String::new()
+        }
+    }
+
+    fn reset(self) -> &'static str {
+        if self.0 {
+            "\x1b[m"
+        } else {
+            ""
+        }
+    }
+}
+
+/// An argument in the listing, in color or not.
+struct Painted(Argument, Colors);
+
+impl Display for Painted {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let c = self.1;
+        match self.0 {
+            Argument::Constant(v) => write!(f, "{}#{}{:x}{}", c.fg(0), c.fg(3), v, c.reset()),
+            Argument::Register(v) => write!(f, "{}v{:1x}{}", c.fg(7), v, c.reset()),
+        }
+    }
+}
+
+impl Argument {
+    /// The argument in pseudo-C.
+    pub(crate) fn pseudo(&self) -> String {
+        match self {
+            Self::Constant(v) => format!("{:#04x}", v),
+            Self::Register(v) => format!("v{:x}", v),
+        }
+    }
+}
+
+/// The mnemonic in the listing.
+const fn name_str(op: &Opcode) -> &'static str {
+    match op {
+        Opcode::Sys(_) => "sys",
+        Opcode::Load(..) | Opcode::LoadRegister(..) => "load",
+        Opcode::Add(..) | Opcode::AddRegister(..) => "add",
+        Opcode::Sub(..) => "sub",
+        Opcode::SubInverse(..) => "subi",
+        Opcode::And(..) => "and",
+        Opcode::Or(..) => "or",
+        Opcode::Xor(..) => "xor",
+        Opcode::LoadI(_) => "ldi",
+        Opcode::AddI(_) => "addi",
+        Opcode::SetSound(_) => "snd",
+        Opcode::LoadR(_) => "ldr",
+        Opcode::LoadKey(_) => "ldk",
+        Opcode::Dump(_) => "dump",
+        Opcode::Draw(..) => "draw",
+        Opcode::Call(_) => "call",
+        Opcode::Jump(_) => "jmp",
+        Opcode::JumpV0(_) => "jmp0",
+        Opcode::Bcd(_) => "bcd",
+        Opcode::Random(..) => "rand",
+        Opcode::SkipKey(_) => "skp",     // skip key present
+        Opcode::SkipNotKey(_) => "snkp", // skip no key present
+        Opcode::SkipEqual(..) | Opcode::SkipEqualRegister(..) => "seq",
+        Opcode::SkipNotEqual(..) | Opcode::SkipNotEqualRegister(..) => "sneq",
+        Opcode::SetDelay(_) => "dly",
+        Opcode::LoadDelay(_) => "loadd",
+        Opcode::Return => "ret",
+        Opcode::Clear => "clr",
+        Opcode::Font(_) => "font",
+        Opcode::ShiftLeft(..) => "shl",
+        Opcode::ShiftRight(..) => "shr",
+    }
+}
+
+/// The arguments in the listing.
+fn format_args(
+    op: &Opcode,
+    f: &mut Formatter,
+    labels: &HashMap<u16, String>,
+    sprites: &HashSet<u16>,
+    colors: Colors,
+) -> Result {
+    let r = |x: u8| Painted(Argument::Register(x as u16), colors);
+    let c = |v: u16| Painted(Argument::Constant(v), colors);
+    match *op {
+        Opcode::Load(x, nn)
+        | Opcode::Add(x, nn)
+        | Opcode::SkipEqual(x, nn)
+        | Opcode::SkipNotEqual(x, nn) => write!(f, "{}, {}", r(x), c(nn as u16)),
+        Opcode::LoadRegister(x, y)
+        | Opcode::AddRegister(x, y)
+        | Opcode::Sub(x, y)
+        | Opcode::SubInverse(x, y)
+        | Opcode::And(x, y)
+        | Opcode::Or(x, y)
+        | Opcode::Xor(x, y)
+        | Opcode::SkipEqualRegister(x, y)
+        | Opcode::SkipNotEqualRegister(x, y) => write!(f, "{}, {}", r(x), r(y)),
+        Opcode::AddI(x)
+        | Opcode::LoadDelay(x)
+        | Opcode::SetDelay(x)
+        | Opcode::SetSound(x)
+        | Opcode::LoadR(x)
+        | Opcode::Dump(x)
+        | Opcode::LoadKey(x)
+        | Opcode::Bcd(x)
+        | Opcode::Font(x)
+        | Opcode::SkipKey(x)
+        | Opcode::SkipNotKey(x) => write!(f, "{}", r(x)),
+        Opcode::LoadI(address) => {
+            write!(f, "{} ", colors.fg(10))?;
+            if sprites.contains(&address) {
+                write!(f, "sprite@{:x}", address)
+            } else {
+                write!(f, "{}", c(address))
+            }
+        }
+        Opcode::Jump(target) | Opcode::JumpV0(target) | Opcode::Call(target) => {
+            write!(f, "{}", colors.fg(6))?;
+            if let Some(name) = labels.get(&target) {
+                write!(f, "{}", name)
+            } else {
+                write!(f, "{}", c(target))
+            }
+        }
+        Opcode::Random(x, mask) => {
+            write!(f, "{}", r(x))?;
+            if mask != 0xff {
+                write!(f, ", {}", c(mask as u16))?;
+            }
+            Ok(())
+        }
+        Opcode::Draw(x, y, n) => write!(f, "{}, {}, {}", r(x), r(y), c(n as u16)),
+        Opcode::Sys(address) => write!(f, "{}", c(address)),
+        Opcode::Return | Opcode::Clear => Ok(()),
+        Opcode::ShiftRight(x, y) | Opcode::ShiftLeft(x, y) => {
+            write!(f, "{}", r(x))?;
+            if x != y {
+                write!(f, ", {}", r(y))
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+/// One row of a sprite as `#` and `.` pixels.
+fn pixels(row: &[u8]) -> String {
+    row.iter()
+        .flat_map(|b| {
+            (0..8)
+                .rev()
+                .map(move |i| if b >> i & 1 == 1 { '#' } else { '.' })
+        })
+        .collect()
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:#04x}", b))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The listing of the program, with addresses and opcodes.
+pub struct Listing<'a> {
+    program: &'a Program,
+    colors: Colors,
+    coverage: Option<&'a Coverage>,
+}
+
+impl<'a> Listing<'a> {
+    pub fn new(program: &'a Program, colors: Colors) -> Self {
+        Self {
+            program,
+            colors,
+            coverage: None,
+        }
+    }
+
+    /// Shows how many times every instruction ran.
+    pub fn coverage(self, coverage: &'a Coverage) -> Self {
+        Self {
+            coverage: Some(coverage),
+            ..self
+        }
+    }
+}
+
+impl<'a> Display for Listing<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let (program, c) = (self.program, self.colors);
+        for row in program.rows(0x200) {
+            let (addr, to) = match row {
+                Row::Code(address, _) => (address, address + 2),
+                Row::Sprite(address, shape) => (address, address + shape.size()),
+                Row::Data(from, to) => (from, to),
+            };
+            if let Some(name) = program.labels.get(&addr) {
+                writeln!(f, "{}{}:{}", c.fg(49), name, c.reset())?;
+            } else if program.sprites.contains(&addr) {
+                writeln!(f, "{}sprite@{:x}:{}", c.fg(10), addr, c.reset())?;
+            }
+            match row {
+                Row::Code(_, instruction) => {
+                    write!(
+                        f,
+                        "{}{:04X} {}{:04x} {}{} ",
+                        c.fg(0),
+                        addr,
+                        c.fg(8),
+                        instruction.encode(),
+                        c.fg(204),
+                        name_str(&instruction)
+                    )?;
+                    format_args(&instruction, f, &program.labels, &program.sprites, c)?;
+                    match self.coverage.map(|coverage| coverage.count(addr)) {
+                        Some(0) => write!(f, " {}; never", c.fg(9))?,
+                        Some(count) => write!(f, " {}; {}x", c.fg(8), count)?,
+                        None => {}
+                    }
+                    writeln!(f, "{}", c.reset())?;
+                }
+                Row::Sprite(_, shape) => {
+                    let per_row = shape.bytes_per_row();
+                    for (i, row) in program.bytes(addr, to).chunks(per_row as usize).enumerate() {
+                        writeln!(
+                            f,
+                            "{}{:04X}      {}db {}{} {}; {}{}",
+                            c.fg(0),
+                            addr + i as u16 * per_row,
+                            c.fg(204),
+                            c.fg(3),
+                            hex_bytes(row),
+                            c.fg(8),
+                            pixels(row),
+                            c.reset()
+                        )?;
+                    }
+                }
+                Row::Data(..) => {
+                    writeln!(
+                        f,
+                        "{}{:04X}      {}db {}{}{}",
+                        c.fg(0),
+                        addr,
+                        c.fg(204),
+                        c.fg(3),
+                        hex_bytes(program.bytes(addr, to)),
+                        c.reset()
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The program as source for `chip8-assembler`,
+/// which assembles back into the exact same ROM.
+pub struct Reassemblable<'a>(pub &'a Program);
+
+impl<'a> Display for Reassemblable<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let program = self.0;
+        // the assembler puts the jump to the entrypoint in by itself.
+        let jumps = read_u16(&program.buffer)
+            .filter(|op| op >> 12 == 1)
+            .is_some();
+        let rows = program.rows(if jumps { 0x202 } else { 0x200 });
+        let starts = rows
+            .iter()
+            .map(|row| match row {
+                Row::Code(address, _) | Row::Sprite(address, _) | Row::Data(address, _) => *address,
+            })
+            .collect::<HashSet<_>>();
+        let name = |address: u16| {
+            if let Some(label) = program.labels.get(&address) {
+                Some(label.trim_end_matches("()").to_string())
+            } else if program.sprites.contains(&address) && starts.contains(&address) {
+                Some(format!("sprite@{:x}", address))
+            } else {
+                None
+            }
+        };
+
+        if jumps {
+            writeln!(f, ".entrypoint main")?;
+        } else {
+            writeln!(f, ".noentrypoint")?;
+        }
+        // targets in the middle of something, or outside of the ROM.
+        let mut outside = program
+            .labels
+            .keys()
+            .filter(|address| !starts.contains(address))
+            .collect::<Vec<_>>();
+        outside.sort();
+        for address in outside {
+            writeln!(f, "{} = {:#05x}", name(*address).unwrap(), address)?;
+        }
+
+        for row in rows.iter() {
+            match row {
+                Row::Code(address, instruction) => {
+                    if let Some(name) = name(*address) {
+                        writeln!(f, "\n{}:", name)?;
+                    }
+                    write!(f, "  ")?;
+                    format_native(instruction, f, name)?;
+                    writeln!(f)?;
+                }
+                Row::Sprite(address, shape) => {
+                    if let Some(name) = name(*address) {
+                        writeln!(f, "\n{}:", name)?;
+                    }
+                    let bytes = program.bytes(*address, address + shape.size());
+                    for row in bytes.chunks(shape.bytes_per_row() as usize) {
+                        writeln!(f, "  db {} ; {}", hex_bytes(row), pixels(row))?;
+                    }
+                }
+                Row::Data(from, to) => {
+                    if let Some(name) = name(*from) {
+                        writeln!(f, "\n{}:", name)?;
+                    }
+                    writeln!(f, "  db {}", hex_bytes(program.bytes(*from, *to)))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The program as JSON, for editors and other tools:
+/// the instructions, labels, data regions and what refers to what.
+pub struct Json<'a>(pub &'a Program);
+
+impl<'a> Display for Json<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let program = self.0;
+        let mut labels = program.labels.iter().collect::<Vec<_>>();
+        labels.sort();
+        let labels = labels
+            .into_iter()
+            .map(|(address, _)| {
+                format!(
+                    "{{\"address\":{},\"name\":{}}}",
+                    address,
+                    json::string(&program.name(*address))
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut instructions = Vec::new();
+        let mut data = Vec::new();
+        for row in program.rows(0x200) {
+            match row {
+                Row::Code(address, i) => instructions.push(format!(
+                    "{{\"address\":{},\"opcode\":{},\"text\":{}}}",
+                    address,
+                    i.encode(),
+                    json::string(&program.native(&i))
+                )),
+                Row::Sprite(from, shape) => data.push((from, from + shape.size(), "sprite")),
+                Row::Data(from, to) => match data.last_mut() {
+                    // the rows split data up into lines, regions don't need to.
+                    Some((_, end, "data")) if *end == from => *end = to,
+                    _ => data.push((from, to, "data")),
+                },
+            }
+        }
+        let data = data
+            .into_iter()
+            .map(|(from, to, kind)| {
+                let bytes = program
+                    .bytes(from, to)
+                    .iter()
+                    .map(|b| *b as u16)
+                    .collect::<Vec<_>>();
+                format!(
+                    "{{\"start\":{},\"end\":{},\"kind\":\"{}\",\"bytes\":[{}]}}",
+                    from,
+                    to,
+                    kind,
+                    json::numbers(bytes.iter())
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let xrefs = program
+            .instructions
+            .iter()
+            .filter_map(|(address, i)| {
+                let (to, kind) = match *i {
+                    Opcode::Jump(target) => (target, "jump"),
+                    Opcode::JumpV0(target) => (target, "table"),
+                    Opcode::Call(target) => (target, "call"),
+                    Opcode::LoadI(what) if program.sprites.contains(&what) => (what, "sprite"),
+                    Opcode::LoadI(what) => (what, "data"),
+                    _ => return None,
+                };
+                Some(format!(
+                    "{{\"from\":{},\"to\":{},\"kind\":\"{}\"}}",
+                    address, to, kind
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        writeln!(
+            f,
+            "{{\"entrypoint\":{},\"labels\":[{}],\"instructions\":[{}],\"data\":[{}],\"xrefs\":[{}]}}",
+            program.entrypoint(),
+            labels.join(","),
+            instructions.join(","),
+            data.join(","),
+            xrefs.join(",")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::{disassemble, Options};
+    use chip8_assembler::assembler::{self, assemble};
+
+    #[test]
+    fn output_modes() {
+        let rom = [0xa2, 0x06, 0xd0, 0x12, 0x12, 0x04, 0x80, 0x40, 0xaa];
+        let program = disassemble(&rom, Options::default());
+        let plain = Listing::new(&program, Colors(false)).to_string();
+        assert_eq!(pixels(&[0x81, 0x40]), "#......#.#......");
+        assert!(!plain.contains('\x1b'));
+        assert!(plain.starts_with("main:\n0200 a206 ldi  sprite@206\n"));
+        let coverage = Coverage::parse("200 3\n202 3").unwrap();
+        let covered = Listing::new(&program, Colors(false))
+            .coverage(&coverage)
+            .to_string();
+        assert!(covered.contains("0200 a206 ldi  sprite@206 ; 3x\n"));
+        assert!(covered.contains("0204 1204 jmp label@204 ; never\n"));
+        assert!(Listing::new(&program, Colors(true))
+            .to_string()
+            .contains("\x1b[38;5;204m"));
+        assert_eq!(
+            Json(&program).to_string(),
+            concat!(
+                r#"{"entrypoint":512,"labels":[{"address":512,"name":"main"},{"address":516,"name":"label@204"}],"#,
+                r#""instructions":[{"address":512,"opcode":41478,"text":"LDI 0x206"},"#,
+                r#"{"address":514,"opcode":53266,"text":"DRW V0, V1, 2"},"#,
+                r#"{"address":516,"opcode":4612,"text":"JP label@204"}],"#,
+                r#""data":[{"start":518,"end":520,"kind":"sprite","bytes":[128,64]},"#,
+                r#"{"start":520,"end":521,"kind":"data","bytes":[170]}],"#,
+                r#""xrefs":[{"from":512,"to":518,"kind":"sprite"},{"from":516,"to":516,"kind":"jump"}]}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let root = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+        let roms = std::fs::read_dir(format!("{}/test-roms", root))
+            .unwrap()
+            .chain(std::fs::read_dir(format!("{}/examples/bin", root)).unwrap());
+        for rom in roms {
+            let path = rom.unwrap().path();
+            let buffer = std::fs::read(&path).unwrap();
+            let program = disassemble(&buffer, Options::default());
+            let source = Reassemblable(&program).to_string();
+            assert_eq!(
+                assemble(&source, &assembler::Options::default()),
+                Ok(buffer),
+                "{}:\n{}",
+                path.display(),
+                source
+            );
+        }
+    }
+}
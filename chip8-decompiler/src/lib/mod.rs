@@ -0,0 +1,20 @@
+//! Disassembly of CHIP8 ROMs, and the listings, graphs
+//! and pseudo-C that come out of it.
+//!
+//! ```
+//! use chip8_decompiler::{disassemble, Options};
+//!
+//! // CALL 0x204, JP 0x202, RET
+//! let program = disassemble(&[0x22, 0x04, 0x12, 0x02, 0x00, 0xee], Options::default());
+//! assert_eq!(program.lookup("function@204"), Some(0x204));
+//! assert_eq!(program.native(&program.instruction(0x200).unwrap()), "CALL function@204");
+//! ```
+pub mod cli;
+pub mod coverage;
+pub mod graph;
+mod json;
+pub mod listing;
+pub mod program;
+pub mod pseudo;
+
+pub use program::{disassemble, Options, Program};
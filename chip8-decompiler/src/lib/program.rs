@@ -0,0 +1,441 @@
+//! Disassembly by recursive descent: every path from the entrypoint is
+//! followed, so only what can run is code and the rest is data.
+use chip8_isa::Opcode;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter, Result};
+
+/// The instruction for `opcode`, if it's one the disassembly can follow.
+/// Machine code routines never run on an interpreter, so `SYS` is data.
+pub fn decode(opcode: u16) -> Option<Opcode> {
+    Opcode::decode(opcode).filter(|op| !matches!(op, Opcode::Sys(_)))
+}
+
+/// Where execution goes after the instruction at `address`,
+/// not counting calls, which come back.
+/// `JP0` goes somewhere that depends on `V0`, so nowhere known.
+pub fn successors(op: &Opcode, address: u16) -> Vec<u16> {
+    match op {
+        Opcode::Jump(target) => vec![*target],
+        Opcode::JumpV0(_) | Opcode::Return => vec![],
+        Opcode::SkipEqual(..)
+        | Opcode::SkipNotEqual(..)
+        | Opcode::SkipEqualRegister(..)
+        | Opcode::SkipNotEqualRegister(..)
+        | Opcode::SkipKey(_)
+        | Opcode::SkipNotKey(_) => vec![address + 2, address + 4],
+        _ => vec![address + 2],
+    }
+}
+
+/// Writes the instruction in the assembler's syntax,
+/// with `name` giving the label for an address if there is one.
+pub(crate) fn format_native(
+    op: &Opcode,
+    f: &mut Formatter,
+    name: impl Fn(u16) -> Option<String>,
+) -> Result {
+    let address = |a: u16| name(a).unwrap_or_else(|| format!("{:#05x}", a));
+    match *op {
+        Opcode::Jump(target)
+        | Opcode::JumpV0(target)
+        | Opcode::Call(target)
+        | Opcode::LoadI(target) => write!(f, "{} {}", op.mnemonic(), address(target)),
+        _ => write!(f, "{}", op),
+    }
+}
+
+pub(crate) fn read_u16(slice: &[u8]) -> Option<u16> {
+    if slice.len() >= 2 {
+        let a = slice[0] as u16;
+        let b = slice[1] as u16;
+        Some(a << 8 | b)
+    } else {
+        None
+    }
+}
+
+/// How a sprite is drawn, from the `DRW` after the `LDI` pointing to it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Shape {
+    pub rows: u16,
+    /// SCHIP's 16x16 sprites, two bytes a row.
+    pub wide: bool,
+}
+
+impl Shape {
+    fn from_height(n: u16) -> Self {
+        if n == 0 {
+            Self {
+                rows: 16,
+                wide: true,
+            }
+        } else {
+            Self {
+                rows: n,
+                wide: false,
+            }
+        }
+    }
+
+    pub fn bytes_per_row(&self) -> u16 {
+        if self.wide {
+            2
+        } else {
+            1
+        }
+    }
+
+    pub fn size(&self) -> u16 {
+        self.rows * self.bytes_per_row()
+    }
+}
+
+/// A row of the listing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Row {
+    Code(u16, Opcode),
+    /// a sprite drawn somewhere, at its address.
+    Sprite(u16, Shape),
+    /// bytes that aren't reachable code, from `.0` to `.1`.
+    Data(u16, u16),
+}
+
+/// A disassembled ROM: its reachable instructions, labels and sprites.
+pub struct Program {
+    pub(crate) main: u16,
+    pub(crate) labels: HashMap<u16, String>,
+    pub(crate) sprites: HashSet<u16>,
+    /// the sprites that are drawn, by the shape of the biggest draw.
+    pub(crate) shapes: HashMap<u16, Shape>,
+    pub(crate) instructions: BTreeMap<u16, Opcode>,
+    pub(crate) buffer: Vec<u8>,
+}
+
+/// What to know about a ROM before disassembling it.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Addresses known to be code, like the targets
+    /// of a `JP0` that can't be guessed.
+    pub code: Vec<u16>,
+}
+
+/// Disassembles the ROM by following every path from the entrypoint,
+/// plus the addresses in `options.code`.
+pub fn disassemble(rom: &[u8], options: Options) -> Program {
+    Program::new(rom, &options.code)
+}
+
+/// How many entries a `JP0` jump table is guessed to have at most.
+const MAX_JUMP_TABLE: u16 = 64;
+
+impl Program {
+    fn new(buffer: &[u8], hints: &[u16]) -> Self {
+        let opcode_at = |address: u16| {
+            let offset = (address as usize).checked_sub(0x200)?;
+            read_u16(buffer.get(offset..)?)
+        };
+        let main = match opcode_at(0x200).and_then(decode) {
+            Some(Opcode::Jump(target)) => target,
+            _ => 0x200,
+        };
+
+        let mut labels = HashMap::new();
+        let mut instructions = BTreeMap::new();
+        let mut sprites = HashSet::new();
+        let mut shapes = HashMap::new();
+        let generate_label = |is_call: bool, location: u16| {
+            if !is_call {
+                format!("label@{:x}", location)
+            } else {
+                format!("function@{:x}()", location)
+            }
+        };
+
+        labels.insert(main, String::from("main"));
+        if main != 0x200 {
+            // the jump to main.
+            instructions.insert(0x200, Opcode::Jump(main));
+        }
+
+        // every address to decode, with where I points to if it's known.
+        let mut queue = VecDeque::new();
+        queue.push_back((main, None));
+        for hint in hints {
+            labels
+                .entry(*hint)
+                .or_insert_with_key(|key| generate_label(false, *key));
+            queue.push_back((*hint, None));
+        }
+
+        while let Some((address, mut i_points_to)) = queue.pop_front() {
+            if instructions.contains_key(&address) {
+                continue;
+            }
+            // paths running into data end there.
+            let next_op = match opcode_at(address).and_then(decode) {
+                Some(op) => op,
+                None => continue,
+            };
+            match next_op {
+                Opcode::Call(target) | Opcode::Jump(target) => {
+                    let is_call = matches!(next_op, Opcode::Call(_));
+                    labels
+                        .entry(target)
+                        .or_insert_with_key(|key| generate_label(is_call, *key));
+                    queue.push_back((target, None));
+                }
+                Opcode::JumpV0(base) => {
+                    // jump tables are usually a run of `JP`s, one per value of V0.
+                    labels
+                        .entry(base)
+                        .or_insert_with_key(|key| generate_label(false, *key));
+                    for entry in (0..MAX_JUMP_TABLE).map(|i| base + i * 2) {
+                        match opcode_at(entry).and_then(decode) {
+                            Some(Opcode::Jump(_)) => queue.push_back((entry, None)),
+                            _ => break,
+                        }
+                    }
+                }
+                Opcode::LoadI(address) => {
+                    sprites.insert(address);
+                    i_points_to = Some(address);
+                }
+                Opcode::AddI(_) | Opcode::LoadR(_) | Opcode::Dump(_) | Opcode::Font(_) => {
+                    i_points_to = None;
+                }
+                Opcode::Draw(_, _, n) => {
+                    if let Some(address) = i_points_to {
+                        let shape = Shape::from_height(n as u16);
+                        shapes
+                            .entry(address)
+                            .and_modify(|s: &mut Shape| {
+                                if shape.size() > s.size() {
+                                    *s = shape
+                                }
+                            })
+                            .or_insert(shape);
+                    }
+                }
+                _ => {}
+            }
+            // calls come back, and the callee doesn't know about I.
+            if let Opcode::Call(_) = next_op {
+                i_points_to = None;
+            }
+            for successor in successors(&next_op, address) {
+                queue.push_back((successor, i_points_to));
+            }
+
+            instructions.insert(address, next_op);
+        }
+
+        Self {
+            main,
+            instructions,
+            labels,
+            sprites,
+            shapes,
+            buffer: buffer.to_vec(),
+        }
+    }
+
+    /// Where the ROM really starts, past the jump at 0x200 if there's one.
+    pub fn entrypoint(&self) -> u16 {
+        self.main
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// The reachable instructions, by address.
+    pub fn instructions(&self) -> impl Iterator<Item = (u16, Opcode)> + '_ {
+        self.instructions.iter().map(|(address, i)| (*address, *i))
+    }
+
+    /// The instruction at `address`, if it's reachable code.
+    pub fn instruction(&self, address: u16) -> Option<Opcode> {
+        self.instructions.get(&address).copied()
+    }
+
+    /// The labels, by address.
+    pub fn labels(&self) -> impl Iterator<Item = (u16, &str)> + '_ {
+        let mut labels = self
+            .labels
+            .iter()
+            .map(|(address, label)| (*address, label.as_str()))
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels.into_iter()
+    }
+
+    /// The label at `address`, as in `function@20a()`.
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    /// Where the label `name` is, with or without the `()` of functions.
+    pub fn lookup(&self, name: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .find(|(_, label)| *label == name || label.trim_end_matches("()") == name)
+            .map(|(address, _)| *address)
+    }
+
+    /// An address with the closest label before it, as in `0x20c <main+4>`.
+    pub fn symbol(&self, address: u16) -> String {
+        match self.labels().take_while(|(at, _)| *at <= address).last() {
+            Some((at, label)) if at == address => format!("{:#05x} <{}>", address, label),
+            Some((at, label)) => format!("{:#05x} <{}+{}>", address, label, address - at),
+            None => format!("{:#05x}", address),
+        }
+    }
+
+    /// The label for an address, as the assembler takes it.
+    pub fn name(&self, address: u16) -> String {
+        match self.labels.get(&address) {
+            Some(label) => label.trim_end_matches("()").to_string(),
+            None => format!("{:#05x}", address),
+        }
+    }
+
+    /// An instruction in the assembler's syntax.
+    pub fn native(&self, instruction: &Opcode) -> String {
+        struct Native<'p>(&'p Program, Opcode);
+        impl<'p> Display for Native<'p> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+                format_native(&self.1, f, |a| {
+                    self.0.labels.get(&a).map(|_| self.0.name(a))
+                })
+            }
+        }
+        Native(self, *instruction).to_string()
+    }
+
+    pub(crate) fn end(&self) -> u16 {
+        0x200 + self.buffer.len() as u16
+    }
+
+    pub(crate) fn bytes(&self, from: u16, to: u16) -> &[u8] {
+        &self.buffer[from as usize - 0x200..to as usize - 0x200]
+    }
+
+    /// Splits the ROM from `start` on into instructions, sprites
+    /// and runs of other data.
+    pub fn rows(&self, start: u16) -> Vec<Row> {
+        let end = self.end();
+        let mut rows = Vec::new();
+        let mut address = start;
+        while address < end {
+            match self.instructions.get(&address) {
+                Some(i) if address + 2 <= end => {
+                    rows.push(Row::Code(address, *i));
+                    address += 2;
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(shape) = self.shapes.get(&address) {
+                let to = address + shape.size();
+                // sprites drawn out of code are better off as code.
+                if to <= end && !self.instructions.range(address..to).any(|_| true) {
+                    rows.push(Row::Sprite(address, *shape));
+                    address = to;
+                    continue;
+                }
+            }
+            let labeled = self.labels.contains_key(&address) || self.sprites.contains(&address);
+            match rows.last_mut() {
+                Some(Row::Data(from, to)) if *to == address && *to - *from < 8 && !labeled => {
+                    *to += 1
+                }
+                _ => rows.push(Row::Data(address, address + 1)),
+            }
+            address += 1;
+        }
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_regions() {
+        let rom = [0xa2, 0x06, 0xd0, 0x12, 0x12, 0x04, 0x80, 0x40, 0xaa];
+        let program = disassemble(&rom, Options::default());
+        let rows = program
+            .rows(0x200)
+            .into_iter()
+            .map(|row| match row {
+                Row::Code(address, _) => format!("code {:x}", address),
+                Row::Sprite(address, shape) => format!("sprite {:x} {}", address, shape.rows),
+                Row::Data(from, to) => format!("data {:x}..{:x}", from, to),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                "code 200",
+                "code 202",
+                "code 204",
+                "sprite 206 2",
+                "data 208..209"
+            ]
+        );
+    }
+
+    fn decoded(program: &Program) -> Vec<u16> {
+        program.instructions().map(|(address, _)| address).collect()
+    }
+
+    #[test]
+    fn traversal() {
+        let rom = [
+            0x30, 0x01, // 200: SEQ V0, 1
+            0xff, 0xff, // 202: data, skipped over
+            0x22, 0x0a, // 204: CALL 20a
+            0x12, 0x04, // 206: JP 204
+            0x60, 0x01, // 208: after the JP, never runs
+            0x00, 0xee, // 20a: RET
+            0x60, 0x02, // 20c: after the RET
+        ];
+        let program = disassemble(&rom, Options::default());
+        assert_eq!(decoded(&program), vec![0x200, 0x204, 0x206, 0x20a]);
+        assert_eq!(program.entrypoint(), 0x200);
+        assert_eq!(program.label(0x20a), Some("function@20a()"));
+        assert_eq!(program.lookup("function@20a"), Some(0x20a));
+        assert_eq!(program.lookup("label@204"), Some(0x204));
+        assert_eq!(program.lookup("nowhere"), None);
+        assert_eq!(program.instruction(0x20a), Some(Opcode::Return));
+        assert_eq!(program.instruction(0x202), None);
+        assert_eq!(program.symbol(0x200), "0x200 <main>");
+        assert_eq!(program.symbol(0x208), "0x208 <label@204+4>");
+        assert_eq!(
+            program.labels().collect::<Vec<_>>(),
+            vec![
+                (0x200, "main"),
+                (0x204, "label@204"),
+                (0x20a, "function@20a()")
+            ]
+        );
+        let program = disassemble(&rom, Options { code: vec![0x208] });
+        assert_eq!(decoded(&program), vec![0x200, 0x204, 0x206, 0x208, 0x20a]);
+    }
+
+    #[test]
+    fn jump_tables() {
+        let rom = [
+            0xb2, 0x04, // 200: JP0 204
+            0x00, 0x00, // 202: data
+            0x12, 0x0a, // 204: JP 20a
+            0x12, 0x0c, // 206: JP 20c
+            0xff, 0xff, // 208: end of the table
+            0x00, 0xe0, // 20a: CLR
+            0x00, 0xee, // 20c: RET
+        ];
+        let program = disassemble(&rom, Options::default());
+        assert_eq!(decoded(&program), vec![0x200, 0x204, 0x206, 0x20a, 0x20c]);
+    }
+}
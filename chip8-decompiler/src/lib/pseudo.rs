@@ -0,0 +1,582 @@
+//! Structured pseudo-C for every function: `if`/`else` and loops come
+//! back from the skip and jump patterns they compile to, registers are
+//! assigned with expressions and whatever doesn't fit a pattern is a `goto`.
+//!
+//! ```text
+//! SNE V0, 10   ; head:
+//! JP exit      ;        while (v0 != 0x0a) {
+//! ADD V0, 1    ;            v0 += 0x01;
+//! JP head      ;        }
+//! ```
+use crate::graph::Graph;
+use crate::listing::Argument;
+use crate::program::Program;
+use chip8_isa::Opcode;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Display, Formatter, Write};
+
+/// When a skip skips the instruction after it,
+/// or when the instruction after a run of skips runs.
+#[derive(Clone)]
+enum Condition {
+    /// the register equals the value, or doesn't.
+    Equal(Argument, Argument, bool),
+    /// the key in the register is pressed, or isn't.
+    Key(Argument, bool),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    fn of(instruction: &Opcode) -> Option<Self> {
+        let r = |x: u8| Argument::Register(x as u16);
+        let c = |nn: u8| Argument::Constant(nn as u16);
+        match *instruction {
+            Opcode::SkipEqual(x, nn) => Some(Self::Equal(r(x), c(nn), true)),
+            Opcode::SkipNotEqual(x, nn) => Some(Self::Equal(r(x), c(nn), false)),
+            Opcode::SkipEqualRegister(x, y) => Some(Self::Equal(r(x), r(y), true)),
+            Opcode::SkipNotEqualRegister(x, y) => Some(Self::Equal(r(x), r(y), false)),
+            Opcode::SkipKey(x) => Some(Self::Key(r(x), true)),
+            Opcode::SkipNotKey(x) => Some(Self::Key(r(x), false)),
+            _ => None,
+        }
+    }
+
+    fn not(&self) -> Self {
+        match self {
+            Self::Equal(a, b, equal) => Self::Equal(*a, *b, !equal),
+            Self::Key(a, pressed) => Self::Key(*a, !pressed),
+            Self::And(a, b) => Self::Or(Box::new(a.not()), Box::new(b.not())),
+            Self::Or(a, b) => Self::And(Box::new(a.not()), Box::new(b.not())),
+        }
+    }
+
+    fn or(self, other: Self) -> Self {
+        Self::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Written inside of an `&&` or `||`.
+    fn operand(&self) -> String {
+        match self {
+            Self::And(..) | Self::Or(..) => format!("({})", self),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Equal(a, b, true) => write!(f, "{} == {}", a.pseudo(), b.pseudo()),
+            Self::Equal(a, b, false) => write!(f, "{} != {}", a.pseudo(), b.pseudo()),
+            Self::Key(a, true) => write!(f, "key({})", a.pseudo()),
+            Self::Key(a, false) => write!(f, "!key({})", a.pseudo()),
+            Self::And(a, b) => write!(f, "{} && {}", a.operand(), b.operand()),
+            Self::Or(a, b) => write!(f, "{} || {}", a.operand(), b.operand()),
+        }
+    }
+}
+
+enum Statement {
+    Simple(String),
+    If(Condition, Vec<Line>, Vec<Line>),
+    /// `while (true)` without a condition.
+    While(Option<Condition>, Vec<Line>),
+    DoWhile(Vec<Line>, Condition),
+    Goto(u16),
+    Break,
+    Continue,
+}
+
+/// A statement with the address it starts at.
+type Line = (u16, Statement);
+
+/// The loop a statement is in, where `break` and `continue` jump to.
+#[derive(Clone, Copy)]
+struct Loop {
+    head: u16,
+    exit: u16,
+    /// `continue` only goes back to the head of a loop that checks nothing at the end.
+    continues: bool,
+}
+
+struct Function<'p> {
+    program: &'p Program,
+    addresses: BTreeSet<u16>,
+    /// where the plain jumps in the program go, which can't disappear into a pattern.
+    targets: &'p HashSet<u16>,
+    gotos: BTreeSet<u16>,
+}
+
+impl<'p> Function<'p> {
+    fn instruction(&self, address: u16) -> Opcode {
+        self.program.instructions[&address]
+    }
+
+    /// Where the plain jump at `address` goes, if there's one in the function.
+    fn jump(&self, address: u16) -> Option<u16> {
+        if !self.addresses.contains(&address) {
+            return None;
+        }
+        match self.instruction(address) {
+            Opcode::Jump(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    fn label(&self, address: u16) -> String {
+        match self.program.labels.get(&address) {
+            Some(label) => identifier(label),
+            None => format!("label_{:x}", address),
+        }
+    }
+
+    fn address(&self, address: u16) -> String {
+        if self.program.labels.contains_key(&address) {
+            self.label(address)
+        } else if self.program.sprites.contains(&address) {
+            format!("sprite_{:x}", address)
+        } else {
+            format!("{:#05x}", address)
+        }
+    }
+
+    fn branch(&mut self, target: u16, inside: Option<Loop>) -> Statement {
+        match inside {
+            Some(l) if target == l.exit => Statement::Break,
+            Some(l) if target == l.head && l.continues => Statement::Continue,
+            // landing on the jump back is the same.
+            Some(l) if l.continues && self.jump(target) == Some(l.head) => Statement::Continue,
+            _ => {
+                self.gotos.insert(target);
+                Statement::Goto(target)
+            }
+        }
+    }
+
+    /// The statements for the function's instructions from `from` up to `to`.
+    fn block(&mut self, from: u16, to: u16, inside: Option<Loop>) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut next = self.addresses.range(from..to).next().copied();
+        while let Some(address) = next {
+            let (statement, after) = self.statement(address, to, inside);
+            lines.push((address, statement));
+            next = self.addresses.range(after..to).next().copied();
+        }
+        lines
+    }
+
+    /// The statement at `address`, and where the one after it starts.
+    fn statement(&mut self, address: u16, end: u16, inside: Option<Loop>) -> (Statement, u16) {
+        // a loop, up to the last jump back here.
+        let back = self
+            .addresses
+            .range(address..end)
+            .rev()
+            .copied()
+            .find(|b| self.jump(*b) == Some(address));
+        if let Some(back) = back {
+            let exit = back + 2;
+            let check = back
+                .checked_sub(2)
+                .filter(|c| *c >= address && self.addresses.contains(c));
+            if let Some(c) = check.and_then(|c| Condition::of(&self.instruction(c))) {
+                if !self.targets.contains(&back) {
+                    let inside = Loop {
+                        head: address,
+                        exit,
+                        continues: false,
+                    };
+                    let body = self.block(address, back - 2, Some(inside));
+                    return (Statement::DoWhile(body, c.not()), exit);
+                }
+            }
+            let inside = Loop {
+                head: address,
+                exit,
+                continues: true,
+            };
+            let mut body = self.block(address, back, Some(inside));
+            // breaking out at the top is checking before every round.
+            if let Some((_, Statement::If(c, then, otherwise))) = body.first() {
+                if let ([(_, Statement::Break)], []) = (then.as_slice(), otherwise.as_slice()) {
+                    let condition = c.not();
+                    body.remove(0);
+                    return (Statement::While(Some(condition), body), exit);
+                }
+            }
+            return (Statement::While(None, body), exit);
+        }
+
+        let instruction = self.instruction(address);
+        if let Some(skips) = Condition::of(&instruction) {
+            let next = address + 2;
+            return match self.guarded(skips.not(), next, end, inside) {
+                Some(guarded) => guarded,
+                None => {
+                    let then = vec![(next, self.branch(address + 4, inside))];
+                    (Statement::If(skips, then, Vec::new()), next)
+                }
+            };
+        }
+        match instruction {
+            Opcode::Jump(target) => (self.branch(target, inside), address + 2),
+            _ => (Statement::Simple(self.simple(instruction)), address + 2),
+        }
+    }
+
+    /// The statement for the instruction at `next`, which only runs when `runs` holds
+    /// and is skipped otherwise, if it and what it jumps over fit in a pattern.
+    fn guarded(
+        &mut self,
+        runs: Condition,
+        next: u16,
+        end: u16,
+        inside: Option<Loop>,
+    ) -> Option<(Statement, u16)> {
+        let after = next + 2;
+        let labeled = self.targets.contains(&next) || self.gotos.contains(&next);
+        if next >= end || !self.addresses.contains(&next) || labeled {
+            return None;
+        }
+        let statement = match self.jump(next) {
+            // skipping the jump runs everything up to where it goes.
+            Some(target) if target > after && target <= end => {
+                let last = target - 2;
+                let otherwise = self
+                    .jump(last)
+                    .filter(|e| last >= after && *e > target && *e <= end)
+                    .filter(|_| !self.targets.contains(&last));
+                return Some(match otherwise {
+                    Some(e) => {
+                        let then = self.block(after, last, inside);
+                        let otherwise = self.block(target, e, inside);
+                        (Statement::If(runs.not(), then, otherwise), e)
+                    }
+                    None => {
+                        let then = self.block(after, target, inside);
+                        (Statement::If(runs.not(), then, Vec::new()), target)
+                    }
+                });
+            }
+            Some(target) => self.branch(target, inside),
+            None => match Condition::of(&self.instruction(next)) {
+                // the one after runs when this one doesn't, or doesn't skip.
+                Some(skips) => return self.guarded(runs.not().or(skips.not()), after, end, inside),
+                None => Statement::Simple(self.simple(self.instruction(next))),
+            },
+        };
+        Some((
+            Statement::If(runs, vec![(next, statement)], Vec::new()),
+            after,
+        ))
+    }
+
+    /// An instruction that doesn't branch, as an expression.
+    fn simple(&self, instruction: Opcode) -> String {
+        let r = |x: u8| format!("v{:x}", x);
+        let c = |nn: u8| format!("{:#04x}", nn);
+        match instruction {
+            Opcode::Load(x, nn) => format!("{} = {}", r(x), c(nn)),
+            Opcode::Add(x, nn) => format!("{} += {}", r(x), c(nn)),
+            Opcode::LoadRegister(x, y) => format!("{} = {}", r(x), r(y)),
+            Opcode::AddRegister(x, y) => format!("{} += {}", r(x), r(y)),
+            Opcode::Sub(x, y) => format!("{} -= {}", r(x), r(y)),
+            Opcode::SubInverse(x, y) => format!("{} = {} - {}", r(x), r(y), r(x)),
+            Opcode::And(x, y) => format!("{} &= {}", r(x), r(y)),
+            Opcode::Or(x, y) => format!("{} |= {}", r(x), r(y)),
+            Opcode::Xor(x, y) => format!("{} ^= {}", r(x), r(y)),
+            Opcode::ShiftRight(x, y) | Opcode::ShiftLeft(x, y) => {
+                let op = if let Opcode::ShiftLeft(..) = instruction {
+                    "<<"
+                } else {
+                    ">>"
+                };
+                if x == y {
+                    format!("{} {}= 1", r(y), op)
+                } else {
+                    format!("{} = {} {} 1", r(y), r(x), op)
+                }
+            }
+            Opcode::LoadI(address) => format!("i = {}", self.address(address)),
+            Opcode::AddI(x) => format!("i += {}", r(x)),
+            Opcode::Font(x) => format!("i = font({})", r(x)),
+            Opcode::Bcd(x) => format!("bcd({})", r(x)),
+            Opcode::LoadR(x) => format!("load({})", r(x)),
+            Opcode::Dump(x) => format!("save({})", r(x)),
+            Opcode::Draw(x, y, n) => format!("vf = draw({}, {}, {})", r(x), r(y), c(n)),
+            Opcode::Random(x, mask) => format!("{} = random() & {}", r(x), c(mask)),
+            Opcode::LoadKey(x) => format!("{} = key()", r(x)),
+            Opcode::LoadDelay(x) => format!("{} = delay", r(x)),
+            Opcode::SetDelay(x) => format!("delay = {}", r(x)),
+            Opcode::SetSound(x) => format!("sound = {}", r(x)),
+            Opcode::Clear => String::from("clear()"),
+            Opcode::Return => String::from("return"),
+            Opcode::Sys(address) => format!("sys({:#05x})", address),
+            Opcode::Call(target) => format!("{}()", self.label(target)),
+            Opcode::Jump(target) => format!("goto {}", self.address(target)),
+            Opcode::JumpV0(target) => format!("goto {} + v0", self.address(target)),
+            Opcode::SkipEqual(..)
+            | Opcode::SkipNotEqual(..)
+            | Opcode::SkipEqualRegister(..)
+            | Opcode::SkipNotEqualRegister(..)
+            | Opcode::SkipKey(_)
+            | Opcode::SkipNotKey(_) => {
+                format!("skip if ({})", Condition::of(&instruction).unwrap())
+            }
+        }
+    }
+
+    fn write(&self, out: &mut String, lines: &[Line], depth: usize, labeled: &mut HashSet<u16>) {
+        let indent = "    ".repeat(depth);
+        for (address, statement) in lines {
+            if self.gotos.contains(address) && labeled.insert(*address) {
+                writeln!(out, "{}:", self.label(*address)).unwrap();
+            }
+            match statement {
+                Statement::If(c, then, otherwise) => {
+                    if let (
+                        [(
+                            _,
+                            s @ (Statement::Simple(_)
+                            | Statement::Goto(_)
+                            | Statement::Break
+                            | Statement::Continue),
+                        )],
+                        [],
+                    ) = (then.as_slice(), otherwise.as_slice())
+                    {
+                        writeln!(out, "{}if ({}) {};", indent, c, self.line(s)).unwrap();
+                        continue;
+                    }
+                    writeln!(out, "{}if ({}) {{", indent, c).unwrap();
+                    self.write(out, then, depth + 1, labeled);
+                    if !otherwise.is_empty() {
+                        writeln!(out, "{}}} else {{", indent).unwrap();
+                        self.write(out, otherwise, depth + 1, labeled);
+                    }
+                    writeln!(out, "{}}}", indent).unwrap();
+                }
+                Statement::While(c, body) => {
+                    match c {
+                        Some(c) => writeln!(out, "{}while ({}) {{", indent, c),
+                        None => writeln!(out, "{}while (true) {{", indent),
+                    }
+                    .unwrap();
+                    self.write(out, body, depth + 1, labeled);
+                    writeln!(out, "{}}}", indent).unwrap();
+                }
+                Statement::DoWhile(body, c) => {
+                    writeln!(out, "{}do {{", indent).unwrap();
+                    self.write(out, body, depth + 1, labeled);
+                    writeln!(out, "{}}} while ({});", indent, c).unwrap();
+                }
+                s => writeln!(out, "{}{};", indent, self.line(s)).unwrap(),
+            }
+        }
+    }
+
+    /// A statement that fits on one line.
+    fn line(&self, statement: &Statement) -> String {
+        match statement {
+            Statement::Simple(s) => s.clone(),
+            Statement::Goto(target) => format!("goto {}", self.label(*target)),
+            Statement::Break => String::from("break"),
+            Statement::Continue => String::from("continue"),
+            _ => unreachable!("only simple statements fit on a line"),
+        }
+    }
+}
+
+/// A label as a C identifier.
+fn identifier(label: &str) -> String {
+    label.trim_end_matches("()").replace('@', "_")
+}
+
+/// Every function in the program, as pseudo-C.
+pub fn decompile(program: &Program) -> String {
+    let graph = Graph::new(program);
+    let targets = program
+        .instructions
+        .values()
+        .filter_map(|i| match i {
+            Opcode::Jump(target) => Some(*target),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    let mut out = String::new();
+    for f in graph.functions.values() {
+        let addresses = f
+            .blocks
+            .values()
+            .flat_map(|b| (b.start..b.end).step_by(2))
+            .collect::<BTreeSet<_>>();
+        let start = *addresses.iter().next().unwrap();
+        let end = *addresses.iter().next_back().unwrap() + 2;
+        let mut function = Function {
+            program,
+            addresses,
+            targets: &targets,
+            gotos: BTreeSet::new(),
+        };
+        let mut lines = function.block(start, end, None);
+        if start != f.entry {
+            // part of the function comes before its entry.
+            lines.insert(0, (start, function.branch(f.entry, None)));
+        }
+
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        writeln!(out, "void {}() {{", identifier(&f.name)).unwrap();
+        function.write(&mut out, &lines, 1, &mut HashSet::new());
+        out.push_str("}\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::{disassemble, Options};
+
+    fn decompiled(rom: &[u8]) -> String {
+        decompile(&disassemble(rom, Options::default()))
+    }
+
+    #[test]
+    fn structures() {
+        let rom = [
+            0x60, 0x00, // 200: LD V0, 0
+            0x40, 0x0a, // 202: SNE V0, 10
+            0x12, 0x14, // 204: JP 214
+            0x31, 0x00, // 206: SEQ V1, 0
+            0x12, 0x0e, // 208: JP 20e
+            0x62, 0x01, // 20a: LD V2, 1
+            0x12, 0x10, // 20c: JP 210
+            0x62, 0x02, // 20e: LD V2, 2
+            0x70, 0x01, // 210: ADD V0, 1
+            0x12, 0x02, // 212: JP 202
+            0x22, 0x18, // 214: CALL 218
+            0x12, 0x14, // 216: JP 214
+            0xe3, 0x9e, // 218: SIK V3
+            0x00, 0xee, // 21a: RET
+            0x83, 0x36, // 21c: SHR V3
+            0x33, 0x00, // 21e: SEQ V3, 0
+            0x12, 0x1c, // 220: JP 21c
+            0x00, 0xee, // 222: RET
+        ];
+        assert_eq!(
+            decompiled(&rom),
+            "void main() {
+    v0 = 0x00;
+    while (v0 != 0x0a) {
+        if (v1 == 0x00) {
+            v2 = 0x01;
+        } else {
+            v2 = 0x02;
+        }
+        v0 += 0x01;
+    }
+    while (true) {
+        function_218();
+    }
+}
+
+void function_218() {
+    if (!key(v3)) return;
+    do {
+        v3 >>= 1;
+    } while (v3 != 0x00);
+    return;
+}
+"
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        let rom = [
+            0x81, 0x25, // 200: SUB V1, V2
+            0x81, 0x27, // 202: SBI V1, V2
+            0x81, 0x26, // 204: SHR V1, V2
+            0xc3, 0x0f, // 206: RND V3, 0x0f
+            0x00, 0xee, // 208: RET
+        ];
+        assert_eq!(
+            decompiled(&rom),
+            "void main() {
+    v1 -= v2;
+    v1 = v2 - v1;
+    v2 = v1 >> 1;
+    v3 = random() & 0x0f;
+    return;
+}
+"
+        );
+    }
+
+    #[test]
+    fn chained_skips() {
+        let rom = [
+            0x30, 0x01, // 200: SEQ V0, 1
+            0x30, 0x02, // 202: SEQ V0, 2
+            0x60, 0x03, // 204: LD V0, 3
+            0x12, 0x00, // 206: JP 200
+        ];
+        assert_eq!(
+            decompiled(&rom),
+            "void main() {
+    while (true) {
+        if (v0 == 0x01 || v0 != 0x02) v0 = 0x03;
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn gotos() {
+        let rom = [
+            0x30, 0x01, // 200: SEQ V0, 1
+            0x12, 0x0a, // 202: JP 20a
+            0x31, 0x01, // 204: SEQ V1, 1
+            0x12, 0x0c, // 206: JP 20c
+            0x62, 0x01, // 208: LD V2, 1
+            0x63, 0x01, // 20a: LD V3, 1
+            0x00, 0xee, // 20c: RET
+        ];
+        assert_eq!(
+            decompiled(&rom),
+            "void main() {
+    if (v0 == 0x01) {
+        if (v1 != 0x01) goto label_20c;
+        v2 = 0x01;
+    }
+    v3 = 0x01;
+label_20c:
+    return;
+}
+"
+        );
+    }
+
+    #[test]
+    fn data_before_a_jump_back() {
+        let rom = [
+            0x30, 0x01, // 200: SEQ V0, 1
+            0xff, 0xff, // 202: data
+            0x12, 0x00, // 204: JP 200
+        ];
+        assert_eq!(
+            decompiled(&rom),
+            "void main() {
+    while (true) {
+        if (v0 == 0x01) continue;
+    }
+}
+"
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{gdb, profile, terminal, trace, CHIP8};
+use chip8_assembler::symbols::Symbols;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// Options for playing a game in the terminal, shared
+/// by `chip8-interpreter` and `chip8 run`.
+#[derive(Debug, StructOpt)]
+pub struct Args {
+    /// Customize color of output
+    #[structopt(long = "color", default_value = "255")]
+    pub color: u8,
+
+    /// Instead of playing, wait for a GDB client on this local port.
+    #[structopt(long = "gdb")]
+    pub gdb: Option<u16>,
+
+    /// Symbols written by the assembler, to show source lines in the trace.
+    #[structopt(long = "symbols", parse(from_os_str))]
+    pub symbols: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    pub trace: trace::Args,
+
+    #[structopt(flatten)]
+    pub profile: profile::Args,
+}
+
+impl Args {
+    /// Plays `game` until ESC is pressed or it crashes,
+    /// then writes the profile and the trace.
+    pub fn run(&self, game: &[u8]) -> Result<(), String> {
+        let symbols = match &self.symbols {
+            Some(path) => Symbols::read(path)?,
+            None => Symbols::default(),
+        };
+        self.run_with_symbols(game, symbols)
+    }
+
+    /// Plays like `run`, with the symbols `game` was just assembled with.
+    pub fn run_with_symbols(&self, game: &[u8], symbols: Symbols) -> Result<(), String> {
+        if let Some(port) = self.gdb {
+            return gdb::listen(game, port);
+        }
+        let mut tracer = self.trace.tracer(game)?.symbols(symbols);
+        let mut machine = CHIP8::with_game(game);
+        let result = terminal::run(&mut machine, self.color, &mut tracer);
+        self.profile.write(game, &machine)?;
+        let result = result.map_err(|fault| tracer.crash_report(fault));
+        tracer
+            .finish()
+            .map_err(|e| format!("Couldn't write the trace: {}", e))?;
+        result
+    }
+}
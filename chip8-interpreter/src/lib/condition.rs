@@ -0,0 +1,258 @@
+//! Conditions on the state of the machine, as in `V3 == 0x10 && I > 0x300`,
+//! for breakpoints that only stop when they hold.
+use crate::debugger::parse_number;
+use crate::CHIP8;
+use std::fmt::{self, Display, Formatter};
+
+/// Something with a value: a register, I, the delay timer or a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    V(u8),
+    I,
+    Delay,
+    Number(u16),
+}
+
+impl Operand {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        match input.to_ascii_uppercase().as_str() {
+            "I" => Ok(Operand::I),
+            "DT" => Ok(Operand::Delay),
+            name if name.len() == 2 && name.starts_with('V') => u8::from_str_radix(&name[1..], 16)
+                .map(Operand::V)
+                .map_err(|_| format!("unknown register {:?}", input)),
+            _ => parse_number(input).map(Operand::Number),
+        }
+    }
+
+    pub fn value(&self, machine: &CHIP8) -> u16 {
+        match *self {
+            Operand::V(x) => machine.registers()[x as usize] as u16,
+            Operand::I => machine.i(),
+            Operand::Delay => machine.delay() as u16,
+            Operand::Number(n) => n,
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Operand::V(x) => write!(f, "V{:X}", x),
+            Operand::I => write!(f, "I"),
+            Operand::Delay => write!(f, "DT"),
+            Operand::Number(n) => write!(f, "{:#x}", n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    const ALL: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessEqual),
+        (">=", Comparison::GreaterEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    fn holds(self, a: u16, b: u16) -> bool {
+        match self {
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b,
+            Comparison::Less => a < b,
+            Comparison::LessEqual => a <= b,
+            Comparison::Greater => a > b,
+            Comparison::GreaterEqual => a >= b,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        Self::ALL.iter().find(|(_, c)| *c == self).unwrap().0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Compare(Operand, Comparison, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+/// Splits a condition into operands, operators and parentheses.
+fn tokens(input: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let symbol = ["&&", "||", "(", ")"]
+            .iter()
+            .copied()
+            .chain(Comparison::ALL.iter().map(|(s, _)| *s))
+            .find(|s| rest.starts_with(s));
+        let len = match symbol {
+            Some(symbol) => symbol.len(),
+            None => rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len()),
+        };
+        if len == 0 {
+            return Err(format!("unexpected {:?}", rest));
+        }
+        tokens.push(rest[..len].to_string());
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.at).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&str, String> {
+        self.at += 1;
+        self.tokens
+            .get(self.at - 1)
+            .map(String::as_str)
+            .ok_or_else(|| "the condition ends too soon".to_string())
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.peek() == Some("||") {
+            self.at += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.comparison()?;
+        while self.peek() == Some("&&") {
+            self.at += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.comparison()?));
+        }
+        Ok(condition)
+    }
+
+    fn comparison(&mut self) -> Result<Condition, String> {
+        if self.peek() == Some("(") {
+            self.at += 1;
+            let condition = self.or()?;
+            return match self.next()? {
+                ")" => Ok(condition),
+                other => Err(format!("expected ')', got {:?}", other)),
+            };
+        }
+        let a = Operand::parse(self.next()?)?;
+        let symbol = self.next()?;
+        let comparison = match Comparison::ALL.iter().find(|(s, _)| *s == symbol) {
+            Some((_, comparison)) => *comparison,
+            None => return Err(format!("expected a comparison, got {:?}", symbol)),
+        };
+        let b = Operand::parse(self.next()?)?;
+        Ok(Condition::Compare(a, comparison, b))
+    }
+}
+
+impl Condition {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokens(input)?,
+            at: 0,
+        };
+        let condition = parser.or()?;
+        match parser.peek() {
+            Some(extra) => Err(format!("unexpected {:?} in the condition", extra)),
+            None => Ok(condition),
+        }
+    }
+
+    pub fn holds(&self, machine: &CHIP8) -> bool {
+        match self {
+            Condition::Compare(a, comparison, b) => {
+                comparison.holds(a.value(machine), b.value(machine))
+            }
+            Condition::And(a, b) => a.holds(machine) && b.holds(machine),
+            Condition::Or(a, b) => a.holds(machine) || b.holds(machine),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Condition::Compare(a, comparison, b) => {
+                write!(f, "{} {} {}", a, comparison.symbol(), b)
+            }
+            Condition::And(a, b) => write!(f, "{} && {}", a, b),
+            Condition::Or(a, b) => write!(f, "({} || {})", a, b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let condition = Condition::parse("V3 == 0x10 && I > 0x300").unwrap();
+        assert_eq!(
+            condition,
+            Condition::And(
+                Box::new(Condition::Compare(
+                    Operand::V(3),
+                    Comparison::Equal,
+                    Operand::Number(0x10)
+                )),
+                Box::new(Condition::Compare(
+                    Operand::I,
+                    Comparison::Greater,
+                    Operand::Number(0x300)
+                )),
+            )
+        );
+        assert_eq!(condition.to_string(), "V3 == 0x10 && I > 0x300");
+        assert_eq!(
+            Condition::parse("(va<=2||dt!=0)&&vf>=1")
+                .unwrap()
+                .to_string(),
+            "(VA <= 0x2 || DT != 0x0) && VF >= 0x1"
+        );
+        assert!(Condition::parse("V3 ==").is_err());
+        assert!(Condition::parse("V3 = 1").is_err());
+        assert!(Condition::parse("VG == 1").is_err());
+        assert!(Condition::parse("(V3 == 1").is_err());
+        assert!(Condition::parse("V3 == 1 V4").is_err());
+    }
+
+    #[test]
+    fn evaluation() {
+        // LD V3, 0x10; LDI 0x310
+        let mut machine = CHIP8::with_game(&[0x63, 0x10, 0xa3, 0x10]);
+        let condition = Condition::parse("V3 == 0x10 && I > 0x300").unwrap();
+        machine.cycle().unwrap();
+        assert!(!condition.holds(&machine));
+        machine.cycle().unwrap();
+        assert!(condition.holds(&machine));
+        assert!(Condition::parse("V0 == 1 || V3 == 16")
+            .unwrap()
+            .holds(&machine));
+    }
+}
@@ -0,0 +1,706 @@
+//! A Debug Adapter Protocol server, so editors can debug a ROM line by
+//! line in the source it was assembled from.
+//!
+//! `launch` takes `program`, the ROM, and `symbols`, the file the
+//! assembler writes with `--symbols`. `source` names the source file when
+//! the symbols don't, and `stopOnEntry` stops before the first instruction.
+use crate::condition::Operand;
+use crate::debugger::{parse_number, Debugger, Stop};
+use chip8_assembler::lsp::{read_message, write_message};
+use chip8_assembler::symbols::Symbols;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+/// How many instructions run between checks for requests.
+const CHUNK: usize = 10_000;
+
+/// The `variablesReference` of the scopes.
+const REGISTERS: u64 = 1;
+const STACK: u64 = 2;
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// What the game does until the next request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Stopped,
+    Continue,
+    /// Until a line other than `line`, skipping the calls
+    /// deeper than `depth` if `over` them.
+    Step {
+        line: Option<usize>,
+        depth: usize,
+        over: bool,
+    },
+    /// Until the stack is shallower than `depth`.
+    Out {
+        depth: usize,
+    },
+}
+
+/// Answers the requests of an editor about a game.
+pub struct Adapter {
+    debugger: Option<Debugger>,
+    symbols: Symbols,
+    /// The source file, for the stack frames.
+    source: Option<String>,
+    /// Addresses of the breakpoints set by line, replaced on every
+    /// `setBreakpoints`.
+    lines: Vec<u16>,
+    stop_on_entry: bool,
+    mode: Mode,
+    seq: u64,
+    done: bool,
+}
+
+impl Default for Adapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adapter {
+    pub fn new() -> Self {
+        Self {
+            debugger: None,
+            symbols: Symbols::default(),
+            source: None,
+            lines: Vec::new(),
+            stop_on_entry: false,
+            mode: Mode::Stopped,
+            seq: 0,
+            done: false,
+        }
+    }
+
+    /// Whether the game runs until something stops it.
+    pub fn running(&self) -> bool {
+        self.mode != Mode::Stopped
+    }
+
+    /// Whether the editor disconnected.
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    fn message(&mut self, kind: &str, mut message: Value) -> Value {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        message["type"] = json!(kind);
+        message
+    }
+
+    fn response(&mut self, request: &Value, body: Value) -> Value {
+        self.message(
+            "response",
+            json!({
+                "request_seq": request["seq"],
+                "command": request["command"],
+                "success": true,
+                "body": body,
+            }),
+        )
+    }
+
+    fn error(&mut self, request: &Value, message: String) -> Value {
+        self.message(
+            "response",
+            json!({
+                "request_seq": request["seq"],
+                "command": request["command"],
+                "success": false,
+                "message": message,
+            }),
+        )
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Value {
+        self.message("event", json!({ "event": event, "body": body }))
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) -> Vec<Value> {
+        self.mode = Mode::Stopped;
+        let mut body = json!({ "reason": reason, "threadId": 1, "allThreadsStopped": true });
+        if let Some(text) = text {
+            body["description"] = json!(text.clone());
+            body["text"] = json!(text);
+        }
+        vec![self.event("stopped", body)]
+    }
+
+    fn debugger(&self) -> Result<&Debugger, String> {
+        self.debugger
+            .as_ref()
+            .ok_or_else(|| "no game is launched".to_string())
+    }
+
+    fn debugger_mut(&mut self) -> Result<&mut Debugger, String> {
+        self.debugger
+            .as_mut()
+            .ok_or_else(|| "no game is launched".to_string())
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<(), String> {
+        let read = |key: &str| -> Result<Option<(String, Vec<u8>)>, String> {
+            match args[key].as_str() {
+                Some(path) => fs::read(path)
+                    .map(|bytes| Some((path.to_string(), bytes)))
+                    .map_err(|e| format!("Couldn't read {}: {}", path, e)),
+                None => Ok(None),
+            }
+        };
+        let (_, game) = read("program")?.ok_or("'launch' expects a program")?;
+        if let Some((path, symbols)) = read("symbols")? {
+            self.symbols = Symbols::parse(&String::from_utf8_lossy(&symbols))
+                .map_err(|e| format!("{}: {}", path, e))?;
+            // the source is relative to the symbols.
+            let directory = Path::new(&path).parent().unwrap_or_else(|| Path::new(""));
+            self.source = self
+                .symbols
+                .source
+                .as_ref()
+                .map(|source| directory.join(source).display().to_string());
+        }
+        if let Some(source) = args["source"].as_str() {
+            self.source = Some(source.to_string());
+        }
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.debugger = Some(Debugger::new(&game));
+        Ok(())
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let debugger = self.debugger.as_mut().ok_or("no game is launched")?;
+        for address in self.lines.drain(..) {
+            debugger.remove_breakpoint(address);
+        }
+        let mut breakpoints = Vec::new();
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        for breakpoint in requested {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as usize;
+            match self.symbols.address(line) {
+                Some((line, address)) => {
+                    debugger.add_breakpoint(address);
+                    self.lines.push(address);
+                    breakpoints.push(json!({ "verified": true, "line": line }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no code on or after this line",
+                })),
+            }
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// The nearest label at or before `address`, as in `loop+2`.
+    fn name(&self, address: u16) -> String {
+        let label = self
+            .symbols
+            .labels
+            .iter()
+            .filter(|(_, a)| *a <= address)
+            .max_by_key(|(_, a)| *a);
+        match (label, &self.debugger) {
+            (Some((name, a)), _) if *a == address => name.clone(),
+            (Some((name, a)), _) => format!("{}+{}", name, address - a),
+            (None, Some(debugger)) => debugger.program().symbol(address),
+            (None, None) => format!("0x{:03x}", address),
+        }
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let machine = self.debugger()?.machine();
+        let mut addresses = vec![machine.pc()];
+        // the calls are the instructions before the return addresses.
+        addresses.extend(machine.stack().iter().rev().map(|a| a.wrapping_sub(2)));
+        let frames: Vec<Value> = addresses
+            .iter()
+            .enumerate()
+            .map(|(id, address)| {
+                let mut frame = json!({
+                    "id": id,
+                    "name": self.name(*address),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("0x{:03x}", address),
+                });
+                if let (Some(location), Some(source)) =
+                    (self.symbols.code.get(address), &self.source)
+                {
+                    frame["line"] = json!(location.line);
+                    frame["column"] = json!(location.column);
+                    frame["source"] = json!({ "path": source });
+                }
+                frame
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": addresses.len() }))
+    }
+
+    fn variables(&self, reference: u64) -> Result<Value, String> {
+        let machine = self.debugger()?.machine();
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+        let variables: Vec<Value> = match reference {
+            REGISTERS => {
+                let mut variables: Vec<Value> = machine
+                    .registers()
+                    .iter()
+                    .enumerate()
+                    .map(|(x, v)| variable(format!("V{:X}", x), format!("0x{:02x}", v)))
+                    .collect();
+                let mut i = variable("I".to_string(), format!("0x{:03x}", machine.i()));
+                i["memoryReference"] = json!(format!("0x{:03x}", machine.i()));
+                variables.push(i);
+                variables.push(variable("DT".to_string(), machine.delay().to_string()));
+                variables.push(variable("ST".to_string(), machine.sound().to_string()));
+                variables
+            }
+            STACK => machine
+                .stack()
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(n, a)| variable(format!("#{}", n), format!("0x{:03x} {}", a, self.name(*a))))
+                .collect(),
+            _ => return Err(format!("no variables {}", reference)),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let memory = self.debugger()?.machine().memory();
+        let reference = args["memoryReference"].as_str().unwrap_or("");
+        let start = parse_number(reference)? as i64 + args["offset"].as_i64().unwrap_or(0);
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        let from = (start.max(0) as usize).min(memory.len());
+        let to = (from + count).min(memory.len());
+        Ok(json!({
+            "address": format!("0x{:03x}", from),
+            "data": base64(&memory[from..to]),
+            "unreadableBytes": count - (to - from),
+        }))
+    }
+
+    /// Evaluates a register, as in `V3`, or runs a command of the debugger.
+    fn evaluate(&mut self, expression: &str) -> Result<(String, bool), String> {
+        let debugger = self.debugger_mut()?;
+        if let Ok(register @ (Operand::V(_) | Operand::I | Operand::Delay)) =
+            Operand::parse(expression)
+        {
+            return Ok((format!("{:#x}", register.value(debugger.machine())), false));
+        }
+        let command = debugger.parse(expression)?.ok_or("nothing to evaluate")?;
+        let pc = debugger.machine().pc();
+        let output = debugger.execute(command);
+        Ok((output.trim_end().to_string(), debugger.machine().pc() != pc))
+    }
+
+    /// The responses and events for `request`.
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        let args = &request["arguments"];
+        let result = match request["command"].as_str().unwrap_or("") {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsEvaluateForHovers": true,
+                });
+                let response = self.response(request, capabilities);
+                return vec![response, self.event("initialized", json!({}))];
+            }
+            "launch" => self.launch(args).map(|_| Value::Null),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "configurationDone" => {
+                let response = self.response(request, Value::Null);
+                if self.stop_on_entry {
+                    let mut replies = vec![response];
+                    replies.extend(self.stopped("entry", None));
+                    return replies;
+                }
+                // running only checks the breakpoints after a step.
+                if self.debugger.as_ref().is_some_and(Debugger::at_breakpoint) {
+                    let mut replies = vec![response];
+                    replies.extend(self.stopped("breakpoint", None));
+                    return replies;
+                }
+                self.mode = Mode::Continue;
+                return vec![response];
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": 1, "name": "chip8" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK, "expensive": false },
+            ]})),
+            "variables" => self.variables(args["variablesReference"].as_u64().unwrap_or(0)),
+            "readMemory" => self.read_memory(args),
+            "continue" => match self.debugger() {
+                Ok(_) => {
+                    self.mode = Mode::Continue;
+                    Ok(json!({ "allThreadsContinued": true }))
+                }
+                Err(e) => Err(e),
+            },
+            "next" | "stepIn" | "stepOut" => match self.debugger() {
+                Ok(debugger) => {
+                    let pc = debugger.machine().pc();
+                    let depth = debugger.machine().stack().len();
+                    self.mode = match request["command"].as_str() {
+                        Some("stepOut") => Mode::Out { depth },
+                        command => Mode::Step {
+                            line: self.symbols.line(pc),
+                            depth,
+                            over: command == Some("next"),
+                        },
+                    };
+                    Ok(Value::Null)
+                }
+                Err(e) => Err(e),
+            },
+            "pause" => {
+                let response = self.response(request, Value::Null);
+                let mut replies = vec![response];
+                replies.extend(self.stopped("pause", None));
+                return replies;
+            }
+            "evaluate" => match self.evaluate(args["expression"].as_str().unwrap_or("")) {
+                Ok((result, moved)) => {
+                    let body = json!({ "result": result, "variablesReference": 0 });
+                    let mut replies = vec![self.response(request, body)];
+                    if moved {
+                        replies.extend(self.stopped("step", None));
+                    }
+                    return replies;
+                }
+                Err(e) => Err(e),
+            },
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(Value::Null)
+            }
+            command => Err(format!("{:?} isn't supported", command)),
+        };
+        match result {
+            Ok(body) => vec![self.response(request, body)],
+            Err(e) => vec![self.error(request, e)],
+        }
+    }
+
+    /// Runs the game for a while, with the events if it stops.
+    pub fn resume(&mut self) -> Vec<Value> {
+        for _ in 0..CHUNK {
+            let debugger = match self.debugger.as_mut() {
+                Some(debugger) => debugger,
+                None => return self.stopped("exception", Some("no game is launched".to_string())),
+            };
+            match debugger.run(1) {
+                Stop::Limit => {}
+                Stop::Breakpoint(_) => return self.stopped("breakpoint", None),
+                Stop::Watchpoint(_, hit) => return self.stopped("data breakpoint", Some(hit)),
+                Stop::Key => {
+                    let text = "waiting for a key, press one with 'key K' in the debug console";
+                    return self.stopped("pause", Some(text.to_string()));
+                }
+                Stop::Fault(fault) => return self.stopped("exception", Some(fault.to_string())),
+            }
+            let pc = debugger.machine().pc();
+            let depth = debugger.machine().stack().len();
+            let done = match self.mode {
+                Mode::Step {
+                    line,
+                    depth: from,
+                    over,
+                } => match self.symbols.line(pc) {
+                    Some(now) => Some(now) != line && (!over || depth <= from),
+                    None => false,
+                },
+                Mode::Out { depth: from } => depth < from,
+                Mode::Continue | Mode::Stopped => false,
+            };
+            if done {
+                return self.stopped("step", None);
+            }
+        }
+        Vec::new()
+    }
+}
+
+/// Serves an editor until it disconnects or `input` ends.
+pub fn serve(input: impl BufRead + Send + 'static, mut output: impl Write) -> io::Result<()> {
+    let (sender, requests) = mpsc::channel();
+    // requests are read aside, so they reach a running game.
+    thread::spawn(move || {
+        let mut input = input;
+        loop {
+            match read_message(&mut input) {
+                Ok(Some(request)) => {
+                    if sender.send(request).is_err() {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => continue,
+                Ok(None) | Err(_) => break,
+            }
+        }
+    });
+    let mut adapter = Adapter::new();
+    while !adapter.done() {
+        let replies = if adapter.running() {
+            match requests.try_recv() {
+                Ok(request) => adapter.handle(&request),
+                Err(TryRecvError::Empty) => adapter.resume(),
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match requests.recv() {
+                Ok(request) => adapter.handle(&request),
+                Err(_) => break,
+            }
+        };
+        for reply in replies.iter() {
+            write_message(&mut output, reply)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_assembler::assembler::{assemble_with_symbols, Options};
+    use std::io::Cursor;
+
+    const SOURCE: &str = "\
+main:
+  LD V0, 5
+  CALL add
+  JP main
+add:
+  ADD V0, 1
+  RET
+.entrypoint main
+";
+
+    fn request(adapter: &mut Adapter, command: &str, arguments: Value) -> Vec<Value> {
+        adapter.handle(
+            &json!({ "seq": 1, "type": "request", "command": command, "arguments": arguments }),
+        )
+    }
+
+    fn body(adapter: &mut Adapter, command: &str, arguments: Value) -> Value {
+        let replies = request(adapter, command, arguments);
+        assert_eq!(replies[0]["success"], true, "{:?}", replies);
+        replies[0]["body"].clone()
+    }
+
+    fn until_stopped(adapter: &mut Adapter) -> Value {
+        loop {
+            if let Some(event) = adapter.resume().pop() {
+                return event["body"].clone();
+            }
+        }
+    }
+
+    #[test]
+    fn session() {
+        let directory = std::env::temp_dir().join(format!("chip8-dap-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let (rom, mut symbols) = assemble_with_symbols(SOURCE, &Options::default()).unwrap();
+        symbols.source = Some("game.asm".to_string());
+        fs::write(directory.join("game.ch8"), rom).unwrap();
+        fs::write(directory.join("game.sym"), symbols.to_string()).unwrap();
+        let source = directory.join("game.asm").display().to_string();
+
+        let mut adapter = Adapter::new();
+        let replies = request(&mut adapter, "initialize", json!({}));
+        assert_eq!(replies[0]["body"]["supportsReadMemoryRequest"], true);
+        assert_eq!(replies[1]["event"], "initialized");
+        let replies = request(&mut adapter, "launch", json!({ "program": "missing.ch8" }));
+        assert_eq!(replies[0]["success"], false);
+        body(
+            &mut adapter,
+            "launch",
+            json!({
+                "program": directory.join("game.ch8"),
+                "symbols": directory.join("game.sym"),
+                "stopOnEntry": true,
+            }),
+        );
+        let breakpoints = body(
+            &mut adapter,
+            "setBreakpoints",
+            json!({ "source": { "path": source }, "breakpoints": [{ "line": 5 }, { "line": 9 }] }),
+        );
+        assert_eq!(
+            breakpoints["breakpoints"],
+            json!([
+                { "verified": true, "line": 6 },
+                { "verified": false, "line": 9, "message": "no code on or after this line" },
+            ])
+        );
+        let replies = request(&mut adapter, "configurationDone", json!({}));
+        assert_eq!(replies[1]["body"]["reason"], "entry");
+        assert!(!adapter.running());
+
+        body(&mut adapter, "next", json!({ "threadId": 1 }));
+        assert_eq!(until_stopped(&mut adapter)["reason"], "step");
+        let frames = body(&mut adapter, "stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(frames["stackFrames"][0]["line"], 2);
+        assert_eq!(frames["stackFrames"][0]["name"], "main");
+        assert_eq!(frames["stackFrames"][0]["source"]["path"], source);
+
+        body(&mut adapter, "continue", json!({ "threadId": 1 }));
+        assert_eq!(until_stopped(&mut adapter)["reason"], "breakpoint");
+        let frames = body(&mut adapter, "stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(frames["totalFrames"], 2);
+        assert_eq!(frames["stackFrames"][0]["name"], "add");
+        assert_eq!(frames["stackFrames"][0]["line"], 6);
+        assert_eq!(frames["stackFrames"][1]["name"], "main+2");
+        assert_eq!(frames["stackFrames"][1]["line"], 3);
+
+        let registers = body(
+            &mut adapter,
+            "variables",
+            json!({ "variablesReference": REGISTERS }),
+        );
+        assert_eq!(registers["variables"][0]["value"], "0x05");
+        assert_eq!(registers["variables"][16]["name"], "I");
+        assert_eq!(registers["variables"][18]["name"], "ST");
+        let stack = body(
+            &mut adapter,
+            "variables",
+            json!({ "variablesReference": STACK }),
+        );
+        assert_eq!(stack["variables"][0]["value"], "0x206 main+4");
+
+        body(&mut adapter, "stepOut", json!({ "threadId": 1 }));
+        assert_eq!(until_stopped(&mut adapter)["reason"], "step");
+        let frames = body(&mut adapter, "stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(frames["stackFrames"][0]["line"], 4);
+
+        let memory = body(
+            &mut adapter,
+            "readMemory",
+            json!({ "memoryReference": "0x202", "count": 2 }),
+        );
+        assert_eq!(memory["data"], "YAU=");
+        assert_eq!(memory["unreadableBytes"], 0);
+        let value = body(&mut adapter, "evaluate", json!({ "expression": "V0" }));
+        assert_eq!(value["result"], "0x6");
+        let value = body(
+            &mut adapter,
+            "evaluate",
+            json!({ "expression": "x 0x202 2" }),
+        );
+        assert_eq!(value["result"], "0x202: 60 05");
+        let replies = request(&mut adapter, "evaluate", json!({ "expression": "jump" }));
+        assert_eq!(replies[0]["success"], false);
+
+        body(&mut adapter, "disconnect", json!({}));
+        assert!(adapter.done());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn breakpoint_on_entry() {
+        let directory =
+            std::env::temp_dir().join(format!("chip8-dap-entry-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let source = "main:\n  LD V0, 5\n  JP main\n.noentrypoint\n";
+        let (rom, symbols) = assemble_with_symbols(source, &Options::default()).unwrap();
+        fs::write(directory.join("game.ch8"), rom).unwrap();
+        fs::write(directory.join("game.sym"), symbols.to_string()).unwrap();
+
+        let mut adapter = Adapter::new();
+        request(&mut adapter, "initialize", json!({}));
+        body(
+            &mut adapter,
+            "launch",
+            json!({
+                "program": directory.join("game.ch8"),
+                "symbols": directory.join("game.sym"),
+                "source": "game.asm",
+            }),
+        );
+        body(
+            &mut adapter,
+            "setBreakpoints",
+            json!({ "source": { "path": "game.asm" }, "breakpoints": [{ "line": 2 }] }),
+        );
+        let replies = request(&mut adapter, "configurationDone", json!({}));
+        assert_eq!(replies[1]["body"]["reason"], "breakpoint");
+        assert!(!adapter.running());
+        let frames = body(&mut adapter, "stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(frames["stackFrames"][0]["line"], 2);
+
+        // going on runs the loop back to it.
+        body(&mut adapter, "continue", json!({ "threadId": 1 }));
+        assert_eq!(until_stopped(&mut adapter)["reason"], "breakpoint");
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn messages() {
+        assert_eq!(base64(b"chip8"), "Y2hpcDg=");
+        assert_eq!(base64(b"ch"), "Y2g=");
+        assert_eq!(base64(b"chi"), "Y2hp");
+
+        let mut input = Vec::new();
+        write_message(
+            &mut input,
+            &json!({ "seq": 1, "type": "request", "command": "initialize" }),
+        )
+        .unwrap();
+        write_message(
+            &mut input,
+            &json!({ "seq": 2, "type": "request", "command": "threads" }),
+        )
+        .unwrap();
+        write_message(
+            &mut input,
+            &json!({ "seq": 3, "type": "request", "command": "disconnect" }),
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output).unwrap();
+
+        let mut output = Cursor::new(output);
+        let mut replies = Vec::new();
+        while let Some(reply) = read_message(&mut output).unwrap() {
+            replies.push(reply);
+        }
+        let kinds: Vec<&str> = replies
+            .iter()
+            .map(|r| r["command"].as_str().or(r["event"].as_str()).unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            ["initialize", "initialized", "threads", "disconnect"]
+        );
+        assert_eq!(replies[2]["body"]["threads"][0]["name"], "chip8");
+        assert_eq!(replies[3]["request_seq"], 3);
+    }
+}
@@ -0,0 +1,731 @@
+use crate::condition::{Condition, Operand};
+use crate::{Access, Fault, CHIP8};
+use chip8_assembler::symbols::Symbols;
+use chip8_decompiler::{disassemble, Options, Program};
+use chip8_isa::Opcode;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, BufRead, Write};
+
+/// How many instructions `continue` runs before giving up
+/// on reaching a breakpoint.
+pub const LIMIT: usize = 1_000_000;
+
+/// What a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    /// Reads or writes of `len` bytes from `start`; both if `access` is `None`.
+    Memory {
+        start: u16,
+        len: u16,
+        access: Option<Access>,
+    },
+    /// A change of V0-VF or I.
+    Register(Operand),
+}
+
+impl Watch {
+    /// Whether `access` of `len` bytes from `start` touches what's watched.
+    fn touches(&self, access: Access, start: u16, len: u16) -> bool {
+        match *self {
+            Watch::Memory {
+                start: from,
+                len: watched,
+                access: kind,
+            } => {
+                kind.is_none_or(|kind| kind == access)
+                    && (start as usize) < from as usize + watched as usize
+                    && (from as usize) < start as usize + len as usize
+            }
+            Watch::Register(_) => false,
+        }
+    }
+}
+
+impl Display for Watch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Watch::Memory { start, len, access } => {
+                let kind = match access {
+                    Some(Access::Read) => "read",
+                    Some(Access::Write) => "write",
+                    None => "access",
+                };
+                write!(f, "{} 0x{:03x}", kind, start)?;
+                if len > 1 {
+                    write!(f, "-0x{:03x}", start as usize + len as usize - 1)?;
+                }
+                Ok(())
+            }
+            Watch::Register(register) => write!(f, "change of {}", register),
+        }
+    }
+}
+
+/// Why the machine stopped running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint(u16),
+    /// A watchpoint, by number, and what the last instruction did.
+    Watchpoint(usize, String),
+    /// Blocked on FX0A with no key pressed.
+    Key,
+    /// Ran the instruction limit without stopping.
+    Limit,
+    /// The instruction at the program counter can't run.
+    Fault(Fault),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Step(usize),
+    Continue,
+    Break(u16, Option<Condition>),
+    Delete(u16),
+    Watch(Watch),
+    Unwatch(usize),
+    Info,
+    Registers,
+    Memory(u16, u16),
+    Screen,
+    Key(u8),
+    Help,
+    Quit,
+}
+
+const HELP: &str = "\
+step [N]       (s) run N instructions, 1 by default
+continue       (c) run until a breakpoint
+break ADDR     (b) stop before executing ADDR, a number or a label
+break ADDR if COND  only when COND holds, as in V3 == 0x10 && I > 0x300
+delete ADDR    (d) remove the breakpoint at ADDR
+watch ADDR [N]     stop after a write to N bytes from ADDR, 1 by default
+rwatch ADDR [N]    stop after a read of them, by DXYN or FX65
+awatch ADDR [N]    stop after either
+watch VX|I         stop after a change of the register
+unwatch N          remove watchpoint N
+info           (i) list the breakpoints and watchpoints
+regs           (r) show the registers, I, the delay timer and the stack
+mem ADDR [N]   (x) dump N bytes of memory, 16 by default
+screen             draw the display
+key K              press the key K (1234 qwer asdf zxcv)
+help           (h) show this
+quit           (q) leave the debugger
+";
+
+/// Parses `0x` prefixed hexadecimal or decimal numbers.
+pub fn parse_number(input: &str) -> Result<u16, String> {
+    let parsed = match input.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => input.parse(),
+    };
+    parsed.map_err(|_| format!("expected a number, got {:?}", input))
+}
+
+impl Command {
+    /// Parses a line of the debugger; `None` if it's blank.
+    /// `resolve` turns what isn't a number into an address.
+    pub fn parse(
+        line: &str,
+        resolve: impl Fn(&str) -> Option<u16>,
+    ) -> Result<Option<Self>, String> {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let args: Vec<&str> = words.collect();
+        let address = |i: usize| match args.get(i) {
+            Some(arg) => parse_number(arg).or_else(|e| resolve(arg).ok_or(e)),
+            None => Err(format!("'{}' expects an address", name)),
+        };
+        let memory = |access: Option<Access>| -> Result<Self, String> {
+            let len = match args.get(1) {
+                Some(n) => parse_number(n)?,
+                None => 1,
+            };
+            Ok(Self::Watch(Watch::Memory {
+                start: address(0)?,
+                len,
+                access,
+            }))
+        };
+        let command = match name {
+            "s" | "step" => match args.first() {
+                Some(n) => Self::Step(parse_number(n)? as usize),
+                None => Self::Step(1),
+            },
+            "c" | "continue" => Self::Continue,
+            "b" | "break" => match args.get(1) {
+                Some(&"if") => {
+                    Self::Break(address(0)?, Some(Condition::parse(&args[2..].join(" "))?))
+                }
+                Some(other) => return Err(format!("expected 'if', got {:?}", other)),
+                None => Self::Break(address(0)?, None),
+            },
+            "d" | "delete" => Self::Delete(address(0)?),
+            "watch" => match args.first().map(|arg| Operand::parse(arg)) {
+                Some(Ok(register @ Operand::V(_))) | Some(Ok(register @ Operand::I)) => {
+                    Self::Watch(Watch::Register(register))
+                }
+                _ => memory(Some(Access::Write))?,
+            },
+            "rwatch" => memory(Some(Access::Read))?,
+            "awatch" => memory(None)?,
+            "unwatch" => match args.first() {
+                Some(n) => Self::Unwatch(parse_number(n)? as usize),
+                None => return Err("'unwatch' expects the number of a watchpoint".to_string()),
+            },
+            "i" | "info" => Self::Info,
+            "r" | "regs" => Self::Registers,
+            "x" | "mem" => match args.get(1) {
+                Some(n) => Self::Memory(address(0)?, parse_number(n)?),
+                None => Self::Memory(address(0)?, 16),
+            },
+            "screen" => Self::Screen,
+            "key" => match args.first().map(|k| k.as_bytes()) {
+                Some(&[k]) if CHIP8::is_chip8_key(k) => Self::Key(k),
+                _ => return Err("'key' expects one of 1234 qwer asdf zxcv".to_string()),
+            },
+            "h" | "help" => Self::Help,
+            "q" | "quit" => Self::Quit,
+            _ => return Err(format!("unknown command {:?}, try 'help'", name)),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// Why `stop` happened, to print.
+fn describe(stop: Stop) -> String {
+    match stop {
+        Stop::Breakpoint(address) => format!("breakpoint at 0x{:03x}", address),
+        Stop::Watchpoint(number, hit) => format!("watchpoint {}: {}", number, hit),
+        Stop::Key => "waiting for a key, press one with 'key K'".to_string(),
+        Stop::Limit => format!("stopped after {} instructions", LIMIT),
+        Stop::Fault(fault) => fault.to_string(),
+    }
+}
+
+/// A machine that can be stepped and stopped at breakpoints and watchpoints.
+pub struct Debugger {
+    machine: CHIP8,
+    /// the ROM disassembled, for its labels.
+    program: Program,
+    /// by address, with the condition they stop on, if any.
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    /// by number, from 1.
+    watches: BTreeMap<usize, Watch>,
+    next_watch: usize,
+    /// from the assembler, to tell the source line next to addresses.
+    symbols: Symbols,
+}
+
+impl Debugger {
+    pub fn new(game: &[u8]) -> Self {
+        Self {
+            machine: CHIP8::with_game(game),
+            program: disassemble(game, Options::default()),
+            breakpoints: BTreeMap::new(),
+            watches: BTreeMap::new(),
+            next_watch: 1,
+            symbols: Symbols::default(),
+        }
+    }
+
+    /// Shows where addresses are in the source these symbols came with.
+    pub fn symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Parses a line of the debugger, with the labels of the program.
+    pub fn parse(&self, line: &str) -> Result<Option<Command>, String> {
+        Command::parse(line, |label| self.program.lookup(label))
+    }
+
+    pub fn machine(&self) -> &CHIP8 {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut CHIP8 {
+        &mut self.machine
+    }
+
+    pub fn breakpoints(&self) -> &BTreeMap<u16, Option<Condition>> {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address, None).is_none()
+    }
+
+    /// A breakpoint that only stops when `condition` holds,
+    /// replacing any other at `address`.
+    pub fn add_breakpoint_if(&mut self, address: u16, condition: Condition) -> bool {
+        self.breakpoints.insert(address, Some(condition)).is_none()
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    /// Whether a breakpoint stops the machine where it is now.
+    pub fn at_breakpoint(&self) -> bool {
+        match self.breakpoints.get(&self.machine.pc()) {
+            Some(None) => true,
+            Some(Some(condition)) => condition.holds(&self.machine),
+            None => false,
+        }
+    }
+
+    pub fn watches(&self) -> &BTreeMap<usize, Watch> {
+        &self.watches
+    }
+
+    /// Adds a watchpoint, returning its number.
+    pub fn add_watch(&mut self, watch: Watch) -> usize {
+        let number = self.next_watch;
+        self.watches.insert(number, watch);
+        self.next_watch += 1;
+        number
+    }
+
+    pub fn remove_watch(&mut self, number: usize) -> bool {
+        self.watches.remove(&number).is_some()
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.machine.key(key);
+    }
+
+    /// Runs a single instruction, or waits a cycle for a key.
+    pub fn step(&mut self) -> Result<(), Fault> {
+        self.machine.cycle()
+    }
+
+    /// The memory the instruction at the program counter is about to
+    /// touch, as `(access, start, len)`.
+    fn next_access(&self) -> Option<(Access, u16, u16)> {
+        if self.machine.waiting_key() {
+            return None;
+        }
+        let at = self.machine.pc() as usize;
+        let word = self.machine.memory().get(at..at + 2)?;
+        let op = Opcode::decode(u16::from_be_bytes([word[0], word[1]]))?;
+        let (access, len) = CHIP8::access(&op)?;
+        Some((access, self.machine.i(), len))
+    }
+
+    fn bytes(&self, start: u16, len: u16) -> String {
+        let memory = self.machine.memory();
+        let start = (start as usize).min(memory.len());
+        let end = (start + len as usize).min(memory.len());
+        let bytes: Vec<String> = memory[start..end]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        bytes.join(" ")
+    }
+
+    /// Runs up to `limit` instructions, stopping before a breakpoint
+    /// whose condition holds, or after an instruction a watchpoint sees.
+    pub fn run(&mut self, limit: usize) -> Stop {
+        for _ in 0..limit {
+            let pc = self.machine.pc();
+            let registers = *self.machine.registers();
+            let i = self.machine.i();
+            let access = self.next_access();
+            let before = match access {
+                Some((Access::Write, start, len)) => self.bytes(start, len),
+                _ => String::new(),
+            };
+            if let Err(fault) = self.step() {
+                return Stop::Fault(fault);
+            }
+            if self.machine.waiting_key() && self.machine.pc() == pc {
+                return Stop::Key;
+            }
+
+            for (number, watch) in self.watches.iter() {
+                let at = self.symbol(pc);
+                let hit = match (*watch, access) {
+                    (Watch::Register(register), _) => {
+                        let old = match register {
+                            Operand::V(x) => registers[x as usize] as u16,
+                            _ => i,
+                        };
+                        let new = register.value(&self.machine);
+                        if old == new {
+                            continue;
+                        }
+                        format!(
+                            "{} changed from {:#x} to {:#x} at {}",
+                            register, old, new, at
+                        )
+                    }
+                    (watch, Some((kind, start, len))) if watch.touches(kind, start, len) => {
+                        match kind {
+                            Access::Read => {
+                                format!("{} read 0x{:03x}: {}", at, start, self.bytes(start, len))
+                            }
+                            Access::Write => format!(
+                                "{} wrote 0x{:03x}: {} -> {}",
+                                at,
+                                start,
+                                before,
+                                self.bytes(start, len)
+                            ),
+                        }
+                    }
+                    _ => continue,
+                };
+                return Stop::Watchpoint(*number, hit);
+            }
+
+            if self.at_breakpoint() {
+                return Stop::Breakpoint(self.machine.pc());
+            }
+        }
+        Stop::Limit
+    }
+
+    /// The address with its label and, with symbols, its source line.
+    fn symbol(&self, address: u16) -> String {
+        match self.symbols.position(address) {
+            Some(position) => format!("{} at {}", self.program.symbol(address), position),
+            None => self.program.symbol(address),
+        }
+    }
+
+    /// The instruction at the program counter,
+    /// as in `0x202 <main+2>: 1208  JP label@208`.
+    pub fn location(&self) -> String {
+        let pc = self.machine.pc();
+        let memory = self.machine.memory();
+        let at = pc as usize;
+        if at + 1 >= memory.len() {
+            return format!("{}: out of memory", self.symbol(pc));
+        }
+        let word = u16::from_be_bytes([memory[at], memory[at + 1]]);
+        match Opcode::decode(word) {
+            Some(op) => format!(
+                "{}: {:04x}  {}",
+                self.symbol(pc),
+                word,
+                self.program.native(&op)
+            ),
+            None => format!("{}: {:04x}  ?", self.symbol(pc), word),
+        }
+    }
+
+    fn registers(&self) -> String {
+        let m = &self.machine;
+        let mut out = String::new();
+        for (i, v) in m.registers().iter().enumerate() {
+            let sep = if i % 8 == 7 { '\n' } else { ' ' };
+            out.push_str(&format!("v{:x} {:02x}{}", i, v, sep));
+        }
+        let stack: Vec<String> = m.stack().iter().map(|a| format!("0x{:03x}", a)).collect();
+        out.push_str(&format!(
+            "pc 0x{:03x} i 0x{:03x} dt {} stack [{}]\n",
+            m.pc(),
+            m.i(),
+            m.delay(),
+            stack.join(", ")
+        ));
+        out
+    }
+
+    fn memory(&self, address: u16, len: u16) -> String {
+        let memory = self.machine.memory();
+        let start = (address as usize).min(memory.len());
+        let end = (start + len as usize).min(memory.len());
+        let mut out = String::new();
+        for (row, bytes) in memory[start..end].chunks(8).enumerate() {
+            let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            out.push_str(&format!("0x{:03x}: {}\n", start + row * 8, bytes.join(" ")));
+        }
+        out
+    }
+
+    fn screen(&self) -> String {
+        let mut out = String::new();
+        for y in 0..32 {
+            let line = self.machine.line_at(y);
+            for x in 0..64 {
+                out.push(if line >> (63 - x) & 1 == 1 { '#' } else { '.' });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn info(&self) -> String {
+        let mut out = String::new();
+        for (address, condition) in self.breakpoints.iter() {
+            out.push_str(&format!("breakpoint at {}", self.symbol(*address)));
+            if let Some(condition) = condition {
+                out.push_str(&format!(" if {}", condition));
+            }
+            out.push('\n');
+        }
+        for (number, watch) in self.watches.iter() {
+            out.push_str(&format!("watchpoint {}: {}\n", number, watch));
+        }
+        if out.is_empty() {
+            out.push_str("no breakpoints or watchpoints\n");
+        }
+        out
+    }
+
+    /// Executes `command`, returning what to print.
+    pub fn execute(&mut self, command: Command) -> String {
+        match command {
+            Command::Step(n) => match self.run(n) {
+                Stop::Limit | Stop::Key => format!("{}\n", self.location()),
+                stop => format!("{}\n{}\n", describe(stop), self.location()),
+            },
+            Command::Continue => format!("{}\n{}\n", describe(self.run(LIMIT)), self.location()),
+            Command::Break(address, condition) => match condition {
+                Some(condition) => {
+                    let text = format!("breakpoint at 0x{:03x} if {}\n", address, condition);
+                    self.add_breakpoint_if(address, condition);
+                    text
+                }
+                None => {
+                    self.add_breakpoint(address);
+                    format!("breakpoint at 0x{:03x}\n", address)
+                }
+            },
+            Command::Delete(address) => {
+                if self.remove_breakpoint(address) {
+                    format!("deleted breakpoint at 0x{:03x}\n", address)
+                } else {
+                    format!("no breakpoint at 0x{:03x}\n", address)
+                }
+            }
+            Command::Watch(watch) => {
+                format!("watchpoint {}: {}\n", self.add_watch(watch), watch)
+            }
+            Command::Unwatch(number) => {
+                if self.remove_watch(number) {
+                    format!("deleted watchpoint {}\n", number)
+                } else {
+                    format!("no watchpoint {}\n", number)
+                }
+            }
+            Command::Info => self.info(),
+            Command::Registers => self.registers(),
+            Command::Memory(address, len) => self.memory(address, len),
+            Command::Screen => self.screen(),
+            Command::Key(key) => {
+                self.press(key);
+                String::new()
+            }
+            Command::Help => HELP.to_string(),
+            Command::Quit => String::new(),
+        }
+    }
+
+    /// Reads commands from `input` until `quit` or the end of it.
+    pub fn repl(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        writeln!(output, "{}", self.location())?;
+        write!(output, "(chip8) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            match self.parse(&line?) {
+                Ok(Some(Command::Quit)) => return Ok(()),
+                Ok(Some(command)) => write!(output, "{}", self.execute(command))?,
+                Ok(None) => {}
+                Err(e) => writeln!(output, "{}", e)?,
+            }
+            write!(output, "(chip8) ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: LD V0, 5
+    // 0x202: ADD V0, 1
+    // 0x204: CALL 0x20a
+    // 0x206: LD V1, key
+    // 0x208: JP 0x208
+    // 0x20a: RET
+    const GAME: &[u8] = &[
+        0x60, 0x05, 0x70, 0x01, 0x22, 0x0a, 0xf1, 0x0a, 0x12, 0x08, 0x00, 0xee,
+    ];
+
+    #[test]
+    fn commands() {
+        assert_eq!(Command::parse("  ", |_| None), Ok(None));
+        assert_eq!(Command::parse("s", |_| None), Ok(Some(Command::Step(1))));
+        assert_eq!(
+            Command::parse("step 10", |_| None),
+            Ok(Some(Command::Step(10)))
+        );
+        assert_eq!(
+            Command::parse("b 0x20a", |_| None),
+            Ok(Some(Command::Break(0x20a, None)))
+        );
+        assert_eq!(
+            Command::parse("b 0x20a if V3 == 0x10 && I > 0x300", |_| None),
+            Ok(Some(Command::Break(
+                0x20a,
+                Some(Condition::parse("V3 == 0x10 && I > 0x300").unwrap())
+            )))
+        );
+        assert_eq!(
+            Command::parse("watch 0x300 4", |_| None),
+            Ok(Some(Command::Watch(Watch::Memory {
+                start: 0x300,
+                len: 4,
+                access: Some(Access::Write)
+            })))
+        );
+        assert_eq!(
+            Command::parse("awatch 0x300", |_| None),
+            Ok(Some(Command::Watch(Watch::Memory {
+                start: 0x300,
+                len: 1,
+                access: None
+            })))
+        );
+        assert_eq!(
+            Command::parse("watch vA", |_| None),
+            Ok(Some(Command::Watch(Watch::Register(Operand::V(10)))))
+        );
+        assert!(Command::parse("b 0x20a when V3 == 1", |_| None).is_err());
+        assert!(Command::parse("b 0x20a if V3 =", |_| None).is_err());
+        assert!(Command::parse("watch", |_| None).is_err());
+        assert_eq!(
+            Command::parse("x 0x200 4", |_| None),
+            Ok(Some(Command::Memory(0x200, 4)))
+        );
+        assert_eq!(
+            Command::parse("key q", |_| None),
+            Ok(Some(Command::Key(b'q')))
+        );
+        assert!(Command::parse("key p", |_| None).is_err());
+        assert!(Command::parse("break", |_| None).is_err());
+        assert!(Command::parse("b 0xzz", |_| None).is_err());
+        assert!(Command::parse("jump", |_| None).is_err());
+
+        let debugger = Debugger::new(GAME);
+        assert_eq!(
+            debugger.parse("b function@20a"),
+            Ok(Some(Command::Break(0x20a, None)))
+        );
+        assert!(debugger.parse("b nowhere").is_err());
+    }
+
+    #[test]
+    fn stepping() {
+        let mut debugger = Debugger::new(GAME);
+        assert_eq!(debugger.location(), "0x200 <main>: 6005  LD V0, 0x05");
+        debugger.step().unwrap();
+        debugger.step().unwrap();
+        assert_eq!(
+            debugger.location(),
+            "0x204 <main+4>: 220a  CALL function@20a"
+        );
+        assert_eq!(debugger.machine().registers()[0], 6);
+
+        let symbols = Symbols::parse("source /games/draw.asm\ncode 204 12 5").unwrap();
+        let located = Debugger::new(GAME).symbols(symbols);
+        assert_eq!(located.symbol(0x204), "0x204 <main+4> at draw.asm:12");
+        assert_eq!(located.symbol(0x206), "0x206 <main+6>");
+
+        debugger.add_breakpoint(0x20a);
+        assert_eq!(debugger.run(100), Stop::Breakpoint(0x20a));
+        assert_eq!(debugger.machine().stack(), &[0x206]);
+
+        assert_eq!(debugger.run(100), Stop::Key);
+        debugger.press(b'w');
+        assert_eq!(debugger.run(100), Stop::Limit);
+        assert_eq!(debugger.machine().registers()[1], b'w');
+        assert_eq!(debugger.machine().pc(), 0x208);
+    }
+
+    // 0x200: LDI 0x300
+    // 0x202: LD V0, 1
+    // 0x204: LD V1, 2
+    // 0x206: DMP V1
+    // 0x208: ADD V3, 8
+    // 0x20a: JP 0x208
+    const WATCHED: &[u8] = &[
+        0xa3, 0x00, 0x60, 0x01, 0x61, 0x02, 0xf1, 0x55, 0x73, 0x08, 0x12, 0x08,
+    ];
+
+    #[test]
+    fn watching() {
+        let mut debugger = Debugger::new(WATCHED);
+        debugger.add_watch(Watch::Memory {
+            start: 0x2ff,
+            len: 1,
+            access: None,
+        });
+        let reads = debugger.add_watch(Watch::Memory {
+            start: 0x301,
+            len: 1,
+            access: Some(Access::Read),
+        });
+        let writes = debugger.add_watch(Watch::Memory {
+            start: 0x301,
+            len: 4,
+            access: Some(Access::Write),
+        });
+        assert_eq!(
+            debugger.run(100),
+            Stop::Watchpoint(
+                writes,
+                "0x206 <main+6> wrote 0x300: 00 00 -> 01 02".to_string()
+            )
+        );
+        assert_eq!(debugger.machine().pc(), 0x208);
+        assert!(debugger.remove_watch(writes));
+        assert!(!debugger.remove_watch(writes));
+        assert!(debugger.remove_watch(reads));
+
+        let v3 = debugger.add_watch(Watch::Register(Operand::V(3)));
+        assert_eq!(
+            debugger.run(100),
+            Stop::Watchpoint(
+                v3,
+                "V3 changed from 0x0 to 0x8 at 0x208 <label@208>".to_string()
+            )
+        );
+        debugger.remove_watch(v3);
+
+        debugger.add_breakpoint_if(0x20a, Condition::parse("V3 == 0x20 && I > 0x2ff").unwrap());
+        assert_eq!(debugger.run(100), Stop::Breakpoint(0x20a));
+        assert_eq!(debugger.machine().registers()[3], 0x20);
+        assert_eq!(
+            debugger.info(),
+            "breakpoint at 0x20a <label@208+2> if V3 == 0x20 && I > 0x2ff\n\
+             watchpoint 1: access 0x2ff\n"
+        );
+    }
+
+    #[test]
+    fn session() {
+        let mut debugger = Debugger::new(GAME);
+        let mut output = Vec::new();
+        let input = "b 0x208\nc\nr\nx 0x200 4\nquit\nstep\n";
+        debugger.repl(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("breakpoint at 0x208"), "{}", output);
+        assert!(output.contains("v0 06 v1 00"), "{}", output);
+        assert!(output.contains("stack []"), "{}", output);
+        assert!(output.contains("0x200: 60 05 70 01\n"), "{}", output);
+        assert_eq!(debugger.machine().pc(), 0x208);
+    }
+}
@@ -0,0 +1,486 @@
+//! A stub speaking enough of the GDB remote serial protocol for a
+//! debugger to attach to a game over TCP: registers, memory, stepping,
+//! continuing, breakpoints and watchpoints, and `monitor` for the
+//! commands of the debugger.
+//!
+//! The registers are V0-VF, I, PC and DT, numbered 0 to 18,
+//! big-endian as the rest of the machine.
+use crate::debugger::{Debugger, Stop, Watch};
+use crate::{Access, Fault};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// The byte a client sends to interrupt a running game.
+const INTERRUPT: u8 = 0x03;
+
+/// How many instructions run between checks for an interrupt.
+const CHUNK: usize = 10_000;
+
+const PC: usize = 17;
+
+const TARGET: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="dt" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+/// `data` as a packet, escaping what the protocol reserves.
+fn frame(data: &str) -> Vec<u8> {
+    let mut escaped = Vec::new();
+    for b in data.bytes() {
+        match b {
+            b'$' | b'#' | b'}' | b'*' => escaped.extend_from_slice(&[b'}', b ^ 0x20]),
+            b => escaped.push(b),
+        }
+    }
+    let mut packet = vec![b'$'];
+    packet.extend_from_slice(&escaped);
+    packet.extend_from_slice(format!("#{:02x}", checksum(&escaped)).as_bytes());
+    packet
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn number(text: &str) -> Option<u16> {
+    u16::from_str_radix(text, 16).ok()
+}
+
+/// `ADDR,LEN` as in `m` and `Z` packets.
+fn range(text: &str) -> Option<(u16, u16)> {
+    let (address, len) = text.split_once(',')?;
+    Some((number(address)?, number(len)?))
+}
+
+/// Reads the next packet, acknowledging it; `None` at the end of the stream.
+/// Acknowledgements and interrupts in between are skipped.
+pub fn read_packet(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+) -> io::Result<Option<String>> {
+    let mut byte = [0];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] != b'$' {
+            continue;
+        }
+        let mut data = Vec::new();
+        reader.read_until(b'#', &mut data)?;
+        if data.pop() != Some(b'#') {
+            return Ok(None);
+        }
+        let mut sum = [0; 2];
+        reader.read_exact(&mut sum)?;
+        let expected = std::str::from_utf8(&sum)
+            .ok()
+            .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+        if expected == Some(checksum(&data)) {
+            writer.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+        writer.write_all(b"-")?;
+        writer.flush()?;
+    }
+}
+
+/// Whether the client sent an interrupt, without waiting for one.
+fn interrupted(reader: &mut BufReader<TcpStream>) -> bool {
+    if reader.get_ref().set_nonblocking(true).is_err() {
+        return false;
+    }
+    let pending = matches!(reader.fill_buf(), Ok(buffer) if buffer.first() == Some(&INTERRUPT));
+    let _ = reader.get_ref().set_nonblocking(false);
+    if pending {
+        reader.consume(1);
+    }
+    pending
+}
+
+/// A game being debugged by a GDB client.
+pub struct Stub {
+    debugger: Debugger,
+    attached: bool,
+}
+
+impl Stub {
+    pub fn new(game: &[u8]) -> Self {
+        Self {
+            debugger: Debugger::new(game),
+            attached: true,
+        }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        let machine = self.debugger.machine();
+        let mut bytes = machine.registers().to_vec();
+        bytes.extend_from_slice(&machine.i().to_be_bytes());
+        bytes.extend_from_slice(&machine.pc().to_be_bytes());
+        bytes.push(machine.delay());
+        bytes
+    }
+
+    /// The bytes of register `n`, by the numbering of the stub.
+    fn register(&self, n: usize) -> Option<Vec<u8>> {
+        let bytes = self.registers();
+        match n {
+            0..=15 => Some(vec![bytes[n]]),
+            16 | PC => Some(bytes[16 + (n - 16) * 2..18 + (n - 16) * 2].to_vec()),
+            18 => Some(vec![bytes[20]]),
+            _ => None,
+        }
+    }
+
+    fn set_register(&mut self, n: usize, bytes: &[u8]) -> Option<()> {
+        let machine = self.debugger.machine_mut();
+        match (n, bytes) {
+            (0..=15, &[value]) => machine.set_register(n as u8, value),
+            (16, &[high, low]) => machine.set_i(u16::from_be_bytes([high, low])),
+            (PC, &[high, low]) => machine.set_pc(u16::from_be_bytes([high, low])),
+            (18, &[value]) => machine.set_delay(value),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn watch(kind: char, start: u16, len: u16) -> Option<Watch> {
+        let access = match kind {
+            '2' => Some(Access::Write),
+            '3' => Some(Access::Read),
+            '4' => None,
+            _ => return None,
+        };
+        Some(Watch::Memory { start, len, access })
+    }
+
+    /// The stop reply for `stop`.
+    fn stopped(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Watchpoint(number, _) => match self.debugger.watches().get(&number) {
+                Some(Watch::Memory { start, access, .. }) => {
+                    let kind = match access {
+                        Some(Access::Write) => "watch",
+                        Some(Access::Read) => "rwatch",
+                        None => "awatch",
+                    };
+                    format!("T05{}:{:x};", kind, start)
+                }
+                _ => "S05".to_string(),
+            },
+            Stop::Fault(Fault::Opcode(_)) => "S04".to_string(),
+            Stop::Fault(_) => "S0b".to_string(),
+            Stop::Breakpoint(_) | Stop::Key | Stop::Limit => "S05".to_string(),
+        }
+    }
+
+    /// Runs until something stops the game or `interrupted` says so.
+    fn resume(&mut self, interrupted: &mut dyn FnMut() -> bool) -> String {
+        loop {
+            match self.debugger.run(CHUNK) {
+                Stop::Limit if !interrupted() => continue,
+                Stop::Limit => return "S02".to_string(),
+                stop => return self.stopped(stop),
+            }
+        }
+    }
+
+    /// The answer to `packet`, or `None` for no answer at all.
+    /// `interrupted` is polled while the game runs.
+    pub fn answer(
+        &mut self,
+        packet: &str,
+        interrupted: &mut dyn FnMut() -> bool,
+    ) -> Option<String> {
+        match packet {
+            "D" => {
+                self.attached = false;
+                return Some("OK".to_string());
+            }
+            "k" => {
+                self.attached = false;
+                return None;
+            }
+            _ => {}
+        }
+        let reply = self.reply(packet, interrupted);
+        Some(reply.unwrap_or_else(|| "E01".to_string()))
+    }
+
+    /// The answer to `packet`, `None` for an error.
+    fn reply(&mut self, packet: &str, interrupted: &mut dyn FnMut() -> bool) -> Option<String> {
+        let (kind, args) = packet.split_at(packet.len().min(1));
+        let ok = Some("OK".to_string());
+        match kind {
+            "?" => Some("S05".to_string()),
+            "g" => Some(hex(&self.registers())),
+            "G" => {
+                let bytes = unhex(args)?;
+                if bytes.len() != 21 {
+                    return None;
+                }
+                for x in 0..16 {
+                    self.set_register(x, &bytes[x..=x])?;
+                }
+                self.set_register(16, &bytes[16..18])?;
+                self.set_register(PC, &bytes[18..20])?;
+                self.set_register(18, &bytes[20..])?;
+                ok
+            }
+            "p" => Some(hex(&self.register(usize::from_str_radix(args, 16).ok()?)?)),
+            "P" => {
+                let (n, value) = args.split_once('=')?;
+                self.set_register(usize::from_str_radix(n, 16).ok()?, &unhex(value)?)?;
+                ok
+            }
+            "m" => {
+                let (address, len) = range(args)?;
+                let memory = self.debugger.machine().memory();
+                let start = address as usize;
+                let end = (start + len as usize).min(memory.len());
+                Some(hex(memory.get(start..end)?))
+            }
+            "M" => {
+                let (range_, data) = args.split_once(':')?;
+                let (address, len) = range(range_)?;
+                let bytes = unhex(data)?;
+                if bytes.len() != len as usize {
+                    return None;
+                }
+                self.debugger.machine_mut().write(address, &bytes).ok()?;
+                ok
+            }
+            "s" | "c" => {
+                if !args.is_empty() {
+                    let pc = number(args)?;
+                    self.debugger.machine_mut().set_pc(pc);
+                }
+                if kind == "s" {
+                    let stop = self.debugger.run(1);
+                    Some(self.stopped(stop))
+                } else {
+                    Some(self.resume(interrupted))
+                }
+            }
+            "Z" | "z" => {
+                let (point, rest) = args.split_once(',')?;
+                let (address, len) = range(rest)?;
+                let insert = kind == "Z";
+                match point {
+                    "0" | "1" if insert => {
+                        self.debugger.add_breakpoint(address);
+                    }
+                    "0" | "1" => {
+                        self.debugger.remove_breakpoint(address);
+                    }
+                    _ => {
+                        let watch = Self::watch(point.chars().next()?, address, len)?;
+                        let existing = self
+                            .debugger
+                            .watches()
+                            .iter()
+                            .find(|(_, w)| **w == watch)
+                            .map(|(number, _)| *number);
+                        match existing {
+                            None if insert => {
+                                self.debugger.add_watch(watch);
+                            }
+                            Some(number) if !insert => {
+                                self.debugger.remove_watch(number);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                ok
+            }
+            "H" => ok,
+            "q" => self.query(args),
+            _ => Some(String::new()),
+        }
+    }
+
+    fn query(&mut self, query: &str) -> Option<String> {
+        if query.starts_with("Supported") {
+            return Some("PacketSize=1000;qXfer:features:read+".to_string());
+        }
+        if query == "Attached" {
+            return Some("1".to_string());
+        }
+        if let Some(command) = query.strip_prefix("Rcmd,") {
+            let line = String::from_utf8(unhex(command)?).ok()?;
+            let output = match self.debugger.parse(&line) {
+                Ok(Some(command)) => self.debugger.execute(command),
+                Ok(None) => String::new(),
+                Err(e) => format!("{}\n", e),
+            };
+            return Some(if output.is_empty() {
+                "OK".to_string()
+            } else {
+                hex(output.as_bytes())
+            });
+        }
+        if let Some(range_) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let (offset, len) = range_.split_once(',')?;
+            let offset = usize::from_str_radix(offset, 16).ok()?.min(TARGET.len());
+            let len = usize::from_str_radix(len, 16).ok()?;
+            let end = (offset + len).min(TARGET.len());
+            let more = if end < TARGET.len() { 'm' } else { 'l' };
+            return Some(format!("{}{}", more, &TARGET[offset..end]));
+        }
+        Some(String::new())
+    }
+
+    /// Answers the packets of `stream` until it detaches, kills or leaves.
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        while self.attached {
+            let packet = match read_packet(&mut reader, &mut writer)? {
+                Some(packet) => packet,
+                None => break,
+            };
+            if let Some(reply) = self.answer(&packet, &mut || interrupted(&mut reader)) {
+                writer.write_all(&frame(&reply))?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Waits for a GDB client on `127.0.0.1:port` and debugs `game` for it.
+pub fn listen(game: &[u8], port: u16) -> Result<(), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|e| format!("Couldn't listen on port {}: {}", port, e))?;
+    eprintln!("waiting for gdb on 127.0.0.1:{}", port);
+    let (stream, _) = listener
+        .accept()
+        .map_err(|e| format!("Couldn't accept a connection: {}", e))?;
+    Stub::new(game)
+        .serve(stream)
+        .map_err(|e| format!("Lost the connection: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::thread;
+
+    // 0x200: LD V0, 5
+    // 0x202: ADD V0, 1
+    // 0x204: LDI 0x300
+    // 0x206: DMP V0
+    // 0x208: JP 0x208
+    const GAME: &[u8] = &[0x60, 0x05, 0x70, 0x01, 0xa3, 0x00, 0xf0, 0x55, 0x12, 0x08];
+
+    #[test]
+    fn packets() {
+        assert_eq!(frame("OK"), b"$OK#9a");
+        assert_eq!(frame("a#b"), b"$a}\x03b#43");
+        let mut acks = Vec::new();
+        let input = b"+$bad#00$g#67";
+        let packet = read_packet(&mut &input[..], &mut acks).unwrap();
+        assert_eq!(packet.as_deref(), Some("g"));
+        assert_eq!(acks, b"-+");
+        assert_eq!(unhex("0aff"), Some(vec![0x0a, 0xff]));
+        assert_eq!(unhex("0"), None);
+    }
+
+    /// Sends `packet` and reads the answer, as a client would.
+    fn send(stream: &mut TcpStream, packet: &str) -> String {
+        stream.write_all(&frame(packet)).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut ack = [0];
+        reader.read_exact(&mut ack).unwrap();
+        assert_eq!(ack[0], b'+');
+        read_packet(&mut reader, stream).unwrap().unwrap()
+    }
+
+    #[test]
+    fn session() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stub = Stub::new(GAME);
+            stub.serve(stream).unwrap();
+            stub.debugger().machine().memory()[0x300]
+        });
+        let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        client.set_nodelay(true).unwrap();
+
+        assert!(send(&mut client, "qSupported:swbreak+").contains("qXfer:features:read+"));
+        assert!(send(&mut client, "qXfer:features:read:target.xml:0,20").starts_with("m<?xml"));
+        assert_eq!(send(&mut client, "?"), "S05");
+        assert_eq!(send(&mut client, "p11"), "0200");
+        assert_eq!(send(&mut client, "s"), "S05");
+        assert_eq!(send(&mut client, "p0"), "05");
+        assert_eq!(
+            send(&mut client, "g"),
+            "050000000000000000000000000000000200020200"
+        );
+        assert_eq!(send(&mut client, "m200,4"), "60057001");
+        assert_eq!(send(&mut client, "Z0,206,2"), "OK");
+        assert_eq!(send(&mut client, "c"), "S05");
+        assert_eq!(send(&mut client, "p11"), "0206");
+        assert_eq!(send(&mut client, "p0"), "06");
+        assert_eq!(send(&mut client, "z0,206,2"), "OK");
+        assert_eq!(send(&mut client, "P0=2a"), "OK");
+        assert_eq!(send(&mut client, "Z2,300,1"), "OK");
+        assert_eq!(send(&mut client, "c"), "T05watch:300;");
+        assert_eq!(send(&mut client, "m300,1"), "2a");
+        assert_eq!(send(&mut client, "M300,2:0102"), "OK");
+        assert_eq!(send(&mut client, "m300,2"), "0102");
+        assert_eq!(send(&mut client, "Mfff,2:0102"), "E01");
+        assert_eq!(send(&mut client, "p20"), "E01");
+        assert_eq!(send(&mut client, "vMustReplyEmpty"), "");
+        let monitor = send(&mut client, &format!("qRcmd,{}", hex(b"regs")));
+        let monitor = String::from_utf8(unhex(&monitor).unwrap()).unwrap();
+        assert!(monitor.starts_with("v0 2a v1 00"), "{}", monitor);
+        assert_eq!(send(&mut client, "D"), "OK");
+        assert_eq!(server.join().unwrap(), 0x01);
+    }
+}
@@ -1,6 +1,49 @@
+use chip8_isa::Opcode;
+use std::fmt::{self, Display, Formatter};
 use std::sync::Arc;
 use std::{sync::atomic::AtomicU8, thread, time::Duration};
 
+pub mod cli;
+pub mod condition;
+pub mod dap;
+pub mod debugger;
+pub mod gdb;
+pub mod profile;
+pub mod terminal;
+pub mod trace;
+
+/// What stops a program from running on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The program counter ran off the end of memory.
+    Pc(u16),
+    /// An opcode that isn't an instruction.
+    Opcode(u16),
+    StackUnderflow,
+    StackOverflow,
+    /// An access to memory past this address.
+    Memory(u16),
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Fault::Pc(pc) => write!(f, "the program counter left memory at {:#05x}", pc),
+            Fault::Opcode(op) => write!(f, "unknown opcode {:04x}", op),
+            Fault::StackUnderflow => write!(f, "return with an empty stack"),
+            Fault::StackOverflow => write!(f, "call with a full stack"),
+            Fault::Memory(address) => write!(f, "memory access past {:#05x}", address),
+        }
+    }
+}
+
+/// How an instruction touches the memory from I.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
 pub struct CHIP8 {
     memory: [u8; 0x1000],
     pc: u16,
@@ -10,9 +53,13 @@ pub struct CHIP8 {
     key: Option<u8>,
     registers: [u8; 16],
     i: u16,
-    rng: RNG,
+    rng: Rng,
     delay_timer: Arc<AtomicU8>, // 60hz
+    /// set by FX18, but as there's no sound it doesn't count down.
+    sound_timer: u8,
     key_wait_target: Option<usize>,
+    /// how many times the instruction at each address ran.
+    executions: Vec<u32>,
     _thread: Option<std::thread::JoinHandle<()>>,
     pub draw_flag: bool,
     pub clear_flag: bool,
@@ -59,45 +106,40 @@ impl CHIP8 {
     }
 
     #[inline(always)]
-    fn _1nnn(&mut self) {
+    fn _1nnn(&mut self, nnn: u16) {
         // jump nnn
-        self.pc = self.op & 0xfff;
+        self.pc = nnn;
     }
 
     #[inline(always)]
-    fn _2nnn(&mut self) {
+    fn _2nnn(&mut self, nnn: u16) {
         // call nnn
         self.stack[self.sp] = self.pc;
         self.sp += 1;
-        self.pc = self.op & 0xfff;
+        self.pc = nnn;
     }
 
     #[inline(always)]
-    fn _3xnn(&mut self) {
+    fn _3xnn(&mut self, x: u8, nn: u8) {
         // skip if vx == nn
-        let x = (self.op >> 8) & 0xf;
         let x = self.registers[x as usize];
-        let nn = (self.op & 0xff) as u8;
         if x == nn {
             self.pc = self.pc.wrapping_add(2);
         }
     }
 
     #[inline(always)]
-    fn _4xnn(&mut self) {
+    fn _4xnn(&mut self, x: u8, nn: u8) {
         // skip if vx != nn
-        let x = (self.op >> 8) & 0xf;
         let x = self.registers[x as usize];
-        let nn = (self.op & 0xff) as u8;
         if x != nn {
             self.pc = self.pc.wrapping_add(2);
         }
     }
 
     #[inline(always)]
-    fn _5xy0(&mut self) {
+    fn _5xy0(&mut self, x: u8, y: u8) {
         // skip if vx == vy
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         let (x, y) = (self.registers[x as usize], self.registers[y as usize]);
         if x == y {
             self.pc = self.pc.wrapping_add(2);
@@ -105,53 +147,44 @@ impl CHIP8 {
     }
 
     #[inline(always)]
-    fn _6xnn(&mut self) {
+    fn _6xnn(&mut self, x: u8, nn: u8) {
         // vx = nn.
-        let x = (self.op >> 8) & 0xf;
-        let nn = (self.op & 0xff) as u8;
         self.registers[x as usize] = nn;
     }
 
     #[inline(always)]
-    fn _7xnn(&mut self) {
+    fn _7xnn(&mut self, x: u8, nn: u8) {
         // vx += nn (no carry set).
-        let x = (self.op >> 8) & 0xf;
-        let nn = (self.op & 0xff) as u8;
         self.registers[x as usize] = self.registers[x as usize].wrapping_add(nn);
     }
 
     #[inline(always)]
-    fn _8xy0(&mut self) {
+    fn _8xy0(&mut self, x: u8, y: u8) {
         // vx = vy.
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         self.registers[x as usize] = self.registers[y as usize];
     }
 
     #[inline(always)]
-    fn _8xy1(&mut self) {
+    fn _8xy1(&mut self, x: u8, y: u8) {
         // vx |= vy.
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         self.registers[x as usize] |= self.registers[y as usize];
     }
 
     #[inline(always)]
-    fn _8xy2(&mut self) {
+    fn _8xy2(&mut self, x: u8, y: u8) {
         // vx &= vy.
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         self.registers[x as usize] &= self.registers[y as usize];
     }
 
     #[inline(always)]
-    fn _8xy3(&mut self) {
+    fn _8xy3(&mut self, x: u8, y: u8) {
         // vx ^= vy.
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         self.registers[x as usize] ^= self.registers[y as usize];
     }
 
     #[inline(always)]
-    fn _8xy4(&mut self) {
+    fn _8xy4(&mut self, x: u8, y: u8) {
         // vx += vy (sets VF if overflow occurs).
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         let (xv, overflowed) =
             self.registers[x as usize].overflowing_add(self.registers[y as usize]);
         self.registers[x as usize] = xv;
@@ -159,9 +192,8 @@ impl CHIP8 {
     }
 
     #[inline(always)]
-    fn _8xy5(&mut self) {
+    fn _8xy5(&mut self, x: u8, y: u8) {
         // vx -= vy (sets VF if no overflow occurs).
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         let y = self.registers[y as usize];
         let (xv, overflowed) = self.registers[x as usize].overflowing_add(!y + 1);
         self.registers[x as usize] = xv;
@@ -169,18 +201,16 @@ impl CHIP8 {
     }
 
     #[inline(always)]
-    fn _8xy6(&mut self) {
+    fn _8xy6(&mut self, x: u8, y: u8) {
         // shift right vx into vy and store the overflowed bit into VF.
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         let (yv, overflowed) = self.registers[x as usize].overflowing_shr(1);
         self.registers[y as usize] = yv;
         self.registers[0xf] = if overflowed { 1 } else { 0 };
     }
 
     #[inline(always)]
-    fn _8xy7(&mut self) {
+    fn _8xy7(&mut self, x: u8, y: u8) {
         // reversed add: x = y - x;
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         let xv = self.registers[x as usize];
         let (xv, overflowed) = self.registers[y as usize].overflowing_add(!xv + 1);
         self.registers[x as usize] = xv;
@@ -188,41 +218,37 @@ impl CHIP8 {
     }
 
     #[inline(always)]
-    fn _8xye(&mut self) {
+    fn _8xye(&mut self, x: u8, y: u8) {
         // shift left vx into vy and store the overflowed bit into VF.
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         let (yv, overflowed) = self.registers[x as usize].overflowing_shl(1);
         self.registers[y as usize] = yv;
         self.registers[0xf] = if overflowed { 1 } else { 0 };
     }
 
     #[inline(always)]
-    fn _9xy0(&mut self) {
+    fn _9xy0(&mut self, x: u8, y: u8) {
         // skip if vx != vy
-        let (x, y) = (self.op >> 8 & 0xf, self.op >> 4 & 0xf);
         if self.registers[x as usize] != self.registers[y as usize] {
             self.pc = self.pc.wrapping_add(2);
         }
     }
 
     #[inline(always)]
-    fn _annn(&mut self) {
+    fn _annn(&mut self, nnn: u16) {
         // set I to nnn.
-        self.i = self.op & 0xfff;
+        self.i = nnn;
     }
 
     #[inline(always)]
-    fn _bnnn(&mut self) {
+    fn _bnnn(&mut self, nnn: u16) {
         // jump to nnn + v0.
         let v0 = self.registers[0] as u16;
-        self.pc = (self.op & 0xfff).wrapping_add(v0);
+        self.pc = nnn.wrapping_add(v0);
     }
 
     #[inline(always)]
-    fn _cxnn(&mut self) {
+    fn _cxnn(&mut self, x: u8, nn: u8) {
         // random & nn -> vx
-        let x = self.op >> 8 & 0xf;
-        let nn = (self.op & 0xff) as u8;
         let next = self.rng.0 & nn;
         self.registers[x as usize] = next;
     }
@@ -244,11 +270,9 @@ impl CHIP8 {
     }
 
     #[inline(always)]
-    fn _dxyn(&mut self) {
+    fn _dxyn(&mut self, x: u8, y: u8, n: u8) {
         // draw at x, y, with n height.
-        let x = self.op >> 8 & 0xf;
-        let y = self.op >> 4 & 0xf;
-        let n = self.op & 0xf;
+        let n = n as u16;
 
         let x = self.registers[x as usize] as u32 % 64;
         let y = self.registers[y as usize] as isize % 32;
@@ -270,72 +294,66 @@ impl CHIP8 {
         self.registers[0xf] = flag;
     }
     #[inline(always)]
-    fn _ex9e(&mut self) {
+    fn _ex9e(&mut self, x: u8) {
         // if key == vx then skip
-        let x = self.op >> 8 & 0xf;
-        if matches!(
-            self.key.filter(|k| self.registers[x as usize] == *k),
-            Some(_)
-        ) {
+        if self
+            .key
+            .filter(|k| self.registers[x as usize] == *k)
+            .is_some()
+        {
             self.pc += 2;
         }
     }
     #[inline(always)]
-    fn _exa1(&mut self) {
-        // if key != vx then skip
-        let x = self.op >> 8 & 0xf;
-        if matches!(
-            self.key.filter(|k| self.registers[x as usize] != *k),
-            Some(_)
-        ) {
+    fn _exa1(&mut self, x: u8) {
+        // if key != vx then skip, no key pressed included.
+        if self.key != Some(self.registers[x as usize]) {
             self.pc += 2;
         }
     }
     #[inline(always)]
-    fn _fx07(&mut self) {
-        let x = self.op >> 8 & 0xf;
-        if self.registers[x as usize] == self.delay_timer.load(std::sync::atomic::Ordering::SeqCst)
-        {
-            self.pc += 2;
-        }
+    fn _fx07(&mut self, x: u8) {
+        // vx = delay timer.
+        self.registers[x as usize] = self.delay_timer.load(std::sync::atomic::Ordering::SeqCst);
     }
 
     #[inline(always)]
-    fn _fx0a(&mut self) {
+    fn _fx0a(&mut self, x: u8) {
         // blocks until a key is pressed.
-        self.key_wait_target = Some((self.op >> 8 & 0xf) as usize);
+        self.key_wait_target = Some(x as usize);
     }
 
     #[inline(always)]
-    fn _fx15(&mut self) {
+    fn _fx15(&mut self, x: u8) {
         // sets delay timer to vx.
-        let x = self.op >> 8 & 0xf;
         self.delay_timer.store(
             self.registers[x as usize],
             std::sync::atomic::Ordering::SeqCst,
         );
     }
 
-    // fx18 not implemented as not dealing with sounds :|
+    #[inline(always)]
+    fn _fx18(&mut self, x: u8) {
+        // sets the sound timer to vx, though nothing plays.
+        self.sound_timer = self.registers[x as usize];
+    }
 
     #[inline(always)]
-    fn _fx1e(&mut self) {
+    fn _fx1e(&mut self, x: u8) {
         // I += vx;
-        let x = self.op >> 8 & 0xf;
-        self.i = self.i.wrapping_add(x);
+        self.i = self.i.wrapping_add(self.registers[x as usize] as u16);
     }
 
     #[inline(always)]
-    fn _fx29(&mut self) {
+    fn _fx29(&mut self, x: u8) {
         // i = font[vx]
-        let x = self.registers[(self.op >> 8 & 0xf) as usize] as u16 & 0xf;
+        let x = self.registers[x as usize] as u16 & 0xf;
         self.i = x * 5;
     }
 
     #[inline(always)]
-    fn _fx33(&mut self) {
+    fn _fx33(&mut self, x: u8) {
         // bcd
-        let x = self.op >> 8 & 0xf;
         let mut v = self.registers[x as usize];
         for i in (0..3).rev() {
             self.memory[self.i as usize + i] = v % 10;
@@ -344,68 +362,102 @@ impl CHIP8 {
     }
 
     #[inline(always)]
-    fn _fx55(&mut self) {
+    fn _fx55(&mut self, x: u8) {
         // dump registers until (and including) vx.
-        let x = (self.op >> 8 & 0xf) as usize;
+        let x = x as usize;
         for i in 0..=x {
             self.memory[self.i as usize + i] = self.registers[i];
         }
     }
 
     #[inline(always)]
-    fn _fx65(&mut self) {
+    fn _fx65(&mut self, x: u8) {
         // same as above, but loading
-        let x = (self.op >> 8 & 0xf) as usize;
-        for i in 0..=x as usize {
+        let x = x as usize;
+        for i in 0..=x {
             self.registers[i] = self.memory[self.i as usize + i];
         }
     }
 
-    #[inline]
-    fn exec(&mut self) {
-        let (a, c, d) = (self.op >> 12, self.op >> 4 & 0xf, self.op & 0xf);
-        match (a, c, d) {
-            (0, 0xe, 0) => self._00e0(),
-            (0, 0xe, 0xe) => self._00ee(),
-            (1, _, _) => self._1nnn(),
-            (2, _, _) => self._2nnn(),
-            (3, _, _) => self._3xnn(),
-            (4, _, _) => self._4xnn(),
-            (5, _, 0) => self._5xy0(),
-            (6, _, _) => self._6xnn(),
-            (7, _, _) => self._7xnn(),
-            (8, _, 0) => self._8xy0(),
-            (8, _, 1) => self._8xy1(),
-            (8, _, 2) => self._8xy2(),
-            (8, _, 3) => self._8xy3(),
-            (8, _, 4) => self._8xy4(),
-            (8, _, 5) => self._8xy5(),
-            (8, _, 6) => self._8xy6(),
-            (8, _, 7) => self._8xy7(),
-            (8, _, 0xe) => self._8xye(),
-            (9, _, 0) => self._9xy0(),
-            (0xa, _, _) => self._annn(),
-            (0xb, _, _) => self._bnnn(),
-            (0xc, _, _) => self._cxnn(),
-            (0xd, _, _) => self._dxyn(),
-            (0xe, 9, 0xe) => self._ex9e(),
-            (0xe, 0xa, 1) => self._exa1(),
-            (0xf, 0, 7) => self._fx07(),
-            (0xf, 0, 0xa) => self._fx0a(),
-            (0xf, 1, 5) => self._fx15(),
-            (0xf, 1, 0xe) => self._fx1e(),
-            (0xf, 2, 9) => self._fx29(),
-            (0xf, 3, 3) => self._fx33(),
-            (0xf, 5, 5) => self._fx55(),
-            (0xf, 6, 5) => self._fx65(),
+    /// The bytes from I that `op` reads or writes, as `(access, length)`.
+    pub fn access(op: &Opcode) -> Option<(Access, u16)> {
+        match *op {
+            Opcode::Draw(_, _, n) => Some((Access::Read, n as u16)),
+            Opcode::LoadR(x) => Some((Access::Read, x as u16 + 1)),
+            Opcode::Bcd(_) => Some((Access::Write, 3)),
+            Opcode::Dump(x) => Some((Access::Write, x as u16 + 1)),
+            _ => None,
+        }
+    }
+
+    /// Whether `op` can run without going out of the stack or memory.
+    fn check(&self, op: Opcode) -> Result<(), Fault> {
+        match op {
+            Opcode::Return if self.sp == 0 => return Err(Fault::StackUnderflow),
+            Opcode::Call(_) if self.sp == self.stack.len() => return Err(Fault::StackOverflow),
             _ => {}
         }
+        match Self::access(&op) {
+            Some((_, len)) if len > 0 && self.i as usize + len as usize > self.memory.len() => {
+                Err(Fault::Memory(self.i))
+            }
+            _ => Ok(()),
+        }
     }
 
-    fn fetch(&mut self) {
+    #[inline]
+    fn exec(&mut self) -> Result<(), Fault> {
+        let op = Opcode::decode(self.op).ok_or(Fault::Opcode(self.op))?;
+        self.check(op)?;
+        match op {
+            Opcode::Clear => self._00e0(),
+            Opcode::Return => self._00ee(),
+            Opcode::Jump(nnn) => self._1nnn(nnn),
+            Opcode::Call(nnn) => self._2nnn(nnn),
+            Opcode::SkipEqual(x, nn) => self._3xnn(x, nn),
+            Opcode::SkipNotEqual(x, nn) => self._4xnn(x, nn),
+            Opcode::SkipEqualRegister(x, y) => self._5xy0(x, y),
+            Opcode::Load(x, nn) => self._6xnn(x, nn),
+            Opcode::Add(x, nn) => self._7xnn(x, nn),
+            Opcode::LoadRegister(x, y) => self._8xy0(x, y),
+            Opcode::Or(x, y) => self._8xy1(x, y),
+            Opcode::And(x, y) => self._8xy2(x, y),
+            Opcode::Xor(x, y) => self._8xy3(x, y),
+            Opcode::AddRegister(x, y) => self._8xy4(x, y),
+            Opcode::Sub(x, y) => self._8xy5(x, y),
+            Opcode::ShiftRight(x, y) => self._8xy6(x, y),
+            Opcode::SubInverse(x, y) => self._8xy7(x, y),
+            Opcode::ShiftLeft(x, y) => self._8xye(x, y),
+            Opcode::SkipNotEqualRegister(x, y) => self._9xy0(x, y),
+            Opcode::LoadI(nnn) => self._annn(nnn),
+            Opcode::JumpV0(nnn) => self._bnnn(nnn),
+            Opcode::Random(x, nn) => self._cxnn(x, nn),
+            Opcode::Draw(x, y, n) => self._dxyn(x, y, n),
+            Opcode::SkipKey(x) => self._ex9e(x),
+            Opcode::SkipNotKey(x) => self._exa1(x),
+            Opcode::LoadDelay(x) => self._fx07(x),
+            Opcode::LoadKey(x) => self._fx0a(x),
+            Opcode::SetDelay(x) => self._fx15(x),
+            Opcode::SetSound(x) => self._fx18(x),
+            Opcode::AddI(x) => self._fx1e(x),
+            Opcode::Font(x) => self._fx29(x),
+            Opcode::Bcd(x) => self._fx33(x),
+            Opcode::Dump(x) => self._fx55(x),
+            Opcode::LoadR(x) => self._fx65(x),
+            // machine code routines aren't emulated.
+            Opcode::Sys(_) => {}
+        }
+        Ok(())
+    }
+
+    fn fetch(&mut self) -> Result<(), Fault> {
+        if self.pc as usize + 1 >= self.memory.len() {
+            return Err(Fault::Pc(self.pc));
+        }
         self.op =
             (self.memory[self.pc as usize] as u16) << 8 | self.memory[self.pc as usize + 1] as u16;
         self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
 
     pub fn key(&mut self, k: u8) {
@@ -416,19 +468,34 @@ impl CHIP8 {
         Self::default()
     }
 
-    pub fn cycle(&mut self) {
+    /// Runs the next instruction, unless waiting for a key.
+    /// A faulting instruction leaves the machine as it was.
+    pub fn cycle(&mut self) -> Result<(), Fault> {
         if let Some(vx) = self.key_wait_target {
             if let Some(k) = self.key {
                 self.registers[vx] = k;
                 self.key_wait_target = None;
             }
+            Ok(())
         } else {
+            let pc = self.pc;
             self.rng.clock();
-            self.fetch();
-            self.exec();
+            self.fetch()?;
+            self.exec().inspect_err(|_| self.pc = pc)?;
+            let count = &mut self.executions[pc as usize];
+            *count = count.saturating_add(1);
+            Ok(())
         }
     }
 
+    /// A machine with the fonts and `game` loaded.
+    pub fn with_game(game: &[u8]) -> Self {
+        let mut machine = Self::new();
+        machine.load_fonts();
+        machine.load_game(game);
+        machine
+    }
+
     pub fn load_fonts(&mut self) {
         self.memory[..5 * 16].clone_from_slice(&[
             0xf0, 0x90, 0x90, 0x90, 0xf0, // 0
@@ -462,6 +529,75 @@ impl CHIP8 {
     pub fn current_op(&self) -> u16 {
         self.op
     }
+
+    /// Address of the next instruction to execute.
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    /// Return addresses of the calls in progress, the innermost last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn delay(&self) -> u8 {
+        self.delay_timer.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub fn sound(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// How many times the instruction at each address ran.
+    pub fn executions(&self) -> &[u32] {
+        &self.executions
+    }
+
+    /// Whether the machine is blocked on FX0A.
+    pub fn waiting_key(&self) -> bool {
+        self.key_wait_target.is_some()
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    pub fn set_register(&mut self, x: u8, value: u8) {
+        self.registers[x as usize & 0xf] = value;
+    }
+
+    pub fn set_delay(&mut self, value: u8) {
+        self.delay_timer
+            .store(value, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Writes `bytes` to memory from `address`, unless they run past its end.
+    pub fn write(&mut self, address: u16, bytes: &[u8]) -> Result<(), Fault> {
+        let start = address as usize;
+        match self.memory.get_mut(start..start + bytes.len()) {
+            Some(memory) => {
+                memory.copy_from_slice(bytes);
+                Ok(())
+            }
+            None => Err(Fault::Memory(address)),
+        }
+    }
 }
 
 impl Default for CHIP8 {
@@ -473,13 +609,15 @@ impl Default for CHIP8 {
             clear_flag: false,
             memory: [0; 0x1000],
             delay_timer: timer,
+            sound_timer: 0,
             i: 0x200,
             key: None,
             key_wait_target: None,
+            executions: vec![0; 0x1000],
             op: 0,
             pc: 0x200,
             registers: [0; 16],
-            rng: RNG(106), // just searched RNG on google, nothing more.
+            rng: Rng(106), // just searched Rng on google, nothing more.
             sp: 0,
             stack: [0; 24],
             _thread: Some(std::thread::spawn(move || {
@@ -502,9 +640,9 @@ impl Drop for CHIP8 {
     }
 }
 
-struct RNG(u8);
+struct Rng(u8);
 
-impl RNG {
+impl Rng {
     // xor shift.
     pub fn clock(&mut self) {
         self.0 ^= self.0.wrapping_shl(13);
@@ -512,3 +650,33 @@ impl RNG {
         self.0 ^= self.0.wrapping_shl(5);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering::SeqCst;
+
+    #[test]
+    fn load_delay() {
+        let mut machine = CHIP8::new();
+        machine.delay_timer.store(0x30, SeqCst);
+        machine._fx07(3);
+        // the timer may tick in between.
+        let v3 = machine.registers[3];
+        assert!(machine.delay_timer.load(SeqCst) <= v3 && v3 <= 0x30 && v3 > 0);
+    }
+
+    #[test]
+    fn skip_not_key() {
+        let mut machine = CHIP8::new();
+        machine.registers[0] = b'w';
+        machine._exa1(0);
+        assert_eq!(machine.pc, 0x202);
+        machine.key(b'w');
+        machine._exa1(0);
+        assert_eq!(machine.pc, 0x202);
+        machine.key(b'q');
+        machine._exa1(0);
+        assert_eq!(machine.pc, 0x204);
+    }
+}
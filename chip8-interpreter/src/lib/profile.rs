@@ -0,0 +1,216 @@
+//! Where a program spends its time and what it never runs,
+//! from the execution counts of the machine.
+use crate::CHIP8;
+use chip8_decompiler::coverage::Coverage;
+use chip8_decompiler::graph::Graph;
+use chip8_decompiler::{disassemble, Options, Program};
+use chip8_isa::Opcode;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// How many of the hottest addresses the report shows.
+const HOTTEST: usize = 10;
+
+/// The instruction in the ROM at `address`, with the labels of the program.
+fn text(program: &Program, address: u16) -> String {
+    let at = (address as usize).wrapping_sub(0x200);
+    let op = match program.rom().get(at..at + 2) {
+        Some(word) => Opcode::decode(u16::from_be_bytes([word[0], word[1]])),
+        None => None,
+    };
+    match op {
+        Some(op) => program.native(&op),
+        None => "?".to_string(),
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+/// The hottest addresses, the cycles spent in every function and
+/// the instructions that never ran, `counts` being by address.
+pub fn report(program: &Program, counts: &[u32]) -> String {
+    let count = |address: u16| counts.get(address as usize).copied().unwrap_or(0) as u64;
+    let total: u64 = counts.iter().map(|c| *c as u64).sum();
+    let mut out = String::new();
+    writeln!(out, "instructions run: {}", total).unwrap();
+
+    let mut hottest = (0..counts.len() as u16)
+        .filter(|a| count(*a) > 0)
+        .collect::<Vec<_>>();
+    hottest.sort_by_key(|a| (std::cmp::Reverse(count(*a)), *a));
+    writeln!(out, "\nhottest addresses:").unwrap();
+    for address in hottest.into_iter().take(HOTTEST) {
+        writeln!(
+            out,
+            "  {:<24} {:>10} {:>6.2}%  {}",
+            program.symbol(address),
+            count(address),
+            percent(count(address), total),
+            text(program, address)
+        )
+        .unwrap();
+    }
+
+    // functions are keyed by the `CALL`s to them, and main.
+    let graph = Graph::new(program);
+    let mut functions = graph
+        .functions
+        .values()
+        .map(|f| {
+            let calls: u64 = program
+                .instructions()
+                .filter(|(_, op)| *op == Opcode::Call(f.entry))
+                .map(|(address, _)| count(address))
+                .sum();
+            let cycles: u64 = f
+                .blocks
+                .values()
+                .flat_map(|b| (b.start..b.end).step_by(2))
+                .map(count)
+                .sum();
+            (cycles, calls, f.name.as_str())
+        })
+        .collect::<Vec<_>>();
+    functions.sort_by(|a, b| b.0.cmp(&a.0).then(a.2.cmp(b.2)));
+    writeln!(out, "\nfunctions:").unwrap();
+    writeln!(
+        out,
+        "  {:<24} {:>10} {:>10} {:>7}",
+        "name", "calls", "cycles", "%"
+    )
+    .unwrap();
+    for (cycles, calls, name) in functions {
+        writeln!(
+            out,
+            "  {:<24} {:>10} {:>10} {:>6.2}%",
+            name,
+            calls,
+            cycles,
+            percent(cycles, total)
+        )
+        .unwrap();
+    }
+
+    // runs of instructions next to each other, as `(from, to)`.
+    let mut never: Vec<(u16, u16)> = Vec::new();
+    let mut instructions = 0;
+    for (address, _) in program.instructions() {
+        instructions += 1;
+        if count(address) > 0 {
+            continue;
+        }
+        match never.last_mut() {
+            Some((_, to)) if *to + 2 == address => *to = address,
+            _ => never.push((address, address)),
+        }
+    }
+    let missed: usize = never
+        .iter()
+        .map(|(from, to)| (to - from) as usize / 2 + 1)
+        .sum();
+    writeln!(
+        out,
+        "\nnever executed: {} of {} instructions",
+        missed, instructions
+    )
+    .unwrap();
+    for (from, to) in never {
+        if from == to {
+            writeln!(out, "  {}", program.symbol(from)).unwrap();
+        } else {
+            writeln!(out, "  {} - {:#05x}", program.symbol(from), to).unwrap();
+        }
+    }
+    out
+}
+
+/// The profiling options of the interpreters.
+#[derive(Debug, StructOpt)]
+pub struct Args {
+    /// When the game ends, write where it spent its time to this file.
+    #[structopt(long = "profile", parse(from_os_str))]
+    pub profile: Option<PathBuf>,
+
+    /// When the game ends, write which instructions ran to this file,
+    /// for the decompiler's --coverage.
+    #[structopt(long = "coverage", parse(from_os_str))]
+    pub coverage: Option<PathBuf>,
+}
+
+impl Args {
+    /// Writes the files asked for, from what ran on `machine`.
+    pub fn write(&self, game: &[u8], machine: &CHIP8) -> Result<(), String> {
+        let write = |path: &PathBuf, contents: String| {
+            fs::write(path, contents)
+                .map_err(|e| format!("Couldn't write {}: {}", path.display(), e))
+        };
+        if let Some(path) = &self.profile {
+            let program = disassemble(game, Options::default());
+            write(path, report(&program, machine.executions()))?;
+        }
+        if let Some(path) = &self.coverage {
+            write(
+                path,
+                Coverage::from_counts(machine.executions()).to_string(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: CALL 0x20a
+    // 0x202: ADD V0, 1
+    // 0x204: SEQ V0, 3
+    // 0x206: JP 0x200
+    // 0x208: JP 0x208
+    // 0x20a: ADD V1, 1
+    // 0x20c: RET
+    const GAME: &[u8] = &[
+        0x22, 0x0a, 0x70, 0x01, 0x30, 0x03, 0x12, 0x00, 0x12, 0x08, 0x71, 0x01, 0x00, 0xee,
+    ];
+
+    #[test]
+    fn counts() {
+        let mut machine = CHIP8::with_game(GAME);
+        for _ in 0..20 {
+            machine.cycle().unwrap();
+        }
+        let counts = machine.executions();
+        assert_eq!(counts[0x200], 3);
+        assert_eq!(counts[0x20a], 3);
+        assert_eq!(counts[0x206], 2);
+
+        let profile = report(&disassemble(GAME, Options::default()), counts);
+        let lines = profile.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "instructions run: 20");
+        assert_eq!(
+            lines[3],
+            "  0x200 <main>                      3  15.00%  CALL function@20a"
+        );
+        assert!(profile.contains(
+            "\n  name                          calls     cycles       %\
+             \n  main                              0         14  70.00%\
+             \n  function@20a                      3          6  30.00%\n"
+        ));
+        assert!(profile.ends_with("never executed: 0 of 7 instructions\n"));
+
+        let mut machine = CHIP8::with_game(GAME);
+        machine.cycle().unwrap();
+        let profile = report(&disassemble(GAME, Options::default()), machine.executions());
+        assert!(
+            profile.ends_with("never executed: 6 of 7 instructions\n  0x202 <main+2> - 0x20c\n")
+        );
+    }
+}
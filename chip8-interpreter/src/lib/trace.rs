@@ -0,0 +1,411 @@
+//! Logging of the instructions a program runs, and of the last
+//! few of them when it crashes.
+use crate::{Fault, CHIP8};
+use chip8_assembler::symbols::Symbols;
+use chip8_decompiler::{disassemble, Options, Program};
+use chip8_isa::Opcode;
+use std::collections::VecDeque;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// Groups of instructions the trace can be narrowed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// jumps, calls and returns.
+    Flow,
+    Skip,
+    /// loads and arithmetic on registers.
+    Alu,
+    /// everything about I and what it points to.
+    Memory,
+    Display,
+    Timer,
+    Input,
+}
+
+impl Class {
+    pub fn of(op: &Opcode) -> Self {
+        match op {
+            Opcode::Sys(_)
+            | Opcode::Return
+            | Opcode::Jump(_)
+            | Opcode::JumpV0(_)
+            | Opcode::Call(_) => Class::Flow,
+            Opcode::SkipEqual(..)
+            | Opcode::SkipNotEqual(..)
+            | Opcode::SkipEqualRegister(..)
+            | Opcode::SkipNotEqualRegister(..) => Class::Skip,
+            Opcode::Load(..)
+            | Opcode::Add(..)
+            | Opcode::LoadRegister(..)
+            | Opcode::Or(..)
+            | Opcode::And(..)
+            | Opcode::Xor(..)
+            | Opcode::AddRegister(..)
+            | Opcode::Sub(..)
+            | Opcode::ShiftRight(..)
+            | Opcode::SubInverse(..)
+            | Opcode::ShiftLeft(..)
+            | Opcode::Random(..) => Class::Alu,
+            Opcode::LoadI(_)
+            | Opcode::AddI(_)
+            | Opcode::Font(_)
+            | Opcode::Bcd(_)
+            | Opcode::Dump(_)
+            | Opcode::LoadR(_) => Class::Memory,
+            Opcode::Clear | Opcode::Draw(..) => Class::Display,
+            Opcode::LoadDelay(_) | Opcode::SetDelay(_) | Opcode::SetSound(_) => Class::Timer,
+            Opcode::SkipKey(_) | Opcode::SkipNotKey(_) | Opcode::LoadKey(_) => Class::Input,
+        }
+    }
+}
+
+impl FromStr for Class {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flow" => Ok(Class::Flow),
+            "skip" => Ok(Class::Skip),
+            "alu" => Ok(Class::Alu),
+            "memory" => Ok(Class::Memory),
+            "display" => Ok(Class::Display),
+            "timer" => Ok(Class::Timer),
+            "input" => Ok(Class::Input),
+            _ => Err(format!(
+                "unknown class {:?}, expected flow, skip, alu, memory, display, timer or input",
+                s
+            )),
+        }
+    }
+}
+
+/// Which instructions go in the trace.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// addresses from `.0` to `.1`, both included.
+    pub range: Option<(u16, u16)>,
+    /// empty for all of them.
+    pub classes: Vec<Class>,
+}
+
+impl Filter {
+    pub fn matches(&self, address: u16, op: Option<&Opcode>) -> bool {
+        let in_range = match self.range {
+            Some((from, to)) => (from..=to).contains(&address),
+            None => true,
+        };
+        let in_class = match op {
+            Some(op) => self.classes.is_empty() || self.classes.contains(&Class::of(op)),
+            None => self.classes.is_empty(),
+        };
+        in_range && in_class
+    }
+}
+
+fn parse_range(input: &str) -> Result<(u16, u16), String> {
+    let number = |s: &str| match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    };
+    let mut bounds = input.splitn(2, '-');
+    match (
+        bounds.next().and_then(number),
+        bounds.next().and_then(number),
+    ) {
+        (Some(from), Some(to)) if from <= to => Ok((from, to)),
+        _ => Err(format!(
+            "expected a range as in 0x200-0x2ff, got {:?}",
+            input
+        )),
+    }
+}
+
+/// The tracing options of the interpreters.
+#[derive(Debug, StructOpt)]
+pub struct Args {
+    /// Log every instruction run to this file.
+    #[structopt(long = "trace", parse(from_os_str))]
+    pub trace: Option<PathBuf>,
+
+    /// Only log the instructions from START to END, as in 0x200-0x2ff.
+    #[structopt(long = "trace-range", parse(try_from_str = parse_range))]
+    pub range: Option<(u16, u16)>,
+
+    /// Only log these classes of instructions, comma separated:
+    /// flow, skip, alu, memory, display, timer or input.
+    #[structopt(long = "trace-only", use_delimiter = true)]
+    pub classes: Vec<Class>,
+
+    /// How many of the last instructions to show when the program crashes.
+    #[structopt(long = "last", default_value = "16")]
+    pub last: usize,
+}
+
+impl Args {
+    /// A tracer for `game`, creating the trace file if there's one.
+    pub fn tracer(&self, game: &[u8]) -> Result<Tracer, String> {
+        let out = match &self.trace {
+            Some(path) => {
+                let file = File::create(path)
+                    .map_err(|e| format!("Couldn't create {}: {}", path.display(), e))?;
+                Some(Box::new(BufWriter::new(file)) as Box<dyn Write>)
+            }
+            None => None,
+        };
+        let filter = Filter {
+            range: self.range,
+            classes: self.classes.clone(),
+        };
+        Ok(Tracer::new(game, filter, out, self.last))
+    }
+}
+
+/// An instruction that ran, and what it left behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub pc: u16,
+    /// the source file and line of the instruction, with symbols.
+    pub source: Option<String>,
+    pub opcode: u16,
+    /// the instruction with the labels of the program.
+    pub text: String,
+    /// the registers that changed, with their new values.
+    pub changes: Vec<(u8, u8)>,
+    pub i: u16,
+    pub delay: u8,
+    pub sound: u8,
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:#05x}  ", self.pc)?;
+        if let Some(source) = &self.source {
+            write!(f, "{:<20}  ", source)?;
+        }
+        write!(
+            f,
+            "{:04x}  {:<24}  I={:#05x} DT={:02x} ST={:02x}",
+            self.opcode, self.text, self.i, self.delay, self.sound
+        )?;
+        for (register, value) in self.changes.iter() {
+            write!(f, " V{:X}={:02x}", register, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a machine, logging what it runs.
+pub struct Tracer {
+    program: Program,
+    symbols: Symbols,
+    filter: Filter,
+    out: Option<Box<dyn Write>>,
+    /// the first error writing the trace, after which it stops.
+    error: Option<io::Error>,
+    last: VecDeque<Record>,
+    capacity: usize,
+}
+
+impl Tracer {
+    /// Logs the instructions matching `filter` to `out`, and
+    /// keeps the `last` ones run, whether they match or not.
+    pub fn new(game: &[u8], filter: Filter, out: Option<Box<dyn Write>>, last: usize) -> Self {
+        Self {
+            program: disassemble(game, Options::default()),
+            symbols: Symbols::default(),
+            filter,
+            out,
+            error: None,
+            last: VecDeque::with_capacity(last),
+            capacity: last,
+        }
+    }
+
+    /// Tells the source line of every instruction from these symbols.
+    pub fn symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// A tracer that logs nothing.
+    pub fn off(game: &[u8]) -> Self {
+        Self::new(game, Filter::default(), None, 0)
+    }
+
+    /// Runs a cycle of `machine`, logging the instruction it runs.
+    pub fn cycle(&mut self, machine: &mut CHIP8) -> Result<(), Fault> {
+        let pc = machine.pc();
+        let memory = machine.memory();
+        if machine.waiting_key() || pc as usize + 1 >= memory.len() {
+            return machine.cycle();
+        }
+        let opcode = u16::from_be_bytes([memory[pc as usize], memory[pc as usize + 1]]);
+        let before = *machine.registers();
+        let result = machine.cycle();
+
+        let op = Opcode::decode(opcode);
+        let record = Record {
+            pc,
+            source: self.symbols.position(pc),
+            opcode,
+            text: match op {
+                Some(op) => self.program.native(&op),
+                None => "?".to_string(),
+            },
+            changes: (0..16u8)
+                .filter(|r| before[*r as usize] != machine.registers()[*r as usize])
+                .map(|r| (r, machine.registers()[r as usize]))
+                .collect(),
+            i: machine.i(),
+            delay: machine.delay(),
+            sound: machine.sound(),
+        };
+        if self.filter.matches(pc, op.as_ref()) && self.error.is_none() {
+            if let Some(out) = self.out.as_mut() {
+                if let Err(e) = writeln!(out, "{}", record) {
+                    self.error = Some(e);
+                }
+            }
+        }
+        if self.capacity > 0 {
+            if self.last.len() == self.capacity {
+                self.last.pop_front();
+            }
+            self.last.push_back(record);
+        }
+        result
+    }
+
+    /// The last instructions run, the oldest first.
+    pub fn last(&self) -> impl Iterator<Item = &Record> {
+        self.last.iter()
+    }
+
+    /// What went wrong and the instructions leading up to it.
+    pub fn crash_report(&self, fault: Fault) -> String {
+        let mut report = vec![format!("crashed: {}", fault)];
+        if !self.last.is_empty() {
+            report.push(format!("last {} instructions:", self.last.len()));
+            report.extend(self.last().map(Record::to_string));
+        }
+        report.join("\n")
+    }
+
+    /// Flushes the trace, returning the first error writing it.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        match self.out.as_mut() {
+            Some(out) => out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // 0x200: LD V0, 5
+    // 0x202: CALL 0x208
+    // 0x204: LDI 0x20c
+    // 0x206: RET, with nothing to return to
+    // 0x208: ADD V0, 0xff
+    // 0x20a: RET
+    const GAME: &[u8] = &[
+        0x60, 0x05, 0x22, 0x08, 0xa2, 0x0c, 0x00, 0xee, 0x70, 0xff, 0x00, 0xee,
+    ];
+
+    fn run(tracer: &mut Tracer) -> Fault {
+        let mut machine = CHIP8::with_game(GAME);
+        loop {
+            if let Err(fault) = tracer.cycle(&mut machine) {
+                assert_eq!(machine.pc(), 0x206);
+                return fault;
+            }
+        }
+    }
+
+    #[test]
+    fn records() {
+        let out = Shared::default();
+        let mut tracer = Tracer::new(GAME, Filter::default(), Some(Box::new(out.clone())), 3);
+        assert_eq!(run(&mut tracer), Fault::StackUnderflow);
+        tracer.finish().unwrap();
+        let trace = String::from_utf8(out.0.borrow().clone()).unwrap();
+        assert_eq!(
+            trace.lines().collect::<Vec<_>>(),
+            vec![
+                "0x200  6005  LD V0, 0x05               I=0x200 DT=00 ST=00 V0=05",
+                "0x202  2208  CALL function@208         I=0x200 DT=00 ST=00",
+                "0x208  70ff  ADD V0, 0xff              I=0x200 DT=00 ST=00 V0=04",
+                "0x20a  00ee  RET                       I=0x200 DT=00 ST=00",
+                "0x204  a20c  LDI 0x20c                 I=0x20c DT=00 ST=00",
+                "0x206  00ee  RET                       I=0x20c DT=00 ST=00",
+            ]
+        );
+    }
+
+    #[test]
+    fn sources() {
+        let out = Shared::default();
+        let symbols =
+            Symbols::parse("source /games/calls.asm\ncode 200 3 1\ncode 202 4 1").unwrap();
+        let mut tracer =
+            Tracer::new(GAME, Filter::default(), Some(Box::new(out.clone())), 0).symbols(symbols);
+        run(&mut tracer);
+        let trace = String::from_utf8(out.0.borrow().clone()).unwrap();
+        let lines = trace.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "0x200  calls.asm:3           6005  LD V0, 0x05               I=0x200 DT=00 ST=00 V0=05"
+        );
+        assert!(lines[1].starts_with("0x202  calls.asm:4           2208"));
+        assert!(lines[2].starts_with("0x208  70ff"));
+    }
+
+    #[test]
+    fn filters() {
+        let out = Shared::default();
+        let filter = Filter {
+            range: Some((0x204, 0x20a)),
+            classes: vec![Class::Flow],
+        };
+        let mut tracer = Tracer::new(GAME, filter, Some(Box::new(out.clone())), 2);
+        run(&mut tracer);
+        let trace = String::from_utf8(out.0.borrow().clone()).unwrap();
+        let pcs = trace.lines().map(|l| &l[..5]).collect::<Vec<_>>();
+        assert_eq!(pcs, vec!["0x20a", "0x206"]);
+
+        let report = tracer.crash_report(Fault::StackUnderflow);
+        assert!(
+            report.starts_with("crashed: return with an empty stack\nlast 2 instructions:\n0x204")
+        );
+        assert_eq!(report.lines().count(), 4);
+
+        assert_eq!(parse_range("0x200-0x2ff"), Ok((0x200, 0x2ff)));
+        assert!(parse_range("0x2ff-0x200").is_err());
+        assert!(parse_range("0x200").is_err());
+        assert_eq!("display".parse(), Ok(Class::Display));
+        assert!("draw".parse::<Class>().is_err());
+    }
+}
@@ -0,0 +1,368 @@
+//! The CHIP-8 instruction set, shared by the assembler, the interpreter
+//! and the decompiler so that they all agree on what every opcode means.
+//!
+//! `X` and `Y` are registers, `N`, `NN` and `NNN` are 4, 8 and 12 bit values.
+//! Every opcode is written in the assembler's syntax:
+//!
+//! ```
+//! use chip8_isa::Opcode;
+//! assert_eq!(Opcode::decode(0x8125), Some(Opcode::Sub(1, 2)));
+//! assert_eq!(Opcode::Sub(1, 2).to_string(), "SUB V1, V2");
+//! assert_eq!(Opcode::Sub(1, 2).encode(), 0x8125);
+//! ```
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    /// `0NNN`, a machine code routine, which interpreters ignore.
+    Sys(u16),
+    /// `00E0`
+    Clear,
+    /// `00EE`
+    Return,
+    /// `1NNN`
+    Jump(u16),
+    /// `2NNN`
+    Call(u16),
+    /// `3XNN`, skips the next instruction if `VX == NN`.
+    SkipEqual(u8, u8),
+    /// `4XNN`, skips if `VX != NN`.
+    SkipNotEqual(u8, u8),
+    /// `5XY0`, skips if `VX == VY`.
+    SkipEqualRegister(u8, u8),
+    /// `6XNN`, `VX = NN`.
+    Load(u8, u8),
+    /// `7XNN`, `VX += NN` without a carry.
+    Add(u8, u8),
+    /// `8XY0`, `VX = VY`.
+    LoadRegister(u8, u8),
+    /// `8XY1`
+    Or(u8, u8),
+    /// `8XY2`
+    And(u8, u8),
+    /// `8XY3`
+    Xor(u8, u8),
+    /// `8XY4`, `VX += VY` with the carry in `VF`.
+    AddRegister(u8, u8),
+    /// `8XY5`, `VX -= VY` with `VF` set when there's no borrow.
+    Sub(u8, u8),
+    /// `8XY6`, `VY = VX >> 1` with the bit shifted out in `VF`.
+    ShiftRight(u8, u8),
+    /// `8XY7`, `VX = VY - VX` with `VF` set when there's no borrow.
+    SubInverse(u8, u8),
+    /// `8XYE`, `VY = VX << 1` with the bit shifted out in `VF`.
+    ShiftLeft(u8, u8),
+    /// `9XY0`, skips if `VX != VY`.
+    SkipNotEqualRegister(u8, u8),
+    /// `ANNN`, `I = NNN`.
+    LoadI(u16),
+    /// `BNNN`, jumps to `NNN + V0`.
+    JumpV0(u16),
+    /// `CXNN`, `VX = random & NN`.
+    Random(u8, u8),
+    /// `DXYN`, draws the `N` rows of the sprite at `I` at `VX`, `VY`.
+    Draw(u8, u8, u8),
+    /// `EX9E`, skips if the key in `VX` is pressed.
+    SkipKey(u8),
+    /// `EXA1`, skips if it isn't.
+    SkipNotKey(u8),
+    /// `FX07`, `VX = delay`.
+    LoadDelay(u8),
+    /// `FX0A`, waits for a key and puts it in `VX`.
+    LoadKey(u8),
+    /// `FX15`, `delay = VX`.
+    SetDelay(u8),
+    /// `FX18`, `sound = VX`.
+    SetSound(u8),
+    /// `FX1E`, `I += VX`.
+    AddI(u8),
+    /// `FX29`, points `I` to the font's digit for `VX`.
+    Font(u8),
+    /// `FX33`, the decimal digits of `VX` at `I`.
+    Bcd(u8),
+    /// `FX55`, stores `V0` up to `VX` at `I`.
+    Dump(u8),
+    /// `FX65`, loads `V0` up to `VX` from `I`.
+    LoadR(u8),
+}
+
+impl Opcode {
+    /// The instruction for `opcode`, `None` if it isn't one.
+    /// Only the exact encoding decodes, so `encode` always gives `opcode` back.
+    pub fn decode(opcode: u16) -> Option<Self> {
+        let x = (opcode >> 8 & 0xf) as u8;
+        let y = (opcode >> 4 & 0xf) as u8;
+        let n = (opcode & 0xf) as u8;
+        let nn = (opcode & 0xff) as u8;
+        let nnn = opcode & 0xfff;
+        let op = match (opcode >> 12, n) {
+            (0, _) if opcode == 0x00e0 => Self::Clear,
+            (0, _) if opcode == 0x00ee => Self::Return,
+            (0, _) => Self::Sys(nnn),
+            (1, _) => Self::Jump(nnn),
+            (2, _) => Self::Call(nnn),
+            (3, _) => Self::SkipEqual(x, nn),
+            (4, _) => Self::SkipNotEqual(x, nn),
+            (5, 0) => Self::SkipEqualRegister(x, y),
+            (6, _) => Self::Load(x, nn),
+            (7, _) => Self::Add(x, nn),
+            (8, 0) => Self::LoadRegister(x, y),
+            (8, 1) => Self::Or(x, y),
+            (8, 2) => Self::And(x, y),
+            (8, 3) => Self::Xor(x, y),
+            (8, 4) => Self::AddRegister(x, y),
+            (8, 5) => Self::Sub(x, y),
+            (8, 6) => Self::ShiftRight(x, y),
+            (8, 7) => Self::SubInverse(x, y),
+            (8, 0xe) => Self::ShiftLeft(x, y),
+            (9, 0) => Self::SkipNotEqualRegister(x, y),
+            (0xa, _) => Self::LoadI(nnn),
+            (0xb, _) => Self::JumpV0(nnn),
+            (0xc, _) => Self::Random(x, nn),
+            (0xd, _) => Self::Draw(x, y, n),
+            (0xe, _) if nn == 0x9e => Self::SkipKey(x),
+            (0xe, _) if nn == 0xa1 => Self::SkipNotKey(x),
+            (0xf, _) => match nn {
+                0x07 => Self::LoadDelay(x),
+                0x0a => Self::LoadKey(x),
+                0x15 => Self::SetDelay(x),
+                0x18 => Self::SetSound(x),
+                0x1e => Self::AddI(x),
+                0x29 => Self::Font(x),
+                0x33 => Self::Bcd(x),
+                0x55 => Self::Dump(x),
+                0x65 => Self::LoadR(x),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// The opcode for the instruction, with the operands cut down
+    /// to the bits they have in it.
+    pub fn encode(&self) -> u16 {
+        let x = |x: u8| (x as u16 & 0xf) << 8;
+        let xy = |vx: u8, vy: u8| x(vx) | (vy as u16 & 0xf) << 4;
+        let xnn = |vx: u8, nn: u8| x(vx) | nn as u16;
+        match *self {
+            Self::Sys(nnn) => nnn & 0xfff,
+            Self::Clear => 0x00e0,
+            Self::Return => 0x00ee,
+            Self::Jump(nnn) => 0x1000 | nnn & 0xfff,
+            Self::Call(nnn) => 0x2000 | nnn & 0xfff,
+            Self::SkipEqual(vx, nn) => 0x3000 | xnn(vx, nn),
+            Self::SkipNotEqual(vx, nn) => 0x4000 | xnn(vx, nn),
+            Self::SkipEqualRegister(vx, vy) => 0x5000 | xy(vx, vy),
+            Self::Load(vx, nn) => 0x6000 | xnn(vx, nn),
+            Self::Add(vx, nn) => 0x7000 | xnn(vx, nn),
+            Self::LoadRegister(vx, vy) => 0x8000 | xy(vx, vy),
+            Self::Or(vx, vy) => 0x8001 | xy(vx, vy),
+            Self::And(vx, vy) => 0x8002 | xy(vx, vy),
+            Self::Xor(vx, vy) => 0x8003 | xy(vx, vy),
+            Self::AddRegister(vx, vy) => 0x8004 | xy(vx, vy),
+            Self::Sub(vx, vy) => 0x8005 | xy(vx, vy),
+            Self::ShiftRight(vx, vy) => 0x8006 | xy(vx, vy),
+            Self::SubInverse(vx, vy) => 0x8007 | xy(vx, vy),
+            Self::ShiftLeft(vx, vy) => 0x800e | xy(vx, vy),
+            Self::SkipNotEqualRegister(vx, vy) => 0x9000 | xy(vx, vy),
+            Self::LoadI(nnn) => 0xa000 | nnn & 0xfff,
+            Self::JumpV0(nnn) => 0xb000 | nnn & 0xfff,
+            Self::Random(vx, nn) => 0xc000 | xnn(vx, nn),
+            Self::Draw(vx, vy, n) => 0xd000 | xy(vx, vy) | n as u16 & 0xf,
+            Self::SkipKey(vx) => 0xe09e | x(vx),
+            Self::SkipNotKey(vx) => 0xe0a1 | x(vx),
+            Self::LoadDelay(vx) => 0xf007 | x(vx),
+            Self::LoadKey(vx) => 0xf00a | x(vx),
+            Self::SetDelay(vx) => 0xf015 | x(vx),
+            Self::SetSound(vx) => 0xf018 | x(vx),
+            Self::AddI(vx) => 0xf01e | x(vx),
+            Self::Font(vx) => 0xf029 | x(vx),
+            Self::Bcd(vx) => 0xf033 | x(vx),
+            Self::Dump(vx) => 0xf055 | x(vx),
+            Self::LoadR(vx) => 0xf065 | x(vx),
+        }
+    }
+
+    /// The assembler's mnemonic for the instruction.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Self::Sys(_) => "SYS",
+            Self::Clear => "CLR",
+            Self::Return => "RET",
+            Self::Jump(_) => "JP",
+            Self::Call(_) => "CALL",
+            Self::SkipEqual(..) | Self::SkipEqualRegister(..) => "SEQ",
+            Self::SkipNotEqual(..) | Self::SkipNotEqualRegister(..) => "SNE",
+            Self::Load(..) | Self::LoadRegister(..) => "LD",
+            Self::Add(..) | Self::AddRegister(..) => "ADD",
+            Self::Or(..) => "OR",
+            Self::And(..) => "AND",
+            Self::Xor(..) => "XOR",
+            Self::Sub(..) => "SUB",
+            Self::ShiftRight(..) => "SHR",
+            Self::SubInverse(..) => "SBI",
+            Self::ShiftLeft(..) => "SHL",
+            Self::LoadI(_) => "LDI",
+            Self::JumpV0(_) => "JP0",
+            Self::Random(..) => "RND",
+            Self::Draw(..) => "DRW",
+            Self::SkipKey(_) => "SIK",
+            Self::SkipNotKey(_) => "SNK",
+            Self::LoadDelay(_) => "LDD",
+            Self::LoadKey(_) => "LDK",
+            Self::SetDelay(_) => "DLY",
+            Self::SetSound(_) => "SND",
+            Self::AddI(_) => "ADDI",
+            Self::Font(_) => "FNT",
+            Self::Bcd(_) => "BCD",
+            Self::Dump(_) => "DMP",
+            Self::LoadR(_) => "LDR",
+        }
+    }
+}
+
+impl Display for Opcode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let m = self.mnemonic();
+        match *self {
+            Self::Clear | Self::Return => write!(f, "{}", m),
+            Self::Sys(nnn)
+            | Self::Jump(nnn)
+            | Self::Call(nnn)
+            | Self::LoadI(nnn)
+            | Self::JumpV0(nnn) => write!(f, "{} {:#05x}", m, nnn),
+            Self::SkipEqual(vx, nn)
+            | Self::SkipNotEqual(vx, nn)
+            | Self::Load(vx, nn)
+            | Self::Add(vx, nn)
+            | Self::Random(vx, nn) => write!(f, "{} V{:X}, {:#04x}", m, vx, nn),
+            Self::SkipEqualRegister(vx, vy)
+            | Self::SkipNotEqualRegister(vx, vy)
+            | Self::LoadRegister(vx, vy)
+            | Self::Or(vx, vy)
+            | Self::And(vx, vy)
+            | Self::Xor(vx, vy)
+            | Self::AddRegister(vx, vy)
+            | Self::Sub(vx, vy)
+            | Self::ShiftRight(vx, vy)
+            | Self::SubInverse(vx, vy)
+            | Self::ShiftLeft(vx, vy) => write!(f, "{} V{:X}, V{:X}", m, vx, vy),
+            Self::Draw(vx, vy, n) => write!(f, "{} V{:X}, V{:X}, {}", m, vx, vy, n),
+            Self::SkipKey(vx)
+            | Self::SkipNotKey(vx)
+            | Self::LoadDelay(vx)
+            | Self::LoadKey(vx)
+            | Self::SetDelay(vx)
+            | Self::SetSound(vx)
+            | Self::AddI(vx)
+            | Self::Font(vx)
+            | Self::Bcd(vx)
+            | Self::Dump(vx)
+            | Self::LoadR(vx) => write!(f, "{} V{:X}", m, vx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn opcode() -> impl Strategy<Value = Opcode> {
+        use Opcode::*;
+        let r = || 0..16u8;
+        let nn = any::<u8>;
+        let nnn = || 0..0x1000u16;
+        prop_oneof![
+            // the ones that are really `CLR` and `RET`.
+            nnn()
+                .prop_filter("not CLR or RET", |a| *a != 0xe0 && *a != 0xee)
+                .prop_map(Sys),
+            Just(Clear),
+            Just(Return),
+            nnn().prop_map(Jump),
+            nnn().prop_map(Call),
+            nnn().prop_map(LoadI),
+            nnn().prop_map(JumpV0),
+            (r(), nn()).prop_map(|(x, nn)| SkipEqual(x, nn)),
+            (r(), nn()).prop_map(|(x, nn)| SkipNotEqual(x, nn)),
+            (r(), nn()).prop_map(|(x, nn)| Load(x, nn)),
+            (r(), nn()).prop_map(|(x, nn)| Add(x, nn)),
+            (r(), nn()).prop_map(|(x, nn)| Random(x, nn)),
+            (r(), r()).prop_map(|(x, y)| SkipEqualRegister(x, y)),
+            (r(), r()).prop_map(|(x, y)| SkipNotEqualRegister(x, y)),
+            (r(), r()).prop_map(|(x, y)| LoadRegister(x, y)),
+            (r(), r()).prop_map(|(x, y)| Or(x, y)),
+            (r(), r()).prop_map(|(x, y)| And(x, y)),
+            (r(), r()).prop_map(|(x, y)| Xor(x, y)),
+            (r(), r()).prop_map(|(x, y)| AddRegister(x, y)),
+            (r(), r()).prop_map(|(x, y)| Sub(x, y)),
+            (r(), r()).prop_map(|(x, y)| ShiftRight(x, y)),
+            (r(), r()).prop_map(|(x, y)| SubInverse(x, y)),
+            (r(), r()).prop_map(|(x, y)| ShiftLeft(x, y)),
+            (r(), r(), r()).prop_map(|(x, y, n)| Draw(x, y, n)),
+            r().prop_map(SkipKey),
+            r().prop_map(SkipNotKey),
+            r().prop_map(LoadDelay),
+            r().prop_map(LoadKey),
+            r().prop_map(SetDelay),
+            r().prop_map(SetSound),
+            r().prop_map(AddI),
+            r().prop_map(Font),
+            r().prop_map(Bcd),
+            r().prop_map(Dump),
+            r().prop_map(LoadR),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn decode_inverts_encode(op in opcode()) {
+            prop_assert_eq!(Opcode::decode(op.encode()), Some(op));
+        }
+
+        #[test]
+        fn encode_inverts_decode(opcode in any::<u16>()) {
+            if let Some(op) = Opcode::decode(opcode) {
+                prop_assert_eq!(op.encode(), opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn every_opcode() {
+        // there are few enough of them to check them all.
+        let decoded = (0..=u16::MAX)
+            .filter(|opcode| match Opcode::decode(*opcode) {
+                Some(op) => {
+                    assert_eq!(op.encode(), *opcode, "{}", op);
+                    true
+                }
+                None => false,
+            })
+            .count();
+        assert_eq!(decoded, 0x1000 * 11 + 0x100 * 11 + 0x10 * 11);
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!(Opcode::Random(1, 0xff).encode(), 0xc1ff);
+        assert_eq!(Opcode::SetSound(2).encode(), 0xf218);
+        assert_eq!(Opcode::Sub(1, 2).encode(), 0x8125);
+        assert_eq!(Opcode::SubInverse(1, 2).encode(), 0x8127);
+        assert_eq!(Opcode::decode(0x01e0), Some(Opcode::Sys(0x1e0)));
+        assert_eq!(Opcode::decode(0x5121), None);
+        assert_eq!(Opcode::decode(0xe19f), None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Opcode::Clear.to_string(), "CLR");
+        assert_eq!(Opcode::Jump(0x208).to_string(), "JP 0x208");
+        assert_eq!(Opcode::Load(0xa, 5).to_string(), "LD VA, 0x05");
+        assert_eq!(Opcode::ShiftLeft(1, 1).to_string(), "SHL V1, V1");
+        assert_eq!(Opcode::Draw(0, 1, 5).to_string(), "DRW V0, V1, 5");
+        assert_eq!(Opcode::LoadR(3).to_string(), "LDR V3");
+    }
+}
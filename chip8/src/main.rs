@@ -0,0 +1,250 @@
+use chip8_assembler::{assembler, cli, lsp, symbols::Symbols, syntax::Syntax};
+use chip8_decompiler::{cli as decompiler, disassemble, Options};
+use chip8_interpreter::{cli as interpreter, dap, debugger::Debugger};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+const ORIGIN: u16 = 0x200;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "chip8",
+    about = "assembles, runs, disassembles and debugs CHIP8 programs."
+)]
+enum Command {
+    /// Assembles a source file into a ROM.
+    Asm(cli::Args),
+    /// Runs a ROM, or a source file assembled on the fly, in the terminal.
+    Run {
+        #[structopt(flatten)]
+        input: Input,
+
+        #[structopt(flatten)]
+        run: interpreter::Args,
+    },
+    /// Disassembles a ROM into a listing, source, JSON or pseudo-C.
+    Dis(decompiler::Args),
+    /// Steps through a ROM with breakpoints, reading commands from stdin.
+    Debug {
+        #[structopt(flatten)]
+        input: Input,
+
+        /// Symbols written by the assembler, to show source lines of a ROM.
+        #[structopt(long = "symbols", parse(from_os_str))]
+        symbols: Option<PathBuf>,
+    },
+    /// Serves the Debug Adapter Protocol on stdin and stdout, for editors.
+    Dap,
+    /// Serves the Language Server Protocol on stdin and stdout, for editing sources.
+    Lsp,
+    /// Shows the size, entrypoint and reachable instructions of a ROM.
+    Info {
+        #[structopt(flatten)]
+        input: Input,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct Input {
+    /// ROM to load. Files ending in .asm, .s or .8o are assembled first.
+    #[structopt(parse(from_os_str))]
+    path: PathBuf,
+
+    /// Dialect of the source: native, cowgod or octo. Defaults to octo for .8o files.
+    #[structopt(long = "syntax")]
+    syntax: Option<Syntax>,
+}
+
+impl Input {
+    /// Reads the ROM, assembling it in memory if it's a source file.
+    fn load(&self) -> Result<Vec<u8>, String> {
+        self.load_with_symbols().map(|(rom, _)| rom)
+    }
+
+    /// Reads the ROM like `load`, with the symbols
+    /// of the source it was assembled from, if it was.
+    fn load_with_symbols(&self) -> Result<(Vec<u8>, Option<Symbols>), String> {
+        let read_error = |e: io::Error| format!("Couldn't read {}: {}", self.path.display(), e);
+        match self.syntax.or_else(|| Syntax::of_file(&self.path)) {
+            Some(syntax) => {
+                let source = fs::read_to_string(&self.path).map_err(read_error)?;
+                let options = assembler::Options {
+                    syntax,
+                    ..Default::default()
+                };
+                let (rom, mut symbols) = assembler::assemble_with_symbols(&source, &options)?;
+                symbols.source = Some(self.path.display().to_string());
+                Ok((rom, Some(symbols)))
+            }
+            None => fs::read(&self.path)
+                .map(|rom| (rom, None))
+                .map_err(read_error),
+        }
+    }
+}
+
+fn info(rom: &[u8]) -> String {
+    let program = disassemble(rom, Options::default());
+    let mut counts = BTreeMap::new();
+    for (_, op) in program.instructions() {
+        *counts.entry(op.mnemonic()).or_insert(0) += 1;
+    }
+    let instructions: usize = counts.values().sum();
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let mut out = String::new();
+    let end = ORIGIN as usize + rom.len();
+    writeln!(
+        out,
+        "size: {} bytes (0x{:03x}-0x{:03x})",
+        rom.len(),
+        ORIGIN,
+        end
+    )
+    .unwrap();
+    writeln!(out, "entrypoint: 0x{:03x}", program.entrypoint()).unwrap();
+    writeln!(
+        out,
+        "code: {} instructions, {} bytes of data",
+        instructions,
+        rom.len().saturating_sub(instructions * 2)
+    )
+    .unwrap();
+    writeln!(out, "labels: {}", program.labels().count()).unwrap();
+    for (mnemonic, count) in counts {
+        writeln!(out, "  {:<5}{}", mnemonic, count).unwrap();
+    }
+    out
+}
+
+fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Asm(args) => args.run(),
+        Command::Run { input, run } => match input.load_with_symbols()? {
+            (rom, Some(symbols)) => run.run_with_symbols(&rom, symbols),
+            (rom, None) => run.run(&rom),
+        },
+        Command::Dis(args) => args.run(),
+        Command::Debug { input, symbols } => {
+            let (rom, assembled) = input.load_with_symbols()?;
+            let symbols = match (assembled, symbols) {
+                (Some(symbols), _) => symbols,
+                (None, Some(path)) => Symbols::read(&path)?,
+                (None, None) => Symbols::default(),
+            };
+            let mut debugger = Debugger::new(&rom).symbols(symbols);
+            let stdin = io::stdin();
+            debugger
+                .repl(stdin.lock(), io::stdout())
+                .map_err(|e| e.to_string())
+        }
+        Command::Dap => {
+            dap::serve(io::BufReader::new(io::stdin()), io::stdout()).map_err(|e| e.to_string())
+        }
+        Command::Lsp => {
+            let stdin = io::stdin();
+            lsp::serve(stdin.lock(), io::stdout()).map_err(|e| e.to_string())
+        }
+        Command::Info { input } => {
+            print!("{}", info(&input.load()?));
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    if let Err(e) = run(Command::from_args()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn input(path: &str) -> Input {
+        Input {
+            path: Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(path),
+            syntax: None,
+        }
+    }
+
+    #[test]
+    fn loading() {
+        let assembled = input("examples/asm/draw_numbers.asm").load().unwrap();
+        let rom = input("examples/bin/draw_numbers.c8").load().unwrap();
+        assert_eq!(assembled, rom);
+        let (_, symbols) = input("examples/asm/draw_numbers.asm")
+            .load_with_symbols()
+            .unwrap();
+        assert_eq!(
+            symbols.unwrap().position(0x202),
+            Some("draw_numbers.asm:5".to_string())
+        );
+        assert_eq!(
+            input("examples/bin/draw_numbers.c8")
+                .load_with_symbols()
+                .unwrap()
+                .1,
+            None
+        );
+
+        assert_eq!(Syntax::of_file(Path::new("game.8o")), Some(Syntax::Octo));
+        assert_eq!(Syntax::of_file(Path::new("game.ch8")), None);
+        assert!(input("examples/missing.ch8").load().is_err());
+    }
+
+    #[test]
+    fn commands() {
+        let command = Command::from_iter(&["chip8", "run", "game.8o", "--color", "3"]);
+        assert!(matches!(command, Command::Run { run, .. } if run.color == 3));
+        let command = Command::from_iter(&["chip8", "asm", "game.asm", "-o", "game.ch8"]);
+        assert!(matches!(command, Command::Asm(args) if args.output.is_some()));
+        let command = Command::from_iter(&["chip8", "dis", "game.ch8", "--code", "0x230"]);
+        assert!(matches!(command, Command::Dis(args) if args.code == [0x230]));
+        let dis = |args: &[&str]| {
+            Command::from_iter_safe(["chip8", "dis", "game.ch8"].iter().chain(args))
+        };
+        assert!(matches!(dis(&["--reassemblable"]), Ok(Command::Dis(args)) if args.reassemblable));
+        assert!(dis(&["--reassemblable", "--format", "json"]).is_err());
+        let command = Command::from_iter(&["chip8", "asm", "game.8o"]);
+        assert!(matches!(command, Command::Asm(args) if args.syntax() == Syntax::Octo));
+        let command = Command::from_iter(&["chip8", "asm", "game.8o", "--syntax", "cowgod"]);
+        assert!(matches!(command, Command::Asm(args) if args.syntax() == Syntax::Cowgod));
+        let command = Command::from_iter(&["chip8", "asm", "game.asm", "--symbols", "game.sym"]);
+        assert!(matches!(command, Command::Asm(args) if args.symbols.is_some()));
+        let command = Command::from_iter(&["chip8", "debug", "game.ch8", "--symbols", "game.sym"]);
+        assert!(matches!(command, Command::Debug { symbols, .. } if symbols.is_some()));
+        let command = Command::from_iter(&["chip8", "run", "game.ch8", "--symbols", "game.sym"]);
+        assert!(matches!(command, Command::Run { run, .. } if run.symbols.is_some()));
+        assert!(matches!(
+            Command::from_iter(&["chip8", "dap"]),
+            Command::Dap
+        ));
+        assert!(matches!(
+            Command::from_iter(&["chip8", "lsp"]),
+            Command::Lsp
+        ));
+    }
+
+    #[test]
+    fn output() {
+        let rom = [0x12, 0x04, 0xff, 0xff, 0x00, 0xe0, 0x12, 0x04, 0x01];
+        assert_eq!(
+            info(&rom),
+            "size: 9 bytes (0x200-0x209)\n\
+             entrypoint: 0x204\n\
+             code: 3 instructions, 3 bytes of data\n\
+             labels: 1\n  \
+             JP   2\n  \
+             CLR  1\n"
+        );
+    }
+}
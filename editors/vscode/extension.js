@@ -0,0 +1,14 @@
+// Runs `chip8 dap` from the PATH for the `chip8` debug configurations.
+const vscode = require("vscode");
+
+function activate(context) {
+    context.subscriptions.push(
+        vscode.debug.registerDebugAdapterDescriptorFactory("chip8", {
+            createDebugAdapterDescriptor() {
+                return new vscode.DebugAdapterExecutable("chip8", ["dap"]);
+            },
+        })
+    );
+}
+
+module.exports = { activate };